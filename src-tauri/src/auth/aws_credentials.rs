@@ -0,0 +1,811 @@
+//! AWS 凭证提供链
+//!
+//! 支持静态密钥、环境变量、命名 profile、credential_process、
+//! Web Identity Token 以及 STS AssumeRole 等凭证来源，临时凭证缓存至过期前自动刷新。
+
+use crate::auth::bedrock::{sign_aws_request_for_service, BedrockCredentials};
+use crate::credentials::{AwsCredentialSource, ClaudeCredentials};
+use crate::error::PluginError;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info};
+
+/// 默认会话名称
+const DEFAULT_SESSION_NAME: &str = "claude-provider";
+
+/// STS API 版本
+const STS_API_VERSION: &str = "2011-06-15";
+
+/// 临时凭证提前刷新的时间窗口（分钟）
+const REFRESH_THRESHOLD_MINUTES: i64 = 5;
+
+/// credential_process 执行超时（秒）
+const CREDENTIAL_PROCESS_TIMEOUT_SECS: u64 = 30;
+
+/// 解析出的 AWS 凭证
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+    /// 临时凭证的过期时间
+    pub expiration: Option<DateTime<Utc>>,
+}
+
+impl AwsCredentials {
+    /// 转换为指定区域的 Bedrock 凭证
+    pub fn into_bedrock(self, region: &str) -> BedrockCredentials {
+        BedrockCredentials {
+            access_key_id: self.access_key_id,
+            secret_access_key: self.secret_access_key,
+            session_token: self.session_token,
+            region: region.to_string(),
+            default_model: None,
+        }
+    }
+
+    /// 是否需要刷新（临时凭证即将过期）
+    fn needs_refresh(&self) -> bool {
+        match self.expiration {
+            Some(expiration) => {
+                expiration <= Utc::now() + Duration::minutes(REFRESH_THRESHOLD_MINUTES)
+            }
+            None => false,
+        }
+    }
+}
+
+/// 缓存的临时凭证
+#[derive(Debug, Clone)]
+struct CachedCredentials {
+    /// 解析时凭证来源配置的指纹，配置变化后缓存失效
+    fingerprint: u64,
+    credentials: AwsCredentials,
+}
+
+lazy_static::lazy_static! {
+    /// 临时凭证缓存（按凭证 ID）
+    static ref CREDENTIAL_CACHE: RwLock<HashMap<String, CachedCredentials>> =
+        RwLock::new(HashMap::new());
+    /// 按凭证 ID 串行化临时凭证获取，避免并发请求重复调用 STS 或 credential_process
+    static ref RESOLVE_LOCKS: std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>> =
+        std::sync::Mutex::new(HashMap::new());
+}
+
+type ResolveFuture<'a> = Pin<Box<dyn Future<Output = Result<AwsCredentials>> + Send + 'a>>;
+
+/// 解析 Bedrock 凭证的 AWS 密钥
+///
+/// 带过期时间的临时凭证会按 `cache_key` 缓存，过期前 5 分钟自动重新获取；
+/// 凭证来源配置变化后缓存失效。同一 `cache_key` 的并发调用只会获取一次。
+pub async fn resolve_bedrock_credentials(
+    cache_key: &str,
+    credential: &ClaudeCredentials,
+) -> Result<BedrockCredentials> {
+    let region = credential.region.as_deref().unwrap_or("us-east-1");
+    let fingerprint = source_fingerprint(credential);
+
+    if let Some(cached) = cached_credentials(cache_key, fingerprint).await {
+        return Ok(cached.into_bedrock(region));
+    }
+
+    let lock = RESOLVE_LOCKS
+        .lock()
+        .unwrap()
+        .entry(cache_key.to_string())
+        .or_default()
+        .clone();
+    let _guard = lock.lock().await;
+    // 等待期间其他调用可能已经获取
+    if let Some(cached) = cached_credentials(cache_key, fingerprint).await {
+        return Ok(cached.into_bedrock(region));
+    }
+
+    let source = credential
+        .credential_source
+        .clone()
+        .unwrap_or(AwsCredentialSource::Static);
+    let resolved = resolve_source(&source, credential, 0).await?;
+
+    let mut cache = CREDENTIAL_CACHE.write().await;
+    if resolved.expiration.is_some() {
+        debug!("缓存 AWS 临时凭证: {}", cache_key);
+        cache.insert(
+            cache_key.to_string(),
+            CachedCredentials {
+                fingerprint,
+                credentials: resolved.clone(),
+            },
+        );
+    } else {
+        cache.remove(cache_key);
+    }
+
+    Ok(resolved.into_bedrock(region))
+}
+
+/// 读取仍然有效且与当前来源配置一致的缓存凭证
+async fn cached_credentials(cache_key: &str, fingerprint: u64) -> Option<AwsCredentials> {
+    CREDENTIAL_CACHE
+        .read()
+        .await
+        .get(cache_key)
+        .filter(|cached| cached.fingerprint == fingerprint && !cached.credentials.needs_refresh())
+        .map(|cached| cached.credentials.clone())
+}
+
+/// 凭证来源配置的指纹（来源、基础密钥、STS 端点与区域）
+fn source_fingerprint(credential: &ClaudeCredentials) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    credential.credential_source.hash(&mut hasher);
+    credential.access_key_id.hash(&mut hasher);
+    credential.secret_access_key.hash(&mut hasher);
+    credential.session_token.hash(&mut hasher);
+    credential.sts_endpoint.hash(&mut hasher);
+    credential.region.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 按来源解析 AWS 凭证
+fn resolve_source<'a>(
+    source: &'a AwsCredentialSource,
+    credential: &'a ClaudeCredentials,
+    depth: u8,
+) -> ResolveFuture<'a> {
+    Box::pin(async move {
+        if depth > 4 {
+            return Err(PluginError::invalid_config("AWS 凭证来源嵌套过深").into());
+        }
+
+        match source {
+            AwsCredentialSource::Static => {
+                let access_key_id = credential
+                    .access_key_id
+                    .clone()
+                    .ok_or_else(|| PluginError::invalid_config("Bedrock 凭证缺少 access_key_id"))?;
+                let secret_access_key = credential.secret_access_key.clone().ok_or_else(|| {
+                    PluginError::invalid_config("Bedrock 凭证缺少 secret_access_key")
+                })?;
+                Ok(AwsCredentials {
+                    access_key_id,
+                    secret_access_key,
+                    session_token: credential.session_token.clone(),
+                    expiration: None,
+                })
+            }
+            AwsCredentialSource::Environment => from_environment(),
+            AwsCredentialSource::Profile { profile } => {
+                let name = profile
+                    .clone()
+                    .or_else(|| std::env::var("AWS_PROFILE").ok())
+                    .unwrap_or_else(|| "default".to_string());
+                let settings = load_profile(&name)?;
+                let source = profile_to_source(&name, &settings)?;
+                match source {
+                    Some(source) => resolve_source_owned(source, credential, depth + 1).await,
+                    None => static_from_profile(&name, &settings),
+                }
+            }
+            AwsCredentialSource::Process { command } => run_credential_process(command).await,
+            AwsCredentialSource::WebIdentity {
+                role_arn,
+                token_file,
+                session_name,
+            } => {
+                let token = tokio::fs::read_to_string(token_file).await.map_err(|e| {
+                    PluginError::refresh_error(format!(
+                        "读取 Web Identity Token 失败: {} - {}",
+                        token_file, e
+                    ))
+                })?;
+                assume_role_with_web_identity(
+                    &sts_endpoint(credential),
+                    role_arn,
+                    session_name.as_deref().unwrap_or(DEFAULT_SESSION_NAME),
+                    token.trim(),
+                )
+                .await
+            }
+            AwsCredentialSource::AssumeRole {
+                role_arn,
+                external_id,
+                session_name,
+                duration_seconds,
+                source,
+            } => {
+                let base_source = source
+                    .as_deref()
+                    .cloned()
+                    .unwrap_or(AwsCredentialSource::Static);
+                let base = resolve_source_owned(base_source, credential, depth + 1).await?;
+                let region = credential.region.as_deref().unwrap_or("us-east-1");
+                assume_role(
+                    &sts_endpoint(credential),
+                    &base.into_bedrock(region),
+                    &AssumeRoleParams {
+                        role_arn,
+                        session_name: session_name.as_deref().unwrap_or(DEFAULT_SESSION_NAME),
+                        external_id: external_id.as_deref(),
+                        duration_seconds: *duration_seconds,
+                    },
+                )
+                .await
+            }
+        }
+    })
+}
+
+/// 解析临时构造的凭证来源
+fn resolve_source_owned(
+    source: AwsCredentialSource,
+    credential: &ClaudeCredentials,
+    depth: u8,
+) -> ResolveFuture<'_> {
+    Box::pin(async move { resolve_source(&source, credential, depth).await })
+}
+
+/// 从环境变量读取凭证
+fn from_environment() -> Result<AwsCredentials> {
+    let access_key_id = std::env::var("AWS_ACCESS_KEY_ID")
+        .map_err(|_| PluginError::invalid_config("环境变量 AWS_ACCESS_KEY_ID 未设置"))?;
+    let secret_access_key = std::env::var("AWS_SECRET_ACCESS_KEY")
+        .map_err(|_| PluginError::invalid_config("环境变量 AWS_SECRET_ACCESS_KEY 未设置"))?;
+    let session_token = std::env::var("AWS_SESSION_TOKEN")
+        .ok()
+        .filter(|t| !t.is_empty());
+
+    Ok(AwsCredentials {
+        access_key_id,
+        secret_access_key,
+        session_token,
+        expiration: None,
+    })
+}
+
+/// STS 端点
+fn sts_endpoint(credential: &ClaudeCredentials) -> String {
+    credential.sts_endpoint.clone().unwrap_or_else(|| {
+        format!(
+            "https://sts.{}.amazonaws.com",
+            credential.region.as_deref().unwrap_or("us-east-1")
+        )
+    })
+}
+
+/// 共享凭证文件路径
+fn shared_credentials_path() -> Option<PathBuf> {
+    std::env::var("AWS_SHARED_CREDENTIALS_FILE")
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|h| h.join(".aws").join("credentials")))
+}
+
+/// 配置文件路径
+fn config_path() -> Option<PathBuf> {
+    std::env::var("AWS_CONFIG_FILE")
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|h| h.join(".aws").join("config")))
+}
+
+/// 解析 INI 格式文件
+pub fn parse_ini(content: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            let name = line[1..line.len() - 1].trim().to_string();
+            sections.entry(name.clone()).or_default();
+            current = Some(name);
+        } else if let (Some(section), Some((key, value))) = (&current, line.split_once('=')) {
+            sections
+                .entry(section.clone())
+                .or_default()
+                .insert(key.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    sections
+}
+
+/// 合并 config 与 credentials 文件中的 profile 配置（credentials 优先）
+fn merge_profile(
+    name: &str,
+    config: &HashMap<String, HashMap<String, String>>,
+    credentials: &HashMap<String, HashMap<String, String>>,
+) -> Option<HashMap<String, String>> {
+    let config_section = if name == "default" {
+        config.get("default")
+    } else {
+        config.get(&format!("profile {}", name))
+    };
+
+    if config_section.is_none() && !credentials.contains_key(name) {
+        return None;
+    }
+
+    let mut merged = config_section.cloned().unwrap_or_default();
+    if let Some(section) = credentials.get(name) {
+        merged.extend(section.clone());
+    }
+    Some(merged)
+}
+
+/// 读取命名 profile
+fn load_profile(name: &str) -> Result<HashMap<String, String>> {
+    let read = |path: Option<PathBuf>| {
+        path.and_then(|p| std::fs::read_to_string(p).ok())
+            .map(|content| parse_ini(&content))
+            .unwrap_or_default()
+    };
+    let config = read(config_path());
+    let credentials = read(shared_credentials_path());
+
+    Ok(merge_profile(name, &config, &credentials)
+        .ok_or_else(|| PluginError::invalid_config(format!("AWS profile 不存在: {}", name)))?)
+}
+
+/// 将 profile 配置转换为凭证来源，静态密钥 profile 返回 None
+fn profile_to_source(
+    name: &str,
+    settings: &HashMap<String, String>,
+) -> Result<Option<AwsCredentialSource>> {
+    if let Some(role_arn) = settings.get("role_arn") {
+        let session_name = settings.get("role_session_name").cloned();
+
+        if let Some(token_file) = settings.get("web_identity_token_file") {
+            return Ok(Some(AwsCredentialSource::WebIdentity {
+                role_arn: role_arn.clone(),
+                token_file: token_file.clone(),
+                session_name,
+            }));
+        }
+
+        let source_profile = settings.get("source_profile").ok_or_else(|| {
+            PluginError::invalid_config(format!(
+                "AWS profile {} 配置了 role_arn 但缺少 source_profile",
+                name
+            ))
+        })?;
+        if source_profile == name {
+            return Err(PluginError::invalid_config(format!(
+                "AWS profile {} 的 source_profile 不能指向自身",
+                name
+            ))
+            .into());
+        }
+
+        return Ok(Some(AwsCredentialSource::AssumeRole {
+            role_arn: role_arn.clone(),
+            external_id: settings.get("external_id").cloned(),
+            session_name,
+            duration_seconds: settings
+                .get("duration_seconds")
+                .and_then(|v| v.parse().ok()),
+            source: Some(Box::new(AwsCredentialSource::Profile {
+                profile: Some(source_profile.clone()),
+            })),
+        }));
+    }
+
+    if settings.contains_key("aws_access_key_id") {
+        return Ok(None);
+    }
+
+    if let Some(command) = settings.get("credential_process") {
+        return Ok(Some(AwsCredentialSource::Process {
+            command: command.clone(),
+        }));
+    }
+
+    Err(PluginError::invalid_config(format!("AWS profile {} 没有可用的凭证配置", name)).into())
+}
+
+/// 读取 profile 中的静态密钥
+fn static_from_profile(name: &str, settings: &HashMap<String, String>) -> Result<AwsCredentials> {
+    let access_key_id = settings.get("aws_access_key_id").cloned().ok_or_else(|| {
+        PluginError::invalid_config(format!("AWS profile {} 缺少 aws_access_key_id", name))
+    })?;
+    let secret_access_key = settings
+        .get("aws_secret_access_key")
+        .cloned()
+        .ok_or_else(|| {
+            PluginError::invalid_config(format!("AWS profile {} 缺少 aws_secret_access_key", name))
+        })?;
+
+    Ok(AwsCredentials {
+        access_key_id,
+        secret_access_key,
+        session_token: settings.get("aws_session_token").cloned(),
+        expiration: None,
+    })
+}
+
+/// credential_process 输出
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProcessOutput {
+    version: u32,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    expiration: Option<DateTime<Utc>>,
+}
+
+/// 解析 credential_process 的 JSON 输出
+fn parse_process_output(stdout: &str) -> Result<AwsCredentials> {
+    let output: ProcessOutput = serde_json::from_str(stdout.trim()).map_err(|e| {
+        PluginError::refresh_error(format!("credential_process 输出格式错误: {}", e))
+    })?;
+    if output.version != 1 {
+        return Err(PluginError::refresh_error(format!(
+            "不支持的 credential_process 输出版本: {}",
+            output.version
+        ))
+        .into());
+    }
+
+    Ok(AwsCredentials {
+        access_key_id: output.access_key_id,
+        secret_access_key: output.secret_access_key,
+        session_token: output.session_token,
+        expiration: output.expiration,
+    })
+}
+
+/// 执行 credential_process 命令
+async fn run_credential_process(command: &str) -> Result<AwsCredentials> {
+    run_credential_process_with_timeout(
+        command,
+        std::time::Duration::from_secs(CREDENTIAL_PROCESS_TIMEOUT_SECS),
+    )
+    .await
+}
+
+/// 执行 credential_process 命令，超时后终止进程
+///
+/// 获取期间持有凭证的解析锁，命令挂起会阻塞该凭证的所有签名与选取请求，因此必须限时。
+async fn run_credential_process_with_timeout(
+    command: &str,
+    timeout: std::time::Duration,
+) -> Result<AwsCredentials> {
+    debug!("执行 credential_process");
+
+    let mut process = if cfg!(windows) {
+        let mut process = tokio::process::Command::new("cmd");
+        process.args(["/C", command]);
+        process
+    } else {
+        let mut process = tokio::process::Command::new("sh");
+        process.args(["-c", command]);
+        process
+    };
+    let output = tokio::time::timeout(timeout, process.kill_on_drop(true).output())
+        .await
+        .map_err(|_| {
+            PluginError::refresh_error(format!(
+                "credential_process 执行超时 ({} 秒)",
+                timeout.as_secs_f64()
+            ))
+        })??;
+
+    if !output.status.success() {
+        return Err(PluginError::refresh_error(format!(
+            "credential_process 执行失败: {} - {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+
+    parse_process_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// AssumeRole 参数
+struct AssumeRoleParams<'a> {
+    role_arn: &'a str,
+    session_name: &'a str,
+    external_id: Option<&'a str>,
+    duration_seconds: Option<u32>,
+}
+
+/// 调用 STS AssumeRole
+async fn assume_role(
+    endpoint: &str,
+    base: &BedrockCredentials,
+    params: &AssumeRoleParams<'_>,
+) -> Result<AwsCredentials> {
+    let mut form = vec![
+        ("Action", "AssumeRole".to_string()),
+        ("Version", STS_API_VERSION.to_string()),
+        ("RoleArn", params.role_arn.to_string()),
+        ("RoleSessionName", params.session_name.to_string()),
+    ];
+    if let Some(external_id) = params.external_id {
+        form.push(("ExternalId", external_id.to_string()));
+    }
+    if let Some(duration) = params.duration_seconds {
+        form.push(("DurationSeconds", duration.to_string()));
+    }
+    let body = encode_form(&form);
+
+    let url = format!("{}/", endpoint.trim_end_matches('/'));
+    let signature = sign_aws_request_for_service("POST", &url, base, "sts", body.as_bytes())?;
+
+    let mut request = build_client()?
+        .post(&url)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Authorization", &signature.authorization)
+        .header("x-amz-date", &signature.x_amz_date);
+    if let Some(token) = &signature.x_amz_security_token {
+        request = request.header("x-amz-security-token", token);
+    }
+
+    info!("STS AssumeRole: {}", params.role_arn);
+    let response = request.body(body).send().await?;
+    parse_sts_response(response).await
+}
+
+/// 调用 STS AssumeRoleWithWebIdentity（无需签名）
+async fn assume_role_with_web_identity(
+    endpoint: &str,
+    role_arn: &str,
+    session_name: &str,
+    token: &str,
+) -> Result<AwsCredentials> {
+    let body = encode_form(&[
+        ("Action", "AssumeRoleWithWebIdentity".to_string()),
+        ("Version", STS_API_VERSION.to_string()),
+        ("RoleArn", role_arn.to_string()),
+        ("RoleSessionName", session_name.to_string()),
+        ("WebIdentityToken", token.to_string()),
+    ]);
+
+    info!("STS AssumeRoleWithWebIdentity: {}", role_arn);
+    let response = build_client()?
+        .post(format!("{}/", endpoint.trim_end_matches('/')))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await?;
+    parse_sts_response(response).await
+}
+
+fn build_client() -> Result<Client> {
+    Ok(Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(10))
+        .timeout(std::time::Duration::from_secs(30))
+        .build()?)
+}
+
+fn encode_form(pairs: &[(&str, String)]) -> String {
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, urlencoding::encode(v)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// 解析 STS XML 响应
+async fn parse_sts_response(response: reqwest::Response) -> Result<AwsCredentials> {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+
+    if !status.is_success() {
+        let code = extract_xml_tag(&body, "Code").unwrap_or_default();
+        let message = extract_xml_tag(&body, "Message").unwrap_or(body.clone());
+        return Err(PluginError::RefreshFailed {
+            credential_id: None,
+            status: Some(status.as_u16()),
+            reason: format!("STS 请求失败: {} {} - {}", status, code, message),
+        }
+        .into());
+    }
+
+    let field = |tag: &str| {
+        extract_xml_tag(&body, tag)
+            .ok_or_else(|| PluginError::refresh_error(format!("STS 响应缺少 {}", tag)))
+    };
+    let expiration = DateTime::parse_from_rfc3339(&field("Expiration")?)
+        .map_err(|e| PluginError::refresh_error(format!("STS 响应 Expiration 格式错误: {}", e)))?
+        .with_timezone(&Utc);
+
+    Ok(AwsCredentials {
+        access_key_id: field("AccessKeyId")?,
+        secret_access_key: field("SecretAccessKey")?,
+        session_token: Some(field("SessionToken")?),
+        expiration: Some(expiration),
+    })
+}
+
+/// 提取 XML 标签内容
+fn extract_xml_tag(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(xml[start..end].trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::spawn_stub;
+
+    const STS_RESPONSE: &str = r#"<AssumeRoleResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <AssumeRoleResult>
+    <Credentials>
+      <AccessKeyId>ASIATEMP</AccessKeyId>
+      <SecretAccessKey>temp-secret</SecretAccessKey>
+      <SessionToken>temp-session</SessionToken>
+      <Expiration>2099-01-01T00:00:00Z</Expiration>
+    </Credentials>
+  </AssumeRoleResult>
+</AssumeRoleResponse>"#;
+
+    #[test]
+    fn test_profile_to_source() {
+        let config = parse_ini(
+            "[default]\nregion = us-east-1\n\n[profile deploy]\nrole_arn = arn:aws:iam::123456789012:role/Deploy\nsource_profile = default\nexternal_id = ext-1\n",
+        );
+        let credentials =
+            parse_ini("[default]\naws_access_key_id = AKIA\naws_secret_access_key = secret\n");
+
+        let default = merge_profile("default", &config, &credentials).unwrap();
+        assert_eq!(profile_to_source("default", &default).unwrap(), None);
+        assert_eq!(
+            static_from_profile("default", &default)
+                .unwrap()
+                .access_key_id,
+            "AKIA"
+        );
+
+        let deploy = merge_profile("deploy", &config, &credentials).unwrap();
+        match profile_to_source("deploy", &deploy).unwrap() {
+            Some(AwsCredentialSource::AssumeRole {
+                external_id,
+                source,
+                ..
+            }) => {
+                assert_eq!(external_id.as_deref(), Some("ext-1"));
+                assert_eq!(
+                    source.as_deref(),
+                    Some(&AwsCredentialSource::Profile {
+                        profile: Some("default".to_string())
+                    })
+                );
+            }
+            other => panic!("unexpected source: {:?}", other),
+        }
+
+        assert!(merge_profile("missing", &config, &credentials).is_none());
+    }
+
+    #[test]
+    fn test_parse_process_output() {
+        let creds = parse_process_output(
+            r#"{"Version":1,"AccessKeyId":"AKIA","SecretAccessKey":"s","SessionToken":"t","Expiration":"2099-01-01T00:00:00Z"}"#,
+        )
+        .unwrap();
+        assert_eq!(creds.session_token.as_deref(), Some("t"));
+        assert!(creds.expiration.is_some());
+        assert!(
+            parse_process_output(r#"{"Version":2,"AccessKeyId":"a","SecretAccessKey":"s"}"#)
+                .is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_credential_process_timeout() {
+        let error =
+            run_credential_process_with_timeout("sleep 5", std::time::Duration::from_millis(100))
+                .await
+                .unwrap_err();
+        assert_eq!(
+            crate::error::find_plugin_error(&error).map(|e| e.code()),
+            Some("refresh_failed")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_assume_role_against_stub() {
+        let (endpoint, captured) = spawn_stub(200, "text/xml", STS_RESPONSE).await;
+
+        let credential = ClaudeCredentials {
+            auth_type: crate::credentials::AuthType::Bedrock,
+            access_key_id: Some("AKIABASE".to_string()),
+            secret_access_key: Some("base-secret".to_string()),
+            sts_endpoint: Some(endpoint),
+            credential_source: Some(AwsCredentialSource::AssumeRole {
+                role_arn: "arn:aws:iam::123456789012:role/Claude".to_string(),
+                external_id: Some("ext-42".to_string()),
+                session_name: None,
+                duration_seconds: None,
+                source: None,
+            }),
+            ..Default::default()
+        };
+
+        let resolved = resolve_bedrock_credentials("test-assume-role", &credential)
+            .await
+            .unwrap();
+        assert_eq!(resolved.access_key_id, "ASIATEMP");
+        assert_eq!(resolved.session_token.as_deref(), Some("temp-session"));
+
+        let request = captured.await.unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("POST", "/")
+        );
+        assert!(request.body.contains("Action=AssumeRole"));
+        assert!(request.body.contains("ExternalId=ext-42"));
+        assert!(request
+            .header("authorization")
+            .unwrap()
+            .contains("Credential=AKIABASE/"));
+
+        // 第二次解析命中缓存，不再访问 STS
+        let cached = resolve_bedrock_credentials("test-assume-role", &credential)
+            .await
+            .unwrap();
+        assert_eq!(cached.access_key_id, "ASIATEMP");
+    }
+
+    #[tokio::test]
+    async fn test_cache_single_flight_and_source_change() {
+        // 桩服务只响应一次：并发解析必须只访问一次 STS
+        let (endpoint, _captured) = spawn_stub(200, "text/xml", STS_RESPONSE).await;
+        let mut credential = ClaudeCredentials {
+            auth_type: crate::credentials::AuthType::Bedrock,
+            access_key_id: Some("AKIABASE".to_string()),
+            secret_access_key: Some("base-secret".to_string()),
+            sts_endpoint: Some(endpoint),
+            credential_source: Some(AwsCredentialSource::AssumeRole {
+                role_arn: "arn:aws:iam::123456789012:role/First".to_string(),
+                external_id: None,
+                session_name: None,
+                duration_seconds: None,
+                source: None,
+            }),
+            ..Default::default()
+        };
+
+        let (first, second) = tokio::join!(
+            resolve_bedrock_credentials("test-single-flight", &credential),
+            resolve_bedrock_credentials("test-single-flight", &credential)
+        );
+        assert_eq!(first.unwrap().access_key_id, "ASIATEMP");
+        assert_eq!(second.unwrap().access_key_id, "ASIATEMP");
+
+        // 更新角色后不再使用旧角色的缓存凭证
+        let (endpoint, captured) = spawn_stub(200, "text/xml", STS_RESPONSE).await;
+        credential.sts_endpoint = Some(endpoint);
+        credential.credential_source = Some(AwsCredentialSource::AssumeRole {
+            role_arn: "arn:aws:iam::123456789012:role/Second".to_string(),
+            external_id: None,
+            session_name: None,
+            duration_seconds: None,
+            source: None,
+        });
+        resolve_bedrock_credentials("test-single-flight", &credential)
+            .await
+            .unwrap();
+        assert!(captured.await.unwrap().body.contains("role%2FSecond"));
+    }
+}
@@ -4,11 +4,16 @@
 
 #![allow(dead_code)]
 
+use crate::auth::probe::{probe_client, send_probe, ProbeResult};
+use crate::credentials::RegionState;
+use crate::error::PluginError;
 use anyhow::Result;
-use chrono::Utc;
-use reqwest::Client;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::debug;
 
 /// Bedrock 凭证
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,43 +25,307 @@ pub struct BedrockCredentials {
     pub default_model: Option<String>,
 }
 
-/// Bedrock 模型映射
+/// Bedrock 认证方式
+#[derive(Debug, Clone)]
+pub enum BedrockAuth {
+    /// AWS SigV4 签名
+    SigV4(BedrockCredentials),
+    /// Bedrock API Key（`Authorization: Bearer`，无需签名）
+    ApiKey { api_key: String, region: String },
+}
+
+impl BedrockAuth {
+    /// 认证对应的区域
+    pub fn region(&self) -> &str {
+        match self {
+            BedrockAuth::SigV4(credentials) => &credentials.region,
+            BedrockAuth::ApiKey { region, .. } => region,
+        }
+    }
+}
+
+/// Bedrock 模型映射（不含区域前缀与 `anthropic.` 前缀）
 pub const BEDROCK_MODEL_MAP: &[(&str, &str)] = &[
-    (
-        "claude-opus-4-20250514",
-        "us.anthropic.claude-opus-4-20250514-v1:0",
-    ),
-    (
-        "claude-opus-4-5-20251101",
-        "us.anthropic.claude-opus-4-5-20251101-v1:0",
-    ),
-    (
-        "claude-sonnet-4-20250514",
-        "us.anthropic.claude-sonnet-4-20250514-v1:0",
-    ),
+    ("claude-opus-4-20250514", "claude-opus-4-20250514-v1:0"),
+    ("claude-opus-4-5-20251101", "claude-opus-4-5-20251101-v1:0"),
+    ("claude-sonnet-4-20250514", "claude-sonnet-4-20250514-v1:0"),
     (
         "claude-sonnet-4-5-20250929",
-        "us.anthropic.claude-sonnet-4-5-20250929-v1:0",
+        "claude-sonnet-4-5-20250929-v1:0",
     ),
     (
         "claude-haiku-3-5-20241022",
-        "us.anthropic.claude-haiku-3-5-20241022-v1:0",
+        "claude-3-5-haiku-20241022-v1:0",
+    ),
+    (
+        "claude-3-5-haiku-20241022",
+        "claude-3-5-haiku-20241022-v1:0",
     ),
     (
         "claude-3-5-sonnet-20241022",
-        "us.anthropic.claude-3-5-sonnet-20241022-v2:0",
+        "claude-3-5-sonnet-20241022-v2:0",
     ),
 ];
 
-/// 将 Anthropic 模型名映射到 Bedrock 模型 ID
-pub fn map_to_bedrock_model(model: &str) -> String {
-    for (anthropic_model, bedrock_model) in BEDROCK_MODEL_MAP {
-        if model == *anthropic_model {
-            return bedrock_model.to_string();
+/// Bedrock 调用目标类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BedrockTargetKind {
+    /// 基础模型 ID（按需调用）
+    FoundationModel,
+    /// 跨区域推理配置文件（系统定义）
+    InferenceProfile,
+    /// 应用推理配置文件 ARN
+    ApplicationInferenceProfile,
+    /// 预置吞吐量 ARN
+    ProvisionedThroughput,
+}
+
+/// Bedrock 调用目标
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BedrockModelTarget {
+    /// 用于调用 URL 的模型 ID 或 ARN
+    pub model_id: String,
+    pub kind: BedrockTargetKind,
+}
+
+/// 模型映射选项
+#[derive(Debug, Clone, Default)]
+pub struct BedrockModelOptions<'a> {
+    /// 凭证区域
+    pub region: &'a str,
+    /// 显式指定的模型 ID 前缀（如 `eu.anthropic.`）
+    pub model_prefix: Option<&'a str>,
+    /// 凭证级模型映射，值可以是模型 ID、推理配置文件 ID 或 ARN
+    pub model_map: Option<&'a HashMap<String, String>>,
+    /// 模型发现得到的基础模型 ID（不含区域前缀），优先于内置映射表
+    pub discovered: Option<&'a HashMap<String, String>>,
+}
+
+/// 根据区域推导跨区域推理前缀
+pub fn geo_prefix_for_region(region: &str) -> Option<&'static str> {
+    if region.starts_with("us-gov-") {
+        Some("us-gov")
+    } else if region.starts_with("us-") {
+        Some("us")
+    } else if region.starts_with("eu-") {
+        Some("eu")
+    } else if region.starts_with("ap-") {
+        Some("apac")
+    } else {
+        None
+    }
+}
+
+/// 判断模型 ID 或 ARN 的调用目标类型
+pub fn classify_bedrock_target(model_id: &str) -> Result<BedrockTargetKind> {
+    if model_id.starts_with("arn:") {
+        let resource = model_id.splitn(6, ':').nth(5).unwrap_or("");
+        return match resource.split('/').next().unwrap_or("") {
+            "application-inference-profile" => Ok(BedrockTargetKind::ApplicationInferenceProfile),
+            "inference-profile" => Ok(BedrockTargetKind::InferenceProfile),
+            "provisioned-model" => Ok(BedrockTargetKind::ProvisionedThroughput),
+            "foundation-model" => Ok(BedrockTargetKind::FoundationModel),
+            _ => Err(PluginError::unsupported_model(
+                model_id,
+                format!("无法识别的 Bedrock ARN: {}", model_id),
+            )
+            .into()),
+        };
+    }
+
+    let is_profile = model_id
+        .split_once('.')
+        .map(|(prefix, rest)| rest.starts_with("anthropic.") && !prefix.is_empty())
+        .unwrap_or(false);
+    if is_profile {
+        Ok(BedrockTargetKind::InferenceProfile)
+    } else {
+        Ok(BedrockTargetKind::FoundationModel)
+    }
+}
+
+/// 将 Anthropic 模型名映射到 Bedrock 调用目标
+///
+/// 优先使用凭证级映射，其次使用内置映射表加上前缀（显式配置或由区域推导）。
+/// 未知模型返回错误，不做猜测。
+pub fn resolve_bedrock_model(
+    model: &str,
+    options: &BedrockModelOptions,
+) -> Result<BedrockModelTarget> {
+    if let Some(mapped) = options.model_map.and_then(|map| map.get(model)) {
+        return Ok(BedrockModelTarget {
+            kind: classify_bedrock_target(mapped)?,
+            model_id: mapped.clone(),
+        });
+    }
+
+    let base_id = options
+        .discovered
+        .and_then(|discovered| discovered.get(model))
+        .map(String::as_str)
+        .or_else(|| {
+            BEDROCK_MODEL_MAP
+                .iter()
+                .find(|(anthropic_model, _)| *anthropic_model == model)
+                .map(|(_, bedrock_model)| *bedrock_model)
+        })
+        .ok_or_else(|| {
+            PluginError::unsupported_model(
+                model,
+                format!(
+                    "Bedrock 没有模型 {} 的映射，请在凭证的 model_map 中配置模型 ID 或 ARN",
+                    model
+                ),
+            )
+        })?;
+
+    let prefix = match options.model_prefix {
+        Some(prefix) => prefix.to_string(),
+        None => match geo_prefix_for_region(options.region) {
+            Some(geo) => format!("{}.anthropic.", geo),
+            None => "anthropic.".to_string(),
+        },
+    };
+
+    let model_id = format!("{}{}", prefix, base_id);
+    Ok(BedrockModelTarget {
+        kind: classify_bedrock_target(&model_id)?,
+        model_id,
+    })
+}
+
+/// 去掉 Bedrock 模型 ID 的区域前缀或 ARN 部分，返回 `anthropic.` 之后的基础模型 ID
+///
+/// 如 `us.anthropic.claude-sonnet-4-5-20250929-v1:0` → `claude-sonnet-4-5-20250929-v1:0`。
+pub fn bedrock_base_id(model_id: &str) -> Option<&str> {
+    let id = model_id.rsplit('/').next().unwrap_or(model_id);
+    id.split_once("anthropic.").map(|(_, base)| base)
+}
+
+/// 将 Bedrock 模型 ID 还原为 Anthropic 模型名（去掉前缀与 `-v1:0` 版本后缀）
+pub fn anthropic_model_name(model_id: &str) -> Option<String> {
+    let base = bedrock_base_id(model_id)?;
+    let name = match base.rsplit_once("-v") {
+        Some((name, version))
+            if version
+                .split(':')
+                .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit())) =>
+        {
+            name
+        }
+        _ => base,
+    };
+    Some(name.to_string())
+}
+
+/// Guardrail 追踪设置的合法取值
+pub const GUARDRAIL_TRACE_VALUES: &[&str] = &["ENABLED", "DISABLED", "ENABLED_FULL"];
+
+/// 构建 Bedrock Guardrail 请求头
+///
+/// 未配置 identifier 时不添加任何 Guardrail 头；version 缺省为 `DRAFT`。
+pub fn build_guardrail_headers(
+    identifier: Option<&str>,
+    version: Option<&str>,
+    trace: Option<&str>,
+) -> Vec<(&'static str, String)> {
+    let mut headers = Vec::new();
+    if let Some(identifier) = identifier {
+        headers.push(("X-Amzn-Bedrock-GuardrailIdentifier", identifier.to_string()));
+        headers.push((
+            "X-Amzn-Bedrock-GuardrailVersion",
+            version.unwrap_or("DRAFT").to_string(),
+        ));
+        if let Some(trace) = trace {
+            headers.push(("X-Amzn-Bedrock-Trace", trace.to_string()));
         }
     }
-    // 默认映射规则
-    format!("us.anthropic.{}-v1:0", model)
+    headers
+}
+
+/// 检查 Bedrock 响应是否被 Guardrail 拦截
+pub fn is_guardrail_intervention(body: &str) -> bool {
+    match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(value) => value["amazon-bedrock-guardrailAction"] == "INTERVENED",
+        Err(_) => body.contains("amazon-bedrock-guardrailAction") && body.contains("INTERVENED"),
+    }
+}
+
+/// 区域限流冷却的基础时长（秒）
+const REGION_COOLDOWN_BASE_SECONDS: u64 = 30;
+
+/// 区域限流冷却的最长时长（秒）
+const REGION_COOLDOWN_MAX_SECONDS: u64 = 600;
+
+/// 选择最健康的区域
+///
+/// 指定模型时跳过模型发现确认未提供该模型的区域；
+/// 优先选择不在冷却期的区域（连续限流次数少者优先，其次按配置顺序）；
+/// 所有区域都在冷却时选择最早结束冷却的区域。
+pub fn select_region<'a>(
+    regions: &'a [String],
+    states: &HashMap<String, RegionState>,
+    model: Option<&str>,
+    now: DateTime<Utc>,
+) -> Option<&'a str> {
+    regions
+        .iter()
+        .enumerate()
+        .filter(|(_, region)| match (model, states.get(region.as_str())) {
+            (Some(model), Some(state)) => region_serves_model(state, model),
+            _ => true,
+        })
+        .min_by_key(|(index, region)| {
+            let state = states.get(region.as_str()).cloned().unwrap_or_default();
+            let cooling = state.cooldown_until.filter(|until| *until > now);
+            (
+                cooling.is_some(),
+                cooling,
+                state.consecutive_throttles,
+                *index,
+            )
+        })
+        .map(|(_, region)| region.as_str())
+}
+
+/// 区域是否提供指定模型（尚未发现时视为提供）
+pub fn region_serves_model(state: &RegionState, model: &str) -> bool {
+    state
+        .available_models
+        .as_ref()
+        .is_none_or(|models| models.iter().any(|m| m == model))
+}
+
+/// 记录区域限流，返回冷却截止时间
+///
+/// 未指定冷却时长时按连续限流次数指数退避。
+pub fn record_region_throttle(
+    state: &mut RegionState,
+    cooldown_seconds: Option<u64>,
+    now: DateTime<Utc>,
+) -> DateTime<Utc> {
+    state.consecutive_throttles += 1;
+    state.total_throttles += 1;
+
+    let seconds = cooldown_seconds.unwrap_or_else(|| {
+        let exponent = state.consecutive_throttles.saturating_sub(1).min(16);
+        (REGION_COOLDOWN_BASE_SECONDS << exponent).min(REGION_COOLDOWN_MAX_SECONDS)
+    });
+    let until = now + Duration::seconds(seconds as i64);
+    state.cooldown_until = Some(until);
+    until
+}
+
+/// 记录区域调用成功
+pub fn record_region_success(state: &mut RegionState) {
+    state.consecutive_throttles = 0;
+    state.cooldown_until = None;
+}
+
+/// 构建 Bedrock Runtime base URL
+pub fn build_bedrock_runtime_base_url(region: &str) -> String {
+    format!("https://bedrock-runtime.{}.amazonaws.com", region)
 }
 
 /// AWS 签名 V4
@@ -72,15 +341,29 @@ pub fn sign_aws_request(
     url: &str,
     credentials: &BedrockCredentials,
     body: &[u8],
+) -> Result<AwsSignature> {
+    sign_aws_request_for_service(method, url, credentials, "bedrock", body)
+}
+
+/// 为指定 AWS 服务生成签名 V4
+pub fn sign_aws_request_for_service(
+    method: &str,
+    url: &str,
+    credentials: &BedrockCredentials,
+    service: &str,
+    body: &[u8],
 ) -> Result<AwsSignature> {
     let now = Utc::now();
     let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
     let date_stamp = now.format("%Y%m%d").to_string();
 
     let parsed_url = reqwest::Url::parse(url)?;
-    let host = parsed_url.host_str().unwrap_or("");
-    let canonical_uri = parsed_url.path();
-    let canonical_querystring = parsed_url.query().unwrap_or("");
+    let host = match parsed_url.port() {
+        Some(port) => format!("{}:{}", parsed_url.host_str().unwrap_or(""), port),
+        None => parsed_url.host_str().unwrap_or("").to_string(),
+    };
+    let canonical_uri = canonical_uri(parsed_url.path());
+    let canonical_querystring = canonical_query(parsed_url.query().unwrap_or(""));
 
     // 计算 payload hash
     let payload_hash = hex::encode(Sha256::digest(body));
@@ -108,7 +391,10 @@ pub fn sign_aws_request(
 
     // 构建 string to sign
     let algorithm = "AWS4-HMAC-SHA256";
-    let credential_scope = format!("{}/{}/bedrock/aws4_request", date_stamp, credentials.region);
+    let credential_scope = format!(
+        "{}/{}/{}/aws4_request",
+        date_stamp, credentials.region, service
+    );
     let string_to_sign = format!(
         "{}\n{}\n{}\n{}",
         algorithm, amz_date, credential_scope, canonical_request_hash
@@ -119,7 +405,7 @@ pub fn sign_aws_request(
         &credentials.secret_access_key,
         &date_stamp,
         &credentials.region,
-        "bedrock",
+        service,
     );
     let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
 
@@ -136,6 +422,24 @@ pub fn sign_aws_request(
     })
 }
 
+/// 规范化 URI：非 S3 服务需对已编码的路径段再编码一次
+fn canonical_uri(path: &str) -> String {
+    if path.is_empty() {
+        return "/".to_string();
+    }
+    path.split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// 规范化查询字符串：按参数名排序
+fn canonical_query(query: &str) -> String {
+    let mut pairs: Vec<&str> = query.split('&').filter(|p| !p.is_empty()).collect();
+    pairs.sort_unstable();
+    pairs.join("&")
+}
+
 /// 生成签名密钥
 fn get_signature_key(key: &str, date_stamp: &str, region: &str, service: &str) -> Vec<u8> {
     let k_date = hmac_sha256(format!("AWS4{}", key).as_bytes(), date_stamp.as_bytes());
@@ -147,7 +451,6 @@ fn get_signature_key(key: &str, date_stamp: &str, region: &str, service: &str) -
 /// HMAC-SHA256
 fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
     use sha2::Sha256;
-    use std::iter::repeat;
 
     let block_size = 64;
     let mut key = key.to_vec();
@@ -157,7 +460,7 @@ fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
     }
 
     if key.len() < block_size {
-        key.extend(repeat(0u8).take(block_size - key.len()));
+        key.extend(std::iter::repeat_n(0u8, block_size - key.len()));
     }
 
     let mut i_key_pad: Vec<u8> = key.iter().map(|&b| b ^ 0x36).collect();
@@ -170,43 +473,223 @@ fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
     Sha256::digest(&o_key_pad).to_vec()
 }
 
+/// Bedrock 控制面地址
+pub fn build_bedrock_control_plane_url(region: &str) -> String {
+    format!("https://bedrock.{}.amazonaws.com", region)
+}
+
+/// 构建 Bedrock 控制面 GET 请求（SigV4 签名或 API Key）
+fn control_plane_request(client: &Client, auth: &BedrockAuth, url: &str) -> Result<RequestBuilder> {
+    Ok(match auth {
+        BedrockAuth::SigV4(credentials) => {
+            let signature = sign_aws_request("GET", url, credentials, &[])?;
+            let mut request = client
+                .get(url)
+                .header("Authorization", &signature.authorization)
+                .header("x-amz-date", &signature.x_amz_date);
+            if let Some(token) = &signature.x_amz_security_token {
+                request = request.header("x-amz-security-token", token);
+            }
+            request
+        }
+        BedrockAuth::ApiKey { api_key, .. } => client
+            .get(url)
+            .header("Authorization", format!("Bearer {}", api_key)),
+    })
+}
+
 /// 验证 Bedrock 凭证
-pub async fn validate_bedrock_credentials(credentials: &BedrockCredentials) -> Result<bool> {
-    let client = Client::builder()
-        .connect_timeout(std::time::Duration::from_secs(10))
-        .timeout(std::time::Duration::from_secs(30))
-        .build()?;
+///
+/// 发送签名后的 ListFoundationModels 请求；`endpoint` 为空时使用凭证区域的控制面地址。
+pub async fn validate_bedrock_credentials(
+    auth: &BedrockAuth,
+    endpoint: Option<&str>,
+) -> Result<ProbeResult> {
+    let base = endpoint
+        .map(|e| e.trim_end_matches('/').to_string())
+        .unwrap_or_else(|| build_bedrock_control_plane_url(auth.region()));
+    let url = format!("{}/foundation-models?byProvider=anthropic", base);
+
+    debug!("验证 Bedrock 凭证: {}", url);
+
+    let client = probe_client()?;
+    send_probe(control_plane_request(&client, auth, &url)?).await
+}
 
+/// 发送 Bedrock 控制面 GET 请求
+async fn control_plane_get(auth: &BedrockAuth, path_and_query: &str) -> Result<serde_json::Value> {
+    let client = probe_client()?;
     let url = format!(
-        "https://bedrock.{}.amazonaws.com/foundation-models",
-        credentials.region
+        "{}{}",
+        build_bedrock_control_plane_url(auth.region()),
+        path_and_query
     );
 
-    let signature = sign_aws_request("GET", &url, credentials, &[])?;
+    let response = control_plane_request(&client, auth, &url)?.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(PluginError::upstream("Bedrock 请求失败", status, body).into());
+    }
+
+    Ok(response.json().await?)
+}
+
+/// 调用 ListFoundationModels（仅 Anthropic 模型）
+pub async fn list_foundation_models(auth: &BedrockAuth) -> Result<serde_json::Value> {
+    control_plane_get(auth, "/foundation-models?byProvider=anthropic").await
+}
 
-    let mut request = client
-        .get(&url)
-        .header("Authorization", &signature.authorization)
-        .header("x-amz-date", &signature.x_amz_date)
-        .header("Host", format!("bedrock.{}.amazonaws.com", credentials.region));
+/// 调用 ListInferenceProfiles（自动翻页）
+pub async fn list_inference_profiles(auth: &BedrockAuth) -> Result<Vec<serde_json::Value>> {
+    let mut profiles = Vec::new();
+    let mut next_token: Option<String> = None;
 
-    if let Some(token) = &signature.x_amz_security_token {
-        request = request.header("x-amz-security-token", token);
+    loop {
+        let mut path = "/inference-profiles?maxResults=1000".to_string();
+        if let Some(token) = &next_token {
+            path.push_str(&format!("&nextToken={}", urlencoding::encode(token)));
+        }
+
+        let page = control_plane_get(auth, &path).await?;
+        if let Some(items) = page["inferenceProfileSummaries"].as_array() {
+            profiles.extend(items.iter().cloned());
+        }
+
+        next_token = page["nextToken"].as_str().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
     }
 
-    let response = request.send().await?;
+    Ok(profiles)
+}
+
+/// 区域提供的模型
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscoveredModel {
+    /// Anthropic 模型名
+    pub model: String,
+    /// 调用目标
+    pub target: BedrockModelTarget,
+}
 
-    Ok(response.status().is_success())
+/// 根据 ListFoundationModels 与 ListInferenceProfiles 的结果计算区域提供的 Claude 模型
+///
+/// 列表中的模型 ID 还原为 Anthropic 模型名，内置映射表只用于补充别名；
+/// 基础模型需要支持按需（ON_DEMAND）调用，推理配置文件需要处于 ACTIVE 状态；
+/// 应用推理配置文件与预置吞吐量 ARN 无法通过列表接口确认，视为提供。
+/// 列表接口只反映区域提供的模型，不代表账户已开通模型访问权限：未开通的模型在调用时
+/// 返回 AccessDeniedException，由 release_credential 按模型停用。
+pub fn compute_offered_models(
+    foundation_models: &serde_json::Value,
+    inference_profiles: &[serde_json::Value],
+    options: &BedrockModelOptions,
+) -> Vec<DiscoveredModel> {
+    let summaries = foundation_models["modelSummaries"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let on_demand: Vec<&str> = summaries
+        .iter()
+        .filter(|m| {
+            m["inferenceTypesSupported"]
+                .as_array()
+                .map(|types| types.iter().any(|t| t == "ON_DEMAND"))
+                .unwrap_or(false)
+        })
+        .filter_map(|m| m["modelId"].as_str())
+        .collect();
+
+    let mut listed: HashMap<String, String> = summaries
+        .iter()
+        .filter_map(|m| m["modelId"].as_str())
+        .filter_map(|id| Some((anthropic_model_name(id)?, bedrock_base_id(id)?.to_string())))
+        .collect();
+    let aliases: Vec<(String, String)> = BEDROCK_MODEL_MAP
+        .iter()
+        .filter(|(alias, base_id)| {
+            !listed.contains_key(*alias) && listed.values().any(|listed_id| listed_id == base_id)
+        })
+        .map(|(alias, base_id)| (alias.to_string(), base_id.to_string()))
+        .collect();
+    listed.extend(aliases);
+
+    let active_profiles: Vec<&str> = inference_profiles
+        .iter()
+        .filter(|p| p["status"].as_str().unwrap_or("ACTIVE") == "ACTIVE")
+        .flat_map(|p| {
+            [
+                p["inferenceProfileId"].as_str(),
+                p["inferenceProfileArn"].as_str(),
+            ]
+        })
+        .flatten()
+        .collect();
+
+    let mut models: Vec<&str> = listed.keys().map(String::as_str).collect();
+    if let Some(map) = options.model_map {
+        models.extend(map.keys().map(String::as_str));
+    }
+    models.sort_unstable();
+    models.dedup();
+
+    let options = BedrockModelOptions {
+        discovered: Some(&listed),
+        ..options.clone()
+    };
+    models
+        .into_iter()
+        .filter_map(|model| {
+            let target = resolve_bedrock_model(model, &options).ok()?;
+            let available = match target.kind {
+                BedrockTargetKind::FoundationModel => {
+                    let id = target.model_id.rsplit('/').next().unwrap_or("");
+                    on_demand.contains(&id)
+                }
+                BedrockTargetKind::InferenceProfile => {
+                    active_profiles.contains(&target.model_id.as_str())
+                }
+                BedrockTargetKind::ApplicationInferenceProfile
+                | BedrockTargetKind::ProvisionedThroughput => true,
+            };
+            available.then(|| DiscoveredModel {
+                model: model.to_string(),
+                target,
+            })
+        })
+        .collect()
 }
 
 /// 构建 Bedrock API URL
 pub fn build_bedrock_url(region: &str, model_id: &str) -> String {
+    build_bedrock_invoke_url(region, model_id, true)
+}
+
+/// 构建 Bedrock 调用 URL（模型 ID/ARN 会进行 URL 编码）
+pub fn build_bedrock_invoke_url(region: &str, model_id: &str, stream: bool) -> String {
     format!(
-        "https://bedrock-runtime.{}.amazonaws.com/model/{}/invoke-with-response-stream",
-        region, model_id
+        "{}/model/{}/{}",
+        build_bedrock_runtime_base_url(region),
+        urlencoding::encode(model_id),
+        if stream {
+            "invoke-with-response-stream"
+        } else {
+            "invoke"
+        }
     )
 }
 
+/// 将 Anthropic Messages 请求体转换为 Bedrock InvokeModel 请求体
+pub fn to_bedrock_body(body: &mut serde_json::Value) {
+    if let Some(obj) = body.as_object_mut() {
+        obj.remove("model");
+        obj.remove("stream");
+        obj.entry("anthropic_version")
+            .or_insert_with(|| serde_json::json!("bedrock-2023-05-31"));
+    }
+}
+
 /// hex 编码
 mod hex {
     pub fn encode(data: impl AsRef<[u8]>) -> String {
@@ -222,15 +705,182 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_map_to_bedrock_model() {
+    fn test_resolve_bedrock_model() {
+        let resolve = |model: &str, region: &str| {
+            resolve_bedrock_model(
+                model,
+                &BedrockModelOptions {
+                    region,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let target = resolve("claude-opus-4-5-20251101", "us-east-1").unwrap();
         assert_eq!(
-            map_to_bedrock_model("claude-opus-4-5-20251101"),
+            target.model_id,
             "us.anthropic.claude-opus-4-5-20251101-v1:0"
         );
+        assert_eq!(target.kind, BedrockTargetKind::InferenceProfile);
+        assert_eq!(
+            resolve("claude-sonnet-4-5-20250929", "eu-central-1")
+                .unwrap()
+                .model_id,
+            "eu.anthropic.claude-sonnet-4-5-20250929-v1:0"
+        );
         assert_eq!(
-            map_to_bedrock_model("claude-sonnet-4-5-20250929"),
-            "us.anthropic.claude-sonnet-4-5-20250929-v1:0"
+            resolve("claude-sonnet-4-20250514", "ap-northeast-1")
+                .unwrap()
+                .model_id,
+            "apac.anthropic.claude-sonnet-4-20250514-v1:0"
         );
+        assert!(resolve("claude-unknown-1", "us-east-1").is_err());
+    }
+
+    #[test]
+    fn test_resolve_bedrock_model_overrides() {
+        let arn = "arn:aws:bedrock:us-east-1:123456789012:application-inference-profile/abc123";
+        let map = HashMap::from([("claude-opus-4-5-20251101".to_string(), arn.to_string())]);
+        let options = BedrockModelOptions {
+            region: "us-east-1",
+            model_prefix: Some("anthropic."),
+            model_map: Some(&map),
+            discovered: None,
+        };
+
+        let target = resolve_bedrock_model("claude-opus-4-5-20251101", &options).unwrap();
+        assert_eq!(target.model_id, arn);
+        assert_eq!(target.kind, BedrockTargetKind::ApplicationInferenceProfile);
+
+        let target = resolve_bedrock_model("claude-sonnet-4-20250514", &options).unwrap();
+        assert_eq!(target.model_id, "anthropic.claude-sonnet-4-20250514-v1:0");
+        assert_eq!(target.kind, BedrockTargetKind::FoundationModel);
+
+        assert_eq!(
+            classify_bedrock_target("arn:aws:bedrock:us-east-1:123456789012:provisioned-model/xyz")
+                .unwrap(),
+            BedrockTargetKind::ProvisionedThroughput
+        );
+    }
+
+    #[test]
+    fn test_compute_offered_models() {
+        let foundation = serde_json::json!({
+            "modelSummaries": [
+                { "modelId": "anthropic.claude-sonnet-4-20250514-v1:0", "inferenceTypesSupported": ["INFERENCE_PROFILE"] },
+                { "modelId": "anthropic.claude-3-5-sonnet-20241022-v2:0", "inferenceTypesSupported": ["ON_DEMAND"] },
+                { "modelId": "anthropic.claude-3-5-haiku-20241022-v1:0", "inferenceTypesSupported": ["INFERENCE_PROFILE"] },
+                { "modelId": "anthropic.claude-haiku-4-5-20251001-v1:0", "inferenceTypesSupported": ["INFERENCE_PROFILE"] },
+                { "modelId": "meta.llama3-70b-instruct-v1:0", "inferenceTypesSupported": ["ON_DEMAND"] }
+            ]
+        });
+        let profiles = vec![
+            serde_json::json!({ "inferenceProfileId": "eu.anthropic.claude-sonnet-4-20250514-v1:0", "status": "ACTIVE" }),
+            serde_json::json!({ "inferenceProfileId": "eu.anthropic.claude-3-5-haiku-20241022-v1:0", "status": "ACTIVE" }),
+            serde_json::json!({ "inferenceProfileId": "eu.anthropic.claude-haiku-4-5-20251001-v1:0", "status": "ACTIVE" }),
+        ];
+        let map = HashMap::from([(
+            "claude-3-5-sonnet-20241022".to_string(),
+            "anthropic.claude-3-5-sonnet-20241022-v2:0".to_string(),
+        )]);
+        let options = BedrockModelOptions {
+            region: "eu-west-1",
+            model_prefix: None,
+            model_map: Some(&map),
+            discovered: None,
+        };
+
+        let models: Vec<String> = compute_offered_models(&foundation, &profiles, &options)
+            .into_iter()
+            .map(|m| m.model)
+            .collect();
+        // 列表中的新模型按 ID 还原名称，内置映射表只补充别名
+        assert_eq!(
+            models,
+            vec![
+                "claude-3-5-haiku-20241022",
+                "claude-3-5-sonnet-20241022",
+                "claude-haiku-3-5-20241022",
+                "claude-haiku-4-5-20251001",
+                "claude-sonnet-4-20250514"
+            ]
+        );
+        assert_eq!(
+            anthropic_model_name(
+                "arn:aws:bedrock:us-east-1::foundation-model/anthropic.claude-opus-4-20250514-v1:0"
+            ),
+            Some("claude-opus-4-20250514".to_string())
+        );
+    }
+
+    #[test]
+    fn test_build_guardrail_headers() {
+        assert!(build_guardrail_headers(None, Some("1"), Some("ENABLED")).is_empty());
+
+        let headers = build_guardrail_headers(Some("gr-123"), None, Some("ENABLED_FULL"));
+        assert_eq!(
+            headers,
+            vec![
+                ("X-Amzn-Bedrock-GuardrailIdentifier", "gr-123".to_string()),
+                ("X-Amzn-Bedrock-GuardrailVersion", "DRAFT".to_string()),
+                ("X-Amzn-Bedrock-Trace", "ENABLED_FULL".to_string()),
+            ]
+        );
+
+        assert!(is_guardrail_intervention(
+            r#"{"amazon-bedrock-guardrailAction":"INTERVENED","content":[]}"#
+        ));
+        assert!(!is_guardrail_intervention(
+            r#"{"amazon-bedrock-guardrailAction":"NONE"}"#
+        ));
+    }
+
+    #[test]
+    fn test_select_region_failover() {
+        let regions = vec!["us-east-1".to_string(), "us-west-2".to_string()];
+        let now = Utc::now();
+        let mut states = HashMap::new();
+        assert_eq!(
+            select_region(&regions, &states, None, now),
+            Some("us-east-1")
+        );
+
+        let mut east = RegionState::default();
+        let until = record_region_throttle(&mut east, None, now);
+        assert_eq!(until, now + Duration::seconds(30));
+        states.insert("us-east-1".to_string(), east.clone());
+        assert_eq!(
+            select_region(&regions, &states, None, now),
+            Some("us-west-2")
+        );
+
+        // 两个区域都在冷却时选择最早恢复的区域
+        let mut west = RegionState::default();
+        record_region_throttle(&mut west, Some(5), now);
+        states.insert("us-west-2".to_string(), west.clone());
+        assert_eq!(
+            select_region(&regions, &states, None, now),
+            Some("us-west-2")
+        );
+
+        // 冷却结束后恢复按顺序优先
+        record_region_success(&mut east);
+        states.insert("us-east-1".to_string(), east.clone());
+        assert_eq!(
+            select_region(&regions, &states, None, now),
+            Some("us-east-1")
+        );
+
+        // 跳过模型发现确认未提供该模型的区域，没有区域可用时返回 None
+        record_region_success(&mut west);
+        east.available_models = Some(vec!["claude-sonnet-4-20250514".to_string()]);
+        west.available_models = Some(vec!["claude-opus-4-5-20251101".to_string()]);
+        states.insert("us-east-1".to_string(), east);
+        states.insert("us-west-2".to_string(), west);
+        let select = |model| select_region(&regions, &states, Some(model), now);
+        assert_eq!(select("claude-sonnet-4-20250514"), Some("us-east-1"));
+        assert_eq!(select("claude-opus-4-5-20251101"), Some("us-west-2"));
+        assert_eq!(select("claude-3-5-haiku-20241022"), None);
     }
 
     #[test]
@@ -238,5 +888,46 @@ mod tests {
         let url = build_bedrock_url("us-east-1", "us.anthropic.claude-opus-4-5-20251101-v1:0");
         assert!(url.contains("bedrock-runtime.us-east-1.amazonaws.com"));
         assert!(url.contains("invoke-with-response-stream"));
+        assert!(url.contains("v1%3A0"));
+        assert_eq!(
+            canonical_uri("/model/us.anthropic.x-v1%3A0/invoke"),
+            "/model/us.anthropic.x-v1%253A0/invoke"
+        );
+    }
+    #[tokio::test]
+    async fn test_validate_bedrock_credentials_signs_list_call() {
+        let (url, captured) = crate::test_support::spawn_stub(
+            200,
+            "application/json",
+            r#"{"modelSummaries":[{"modelId":"anthropic.claude-sonnet-4-5-20250929-v1:0"}]}"#,
+        )
+        .await;
+        let auth = BedrockAuth::SigV4(BedrockCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: Some("session".to_string()),
+            region: "us-west-2".to_string(),
+            default_model: None,
+        });
+
+        let probe = validate_bedrock_credentials(&auth, Some(&url))
+            .await
+            .unwrap();
+        assert!(probe.valid);
+        assert_eq!(
+            probe.body.unwrap()["modelSummaries"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let request = captured.await.unwrap();
+        assert_eq!(request.path, "/foundation-models?byProvider=anthropic");
+        assert!(request
+            .header("authorization")
+            .unwrap()
+            .contains("us-west-2/bedrock/aws4_request"));
+        assert_eq!(request.header("x-amz-security-token"), Some("session"));
     }
 }
@@ -4,45 +4,239 @@
 
 #![allow(dead_code)]
 
+use crate::auth::probe::{
+    probe_chat_completions, probe_client, probe_messages, send_probe, ProbeResult,
+};
+use crate::credentials::{ApiKeyState, KeyRotation, UpstreamProtocol};
+use crate::error::PluginError;
 use anyhow::Result;
-use reqwest::Client;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use tracing::debug;
 
+/// 默认认证头模板
+pub const DEFAULT_AUTH_HEADER: &str = "x-api-key: {api_key}";
+/// OpenAI 协议中转服务的默认认证头模板
+pub const DEFAULT_OPENAI_AUTH_HEADER: &str = "Authorization: Bearer {api_key}";
+
 /// CCR 凭证
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CCRCredentials {
     pub api_key: String,
     pub base_url: String,
     pub name: Option<String>,
+    /// 认证头模板（如 `Authorization: Bearer {api_key}`），默认 `x-api-key: {api_key}`
+    #[serde(default)]
+    pub auth_header: Option<String>,
+    /// 额外的静态请求头（同名时覆盖默认请求头）
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
+    /// 中转服务使用的协议
+    #[serde(default)]
+    pub upstream_protocol: UpstreamProtocol,
 }
 
 /// 验证 CCR 凭证
-pub async fn validate_ccr_credentials(credentials: &CCRCredentials) -> Result<bool> {
-    let client = Client::builder()
-        .connect_timeout(std::time::Duration::from_secs(10))
-        .timeout(std::time::Duration::from_secs(30))
-        .build()?;
-
-    // 尝试调用 /v1/models 端点验证凭证
-    let url = format!("{}/v1/models", credentials.base_url.trim_end_matches('/'));
+///
+/// 调用 /v1/models 端点，只有 401/403 视为凭证无效，其他状态码可能是端点不存在但凭证有效。
+pub async fn validate_ccr_credentials(credentials: &CCRCredentials) -> Result<ProbeResult> {
+    let url = build_ccr_url(&credentials.base_url, "v1/models");
 
     debug!("验证 CCR 凭证: {}", url);
 
-    let response = client
-        .get(&url)
-        .header("x-api-key", &credentials.api_key)
-        .header("anthropic-version", "2023-06-01")
-        .send()
-        .await?;
+    let mut request = probe_client()?.get(&url);
+    for (name, value) in build_ccr_headers(credentials)? {
+        request = request.header(name, value);
+    }
+    let mut probe = send_probe(request).await?;
+    probe.valid = probe.status != 401 && probe.status != 403;
+
+    Ok(probe)
+}
+
+/// 通过最小推理请求验证 CCR 凭证（按中转服务协议发送 Messages 或 Chat Completions 请求）
+pub async fn probe_ccr_messages(credentials: &CCRCredentials, model: &str) -> Result<ProbeResult> {
+    let headers = build_ccr_headers(credentials)?;
+    match credentials.upstream_protocol {
+        UpstreamProtocol::Anthropic => probe_messages(&credentials.base_url, &headers, model).await,
+        UpstreamProtocol::Openai => {
+            probe_chat_completions(&credentials.base_url, &headers, model).await
+        }
+    }
+}
+
+/// 中转服务的推理接口路径
+pub fn ccr_inference_path(protocol: UpstreamProtocol) -> &'static str {
+    match protocol {
+        UpstreamProtocol::Anthropic => "/v1/messages",
+        UpstreamProtocol::Openai => "/v1/chat/completions",
+    }
+}
+
+/// 获取中转服务报告的模型列表（兼容 Anthropic 分页格式与 OpenAI 格式）
+pub async fn list_ccr_models(credentials: &CCRCredentials) -> Result<Vec<serde_json::Value>> {
+    let client = probe_client()?;
+    let mut models = Vec::new();
+    let mut after_id: Option<String> = None;
+
+    loop {
+        let mut url = build_ccr_url(&credentials.base_url, "v1/models?limit=1000");
+        if let Some(after) = &after_id {
+            url.push_str(&format!("&after_id={}", urlencoding::encode(after)));
+        }
+
+        let mut request = client.get(&url);
+        for (name, value) in build_ccr_headers(credentials)? {
+            request = request.header(name, value);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(PluginError::upstream("中转服务模型列表获取失败", status, body).into());
+        }
 
-    // 如果返回 401/403 则凭证无效，其他状态码可能是端点不存在但凭证有效
-    let status = response.status();
-    if status.as_u16() == 401 || status.as_u16() == 403 {
-        return Ok(false);
+        let page: serde_json::Value = response.json().await?;
+        let items = page["data"].as_array().ok_or_else(|| {
+            PluginError::upstream("中转服务模型列表格式错误", status, page.to_string())
+        })?;
+        models.extend(items.iter().filter(|m| m["id"].is_string()).cloned());
+
+        after_id = page["last_id"].as_str().map(String::from);
+        if !page["has_more"].as_bool().unwrap_or(false) || after_id.is_none() || items.is_empty() {
+            break;
+        }
     }
 
-    Ok(true)
+    debug!("中转服务报告 {} 个模型", models.len());
+    Ok(models)
+}
+
+/// 将请求的模型名映射为中转服务使用的别名
+pub fn resolve_ccr_model(model: &str, aliases: &HashMap<String, String>) -> String {
+    aliases
+        .get(model)
+        .cloned()
+        .unwrap_or_else(|| model.to_string())
+}
+
+/// 模型名是否匹配模式（支持末尾 `*` 通配）
+pub fn model_matches(pattern: &str, model: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model.starts_with(prefix),
+        None => pattern == model,
+    }
+}
+
+/// 模型是否通过允许/拒绝列表（拒绝优先，允许列表为空时不限制）
+pub fn is_model_allowed(model: &str, allowed: &[String], denied: &[String]) -> bool {
+    if denied.iter().any(|p| model_matches(p, model)) {
+        return false;
+    }
+    allowed.is_empty() || allowed.iter().any(|p| model_matches(p, model))
+}
+
+/// 限流时未指定冷却时间的默认冷却秒数
+const DEFAULT_KEY_COOLDOWN_SECONDS: i64 = 60;
+
+/// API Key 的稳定标识（SHA-256 前 12 位，不暴露 Key 本身）
+pub fn api_key_id(api_key: &str) -> String {
+    Sha256::digest(api_key.as_bytes())[..6]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// API Key 当前是否可用（不在冷却中）
+pub fn is_key_available(state: Option<&ApiKeyState>, now: DateTime<Utc>) -> bool {
+    state
+        .and_then(|s| s.cooldown_until)
+        .is_none_or(|until| until <= now)
+}
+
+/// 按轮换策略选择 API Key，返回其下标
+///
+/// 优先选择健康的 Key；只剩冷却结束但尚未恢复的 Key 时也会放行，用于试探恢复。
+pub fn select_api_key(
+    keys: &[String],
+    states: &HashMap<String, ApiKeyState>,
+    rotation: KeyRotation,
+    cursor: &mut usize,
+    now: DateTime<Utc>,
+) -> Option<usize> {
+    let state_of = |i: usize| states.get(&api_key_id(&keys[i]));
+    let available: Vec<usize> = (0..keys.len())
+        .filter(|&i| is_key_available(state_of(i), now))
+        .collect();
+    let healthy: Vec<usize> = available
+        .iter()
+        .copied()
+        .filter(|&i| state_of(i).is_none_or(|s| s.is_healthy))
+        .collect();
+    let candidates = if healthy.is_empty() {
+        available
+    } else {
+        healthy
+    };
+
+    let selected = match rotation {
+        KeyRotation::RoundRobin => candidates
+            .iter()
+            .copied()
+            .find(|&i| i >= *cursor % keys.len().max(1))
+            .or_else(|| candidates.first().copied()),
+        KeyRotation::LeastUsed => candidates
+            .iter()
+            .copied()
+            .min_by_key(|&i| state_of(i).map(|s| s.usage_count).unwrap_or(0)),
+    }?;
+
+    *cursor = selected + 1;
+    Some(selected)
+}
+
+/// 记录 API Key 调用成功
+pub fn record_key_success(state: &mut ApiKeyState) {
+    state.usage_count += 1;
+    state.consecutive_failures = 0;
+    state.cooldown_until = None;
+    state.is_healthy = true;
+    state.last_error = None;
+}
+
+/// 记录 API Key 调用失败
+///
+/// 限流只让 Key 冷却；认证失败或连续失败达到阈值时标记为不健康，冷却 `retry_seconds` 后再试探。
+pub fn record_key_failure(
+    state: &mut ApiKeyState,
+    message: Option<String>,
+    throttled: Option<u64>,
+    fatal: bool,
+    unhealthy_threshold: u32,
+    retry_seconds: u64,
+    now: DateTime<Utc>,
+) {
+    state.usage_count += 1;
+    state.error_count += 1;
+    state.last_error = message;
+
+    if let Some(cooldown) = throttled {
+        let seconds = if cooldown > 0 {
+            cooldown as i64
+        } else {
+            DEFAULT_KEY_COOLDOWN_SECONDS
+        };
+        state.cooldown_until = Some(now + Duration::seconds(seconds));
+        return;
+    }
+
+    state.consecutive_failures += 1;
+    if fatal || state.consecutive_failures >= unhealthy_threshold.max(1) {
+        state.is_healthy = false;
+        state.cooldown_until = Some(now + Duration::seconds(retry_seconds as i64));
+    }
 }
 
 /// 构建 CCR API URL
@@ -54,13 +248,60 @@ pub fn build_ccr_url(base_url: &str, endpoint: &str) -> String {
     )
 }
 
+/// 解析认证头模板，返回（请求头名称，值模板）
+pub fn parse_auth_header_template(template: &str) -> Result<(String, String)> {
+    let (name, value) = template.split_once(':').ok_or_else(|| {
+        PluginError::invalid_config(format!(
+            "认证头模板格式应为 `Header-Name: value`: {}",
+            template
+        ))
+    })?;
+    let (name, value) = (name.trim(), value.trim());
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(PluginError::invalid_config(format!(
+            "认证头模板的请求头名称无效: {}",
+            template
+        ))
+        .into());
+    }
+    if !value.contains("{api_key}") {
+        return Err(PluginError::invalid_config(format!(
+            "认证头模板需要包含 {{api_key}} 占位符: {}",
+            template
+        ))
+        .into());
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
 /// 构建 CCR 请求头
-pub fn build_ccr_headers(api_key: &str) -> Vec<(&'static str, String)> {
-    vec![
-        ("x-api-key", api_key.to_string()),
-        ("anthropic-version", "2023-06-01".to_string()),
-        ("Content-Type", "application/json".to_string()),
-    ]
+///
+/// 依次为认证头、`anthropic-version`、`Content-Type`，最后应用 `extra_headers`（同名覆盖）。
+pub fn build_ccr_headers(credentials: &CCRCredentials) -> Result<Vec<(String, String)>> {
+    let default_template = match credentials.upstream_protocol {
+        UpstreamProtocol::Anthropic => DEFAULT_AUTH_HEADER,
+        UpstreamProtocol::Openai => DEFAULT_OPENAI_AUTH_HEADER,
+    };
+    let (name, value) = parse_auth_header_template(
+        credentials
+            .auth_header
+            .as_deref()
+            .unwrap_or(default_template),
+    )?;
+
+    let mut headers = vec![
+        (name, value.replace("{api_key}", &credentials.api_key)),
+        ("anthropic-version".to_string(), "2023-06-01".to_string()),
+        ("Content-Type".to_string(), "application/json".to_string()),
+    ];
+    let mut extra: Vec<_> = credentials.extra_headers.iter().collect();
+    extra.sort();
+    for (name, value) in extra {
+        headers.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+        headers.push((name.clone(), value.clone()));
+    }
+
+    Ok(headers)
 }
 
 #[cfg(test)]
@@ -81,8 +322,189 @@ mod tests {
 
     #[test]
     fn test_build_ccr_headers() {
-        let headers = build_ccr_headers("test-api-key");
+        let mut credentials = CCRCredentials {
+            api_key: "test-api-key".to_string(),
+            ..Default::default()
+        };
+        let headers = build_ccr_headers(&credentials).unwrap();
         assert_eq!(headers.len(), 3);
-        assert_eq!(headers[0], ("x-api-key", "test-api-key".to_string()));
+        assert_eq!(
+            headers[0],
+            ("x-api-key".to_string(), "test-api-key".to_string())
+        );
+
+        credentials.auth_header = Some("Authorization: Bearer {api_key}".to_string());
+        credentials.extra_headers = HashMap::from([
+            ("X-Tenant-Id".to_string(), "tenant-1".to_string()),
+            ("Anthropic-Version".to_string(), "2023-01-01".to_string()),
+        ]);
+        let headers = build_ccr_headers(&credentials).unwrap();
+        assert_eq!(
+            headers[0],
+            (
+                "Authorization".to_string(),
+                "Bearer test-api-key".to_string()
+            )
+        );
+        assert!(headers.contains(&("X-Tenant-Id".to_string(), "tenant-1".to_string())));
+        assert!(headers.contains(&("Anthropic-Version".to_string(), "2023-01-01".to_string())));
+        assert!(!headers.iter().any(|(k, _)| k == "anthropic-version"));
+
+        assert!(parse_auth_header_template("Authorization Bearer {api_key}").is_err());
+        assert!(parse_auth_header_template("x-api-key: static").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_ccr_credentials_against_stub() {
+        let (url, captured) = crate::test_support::spawn_stub_sequence(vec![
+            (404, "text/plain", "not found"),
+            (401, "application/json", r#"{"error":"invalid key"}"#),
+        ])
+        .await;
+        let credentials = CCRCredentials {
+            api_key: "relay-key".to_string(),
+            base_url: url,
+            ..Default::default()
+        };
+
+        // /v1/models 不存在不代表凭证无效
+        assert!(validate_ccr_credentials(&credentials).await.unwrap().valid);
+        let probe = probe_ccr_messages(&credentials, "claude-sonnet-4-5-20250929")
+            .await
+            .unwrap();
+        assert!(!probe.valid);
+        assert_eq!(probe.status, 401);
+
+        let requests = captured.await.unwrap();
+        assert_eq!(requests[0].path, "/v1/models");
+        assert_eq!(requests[1].header("x-api-key"), Some("relay-key"));
+    }
+
+    #[test]
+    fn test_model_alias_and_filters() {
+        let aliases = HashMap::from([(
+            "claude-sonnet-4-5-20250929".to_string(),
+            "sonnet-4.5".to_string(),
+        )]);
+        assert_eq!(
+            resolve_ccr_model("claude-sonnet-4-5-20250929", &aliases),
+            "sonnet-4.5"
+        );
+        assert_eq!(
+            resolve_ccr_model("claude-opus-4-5-20251101", &aliases),
+            "claude-opus-4-5-20251101"
+        );
+
+        let allowed = vec!["claude-sonnet-*".to_string(), "claude-opus-*".to_string()];
+        let denied = vec!["claude-opus-4-20250514".to_string()];
+        assert!(is_model_allowed(
+            "claude-sonnet-4-5-20250929",
+            &allowed,
+            &denied
+        ));
+        assert!(is_model_allowed(
+            "claude-opus-4-5-20251101",
+            &allowed,
+            &denied
+        ));
+        assert!(!is_model_allowed(
+            "claude-opus-4-20250514",
+            &allowed,
+            &denied
+        ));
+        assert!(!is_model_allowed(
+            "claude-3-5-haiku-20241022",
+            &allowed,
+            &denied
+        ));
+        assert!(is_model_allowed("claude-3-5-haiku-20241022", &[], &[]));
+    }
+
+    #[tokio::test]
+    async fn test_list_ccr_models_follows_pages() {
+        let (url, captured) = crate::test_support::spawn_stub_sequence(vec![
+            (
+                200,
+                "application/json",
+                r#"{"data":[{"id":"sonnet-4.5","type":"model"}],"has_more":true,"last_id":"sonnet-4.5"}"#,
+            ),
+            (
+                200,
+                "application/json",
+                r#"{"data":[{"id":"opus-4.5","type":"model"}],"has_more":false,"last_id":"opus-4.5"}"#,
+            ),
+        ])
+        .await;
+        let credentials = CCRCredentials {
+            api_key: "relay-key".to_string(),
+            base_url: url,
+            ..Default::default()
+        };
+
+        let models = list_ccr_models(&credentials).await.unwrap();
+        let ids: Vec<_> = models.iter().map(|m| m["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["sonnet-4.5", "opus-4.5"]);
+
+        let requests = captured.await.unwrap();
+        assert_eq!(
+            requests[1].path,
+            "/v1/models?limit=1000&after_id=sonnet-4.5"
+        );
+    }
+
+    #[test]
+    fn test_select_api_key_rotation() {
+        let keys: Vec<String> = ["key-a", "key-b", "key-c"]
+            .iter()
+            .map(|k| k.to_string())
+            .collect();
+        let now = Utc::now();
+        let mut states = HashMap::new();
+        let mut cursor = 0;
+
+        let picks: Vec<_> = (0..4)
+            .map(|_| select_api_key(&keys, &states, KeyRotation::RoundRobin, &mut cursor, now))
+            .collect();
+        assert_eq!(picks, vec![Some(0), Some(1), Some(2), Some(0)]);
+
+        // 限流的 Key 在冷却期间被跳过
+        let mut throttled = ApiKeyState::default();
+        record_key_failure(&mut throttled, None, Some(30), false, 3, 300, now);
+        states.insert(api_key_id("key-b"), throttled);
+        assert_eq!(
+            select_api_key(&keys, &states, KeyRotation::RoundRobin, &mut cursor, now),
+            Some(2)
+        );
+
+        // least_used 选择使用次数最少的可用 Key
+        let mut used = ApiKeyState::default();
+        record_key_success(&mut used);
+        states.insert(api_key_id("key-a"), used);
+        assert_eq!(
+            select_api_key(&keys, &states, KeyRotation::LeastUsed, &mut cursor, now),
+            Some(2)
+        );
+
+        // 认证失败的 Key 标记为不健康，冷却结束后仅在没有健康 Key 时放行
+        let mut revoked = ApiKeyState::default();
+        record_key_failure(&mut revoked, None, None, true, 3, 60, now);
+        assert!(!revoked.is_healthy);
+        states.insert(api_key_id("key-c"), revoked);
+        let later = now + Duration::seconds(61);
+        assert_eq!(
+            select_api_key(&keys, &states, KeyRotation::LeastUsed, &mut cursor, later),
+            Some(0)
+        );
+        let only_c = vec!["key-c".to_string()];
+        assert_eq!(
+            select_api_key(
+                &only_c,
+                &states,
+                KeyRotation::RoundRobin,
+                &mut cursor,
+                later
+            ),
+            Some(0)
+        );
     }
 }
@@ -0,0 +1,206 @@
+//! Microsoft Foundry (Azure AI Foundry) 认证模块
+//!
+//! 支持 API Key 与 Entra ID 客户端凭证两种方式访问 Foundry 上部署的 Claude
+
+use crate::auth::probe::{probe_messages, ProbeResult};
+use crate::error::PluginError;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::{debug, info};
+
+/// Entra ID 默认认证端点
+pub const DEFAULT_AUTHORITY_HOST: &str = "https://login.microsoftonline.com";
+
+/// 访问 Foundry 所需的 scope
+pub const FOUNDRY_SCOPE: &str = "https://cognitiveservices.azure.com/.default";
+
+/// Entra ID 访问令牌
+#[derive(Debug, Clone)]
+pub struct EntraAccessToken {
+    pub access_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Token 响应
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+}
+
+/// 构建 Foundry Anthropic base URL
+///
+/// 资源端点形如 `https://<resource>.services.ai.azure.com`，Claude 位于 `/anthropic` 路径下。
+pub fn build_foundry_base_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/anthropic") {
+        endpoint.to_string()
+    } else {
+        format!("{}/anthropic", endpoint)
+    }
+}
+
+/// 将 Anthropic 模型名映射到 Foundry 部署名
+///
+/// 未配置部署映射时直接使用模型名；配置了映射但找不到对应部署时返回错误。
+pub fn resolve_deployment(model: &str, deployments: &HashMap<String, String>) -> Result<String> {
+    if deployments.is_empty() {
+        return Ok(model.to_string());
+    }
+    deployments.get(model).cloned().ok_or_else(|| {
+        PluginError::unsupported_model(model, format!("Foundry 凭证没有模型 {} 的部署", model))
+            .into()
+    })
+}
+
+/// 使用 Entra ID 客户端凭证换取访问令牌
+pub async fn fetch_entra_token(
+    authority_host: Option<&str>,
+    tenant_id: &str,
+    client_id: &str,
+    client_secret: &str,
+) -> Result<EntraAccessToken> {
+    let url = format!(
+        "{}/{}/oauth2/v2.0/token",
+        authority_host
+            .unwrap_or(DEFAULT_AUTHORITY_HOST)
+            .trim_end_matches('/'),
+        tenant_id
+    );
+
+    let client = Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(10))
+        .timeout(std::time::Duration::from_secs(30))
+        .build()?;
+
+    debug!("换取 Entra ID 访问令牌: {}", client_id);
+
+    let body = format!(
+        "grant_type=client_credentials&client_id={}&client_secret={}&scope={}",
+        urlencoding::encode(client_id),
+        urlencoding::encode(client_secret),
+        urlencoding::encode(FOUNDRY_SCOPE)
+    );
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(PluginError::refresh_failed(
+            "Entra ID 访问令牌获取失败",
+            status.as_u16(),
+            body,
+        )
+        .into());
+    }
+
+    let token: TokenResponse = response.json().await?;
+    info!("Entra ID 访问令牌获取成功");
+
+    Ok(EntraAccessToken {
+        access_token: token.access_token,
+        expires_at: Utc::now() + Duration::seconds(token.expires_in.unwrap_or(3600)),
+    })
+}
+
+/// 构建 Foundry 认证请求头
+pub fn build_foundry_headers(
+    api_key: Option<&str>,
+    access_token: Option<&str>,
+) -> Result<Vec<(&'static str, String)>> {
+    let auth = match (api_key, access_token) {
+        (Some(key), _) => ("x-api-key", key.to_string()),
+        (None, Some(token)) => ("Authorization", format!("Bearer {}", token)),
+        (None, None) => {
+            return Err(PluginError::invalid_config(
+                "Foundry 凭证需要 api_key 或 Entra ID 访问令牌",
+            )
+            .into())
+        }
+    };
+    Ok(vec![
+        auth,
+        ("anthropic-version", "2023-06-01".to_string()),
+        ("Content-Type", "application/json".to_string()),
+    ])
+}
+
+/// 发送最小推理请求探测 Foundry 部署
+pub async fn probe_foundry(
+    base_url: &str,
+    headers: &[(&'static str, String)],
+    deployment: &str,
+) -> Result<ProbeResult> {
+    probe_messages(base_url, headers, deployment).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::spawn_stub;
+
+    #[test]
+    fn test_build_foundry_base_url() {
+        assert_eq!(
+            build_foundry_base_url("https://res.services.ai.azure.com/"),
+            "https://res.services.ai.azure.com/anthropic"
+        );
+        assert_eq!(
+            build_foundry_base_url("https://res.services.ai.azure.com/anthropic"),
+            "https://res.services.ai.azure.com/anthropic"
+        );
+
+        let deployments = HashMap::from([(
+            "claude-sonnet-4-5-20250929".to_string(),
+            "sonnet-prod".to_string(),
+        )]);
+        assert_eq!(
+            resolve_deployment("claude-sonnet-4-5-20250929", &deployments).unwrap(),
+            "sonnet-prod"
+        );
+        assert!(resolve_deployment("claude-opus-4-5-20251101", &deployments).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_entra_token_and_probe_against_stub() {
+        let (authority, captured) = spawn_stub(
+            200,
+            "application/json",
+            r#"{"access_token":"entra-token","expires_in":3600,"token_type":"Bearer"}"#,
+        )
+        .await;
+        let token = fetch_entra_token(Some(&authority), "tenant-1", "client-1", "secret")
+            .await
+            .unwrap();
+        assert_eq!(token.access_token, "entra-token");
+        let request = captured.await.unwrap();
+        assert_eq!(request.path, "/tenant-1/oauth2/v2.0/token");
+        assert!(request.body.contains("grant_type=client_credentials"));
+
+        let (endpoint, captured) = spawn_stub(
+            200,
+            "application/json",
+            r#"{"type":"message","content":[]}"#,
+        )
+        .await;
+        let headers = build_foundry_headers(None, Some(&token.access_token)).unwrap();
+        let result = probe_foundry(&build_foundry_base_url(&endpoint), &headers, "sonnet-prod")
+            .await
+            .unwrap();
+        assert!(result.valid);
+        assert_eq!(result.status, 200);
+
+        let request = captured.await.unwrap();
+        assert_eq!(request.path, "/anthropic/v1/messages");
+        assert_eq!(request.header("authorization"), Some("Bearer entra-token"));
+        assert!(request.body.contains("\"model\":\"sonnet-prod\""));
+    }
+}
@@ -1,7 +1,11 @@
 //! 认证模块
 //!
-//! 支持多种认证方式：OAuth、Claude Code、Console、Setup Token、Bedrock、CCR
+//! 支持多种认证方式：OAuth、Claude Code、Console、Setup Token、Bedrock、Vertex AI、Foundry、CCR
 
-pub mod oauth;
+pub mod aws_credentials;
 pub mod bedrock;
 pub mod ccr;
+pub mod foundry;
+pub mod oauth;
+pub mod probe;
+pub mod vertex;
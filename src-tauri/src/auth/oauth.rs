@@ -2,7 +2,9 @@
 //!
 //! 实现 Claude OAuth 2.0 + PKCE 认证流程
 
+use crate::auth::probe::{probe_client, send_probe, ProbeResult};
 use crate::credentials::{OAuthParams, OAuthTokens};
+use crate::error::PluginError;
 use anyhow::Result;
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chrono::{Duration, Utc};
@@ -20,6 +22,13 @@ pub const CLAUDE_REDIRECT_URI: &str = "https://console.anthropic.com/oauth/code/
 pub const CLAUDE_SCOPES: &str = "org:create_api_key user:profile user:inference";
 pub const CLAUDE_SCOPES_SETUP: &str = "user:inference";
 
+/// Anthropic API 地址
+pub const ANTHROPIC_API_BASE: &str = "https://api.anthropic.com";
+/// OAuth Token 访问 API 所需的 beta 标记
+pub const OAUTH_BETA: &str = "oauth-2025-04-20";
+/// 探测推理时默认使用的模型（可通过 config.json 的 `health_check.probe_model` 覆盖）
+pub const PROBE_MODEL: &str = "claude-haiku-4-5-20251001";
+
 /// Token 响应
 #[derive(Debug, Deserialize)]
 struct TokenResponse {
@@ -100,7 +109,10 @@ pub async fn exchange_authorization_code(
         .timeout(std::time::Duration::from_secs(60))
         .build()?;
 
-    debug!("交换授权码: code={}", &authorization_code[..20.min(authorization_code.len())]);
+    debug!(
+        "交换授权码: code={}",
+        &authorization_code[..20.min(authorization_code.len())]
+    );
 
     let response = client
         .post(CLAUDE_TOKEN_URL)
@@ -119,7 +131,7 @@ pub async fn exchange_authorization_code(
     let status = response.status();
     if !status.is_success() {
         let body = response.text().await.unwrap_or_default();
-        anyhow::bail!("Token 交换失败: {} - {}", status, body);
+        return Err(PluginError::upstream("Token 交换失败", status, body).into());
     }
 
     let token_response: TokenResponse = response.json().await?;
@@ -164,7 +176,7 @@ pub async fn oauth_with_cookie(session_key: &str, is_setup_token: bool) -> Resul
     let status = orgs_response.status();
     if !status.is_success() {
         let body = orgs_response.text().await.unwrap_or_default();
-        anyhow::bail!("获取组织信息失败: {} - {}", status, body);
+        return Err(PluginError::upstream("获取组织信息失败", status, body).into());
     }
 
     let organizations: Vec<Organization> = orgs_response.json().await?;
@@ -173,7 +185,7 @@ pub async fn oauth_with_cookie(session_key: &str, is_setup_token: bool) -> Resul
     let _org = organizations
         .iter()
         .find(|o| o.capabilities.contains(&"chat".to_string()))
-        .ok_or_else(|| anyhow::anyhow!("没有找到具有 chat 能力的组织"))?;
+        .ok_or_else(|| PluginError::invalid_config("没有找到具有 chat 能力的组织"))?;
 
     debug!("找到有效组织: {}", _org.name);
 
@@ -196,7 +208,9 @@ pub async fn oauth_with_cookie(session_key: &str, is_setup_token: bool) -> Resul
         .headers()
         .get("location")
         .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| anyhow::anyhow!("未收到重定向响应"))?;
+        .ok_or_else(|| {
+            PluginError::upstream("未收到重定向响应", auth_response.status(), String::new())
+        })?;
 
     let code = extract_code_from_url(callback_url)?;
 
@@ -213,7 +227,7 @@ fn extract_code_from_url(url: &str) -> Result<String> {
         .query_pairs()
         .find(|(key, _)| key == "code")
         .map(|(_, value)| value.to_string())
-        .ok_or_else(|| anyhow::anyhow!("URL 中没有找到授权码"))?;
+        .ok_or_else(|| PluginError::invalid_params("URL 中没有找到授权码"))?;
     Ok(code)
 }
 
@@ -240,7 +254,7 @@ pub async fn refresh_oauth_token(refresh_token: &str) -> Result<OAuthTokens> {
     let status = response.status();
     if !status.is_success() {
         let body = response.text().await.unwrap_or_default();
-        anyhow::bail!("Token 刷新失败: {} - {}", status, body);
+        return Err(PluginError::refresh_failed("Token 刷新失败", status.as_u16(), body).into());
     }
 
     let token_response: TokenResponse = response.json().await?;
@@ -259,6 +273,46 @@ pub async fn refresh_oauth_token(refresh_token: &str) -> Result<OAuthTokens> {
     })
 }
 
+/// OAuth Token 调用推理接口的请求头
+pub fn oauth_inference_headers(access_token: &str) -> Vec<(&'static str, String)> {
+    vec![
+        ("Authorization", format!("Bearer {}", access_token)),
+        ("anthropic-version", "2023-06-01".to_string()),
+        ("anthropic-beta", OAUTH_BETA.to_string()),
+        ("Content-Type", "application/json".to_string()),
+    ]
+}
+
+/// 读取 OAuth 账户资料（需要 user:profile 权限）
+pub async fn probe_oauth_profile(api_base: &str, access_token: &str) -> Result<ProbeResult> {
+    debug!("探测 OAuth 账户资料");
+
+    let request = probe_client()?
+        .get(format!(
+            "{}/api/oauth/profile",
+            api_base.trim_end_matches('/')
+        ))
+        .header("Authorization", format!("Bearer {}", access_token))
+        .header("anthropic-beta", OAUTH_BETA);
+    send_probe(request).await
+}
+
+/// 从账户资料中提取账户信息
+pub fn profile_account_info(profile: &serde_json::Value) -> serde_json::Value {
+    let account = &profile["account"];
+    let organization = &profile["organization"];
+    serde_json::json!({
+        "email": account["email"].as_str().or_else(|| account["email_address"].as_str()),
+        "account_uuid": account["uuid"],
+        "has_claude_pro": account["has_claude_pro"],
+        "has_claude_max": account["has_claude_max"],
+        "organization_uuid": organization["uuid"],
+        "organization_name": organization["name"],
+        "organization_type": organization["organization_type"],
+        "rate_limit_tier": organization["rate_limit_tier"],
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -279,4 +333,26 @@ mod tests {
         assert!(params.auth_url.contains("user%3Ainference"));
         assert!(!params.auth_url.contains("org%3Acreate_api_key"));
     }
+
+    #[tokio::test]
+    async fn test_probe_oauth_profile_against_stub() {
+        let (url, captured) = crate::test_support::spawn_stub(
+            200,
+            "application/json",
+            r#"{"account":{"uuid":"acc-1","email":"dev@example.com","has_claude_max":true},"organization":{"uuid":"org-1","name":"Dev Org"}}"#,
+        )
+        .await;
+
+        let probe = probe_oauth_profile(&url, "sk-ant-oat01-token")
+            .await
+            .unwrap();
+        assert!(probe.valid);
+        let info = profile_account_info(probe.body.as_ref().unwrap());
+        assert_eq!(info["email"], "dev@example.com");
+        assert_eq!(info["organization_name"], "Dev Org");
+
+        let request = captured.await.unwrap();
+        assert_eq!(request.path, "/api/oauth/profile");
+        assert_eq!(request.header("anthropic-beta"), Some(OAUTH_BETA));
+    }
 }
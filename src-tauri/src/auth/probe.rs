@@ -0,0 +1,132 @@
+//! 凭证探测
+//!
+//! 各认证方式共用的探测请求与结果结构
+
+use anyhow::Result;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+
+/// 探测结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeResult {
+    /// 凭证是否可用
+    pub valid: bool,
+    /// HTTP 状态码
+    pub status: u16,
+    /// 耗时（毫秒）
+    pub latency_ms: u64,
+    /// 失败时的响应内容
+    #[serde(default)]
+    pub error: Option<String>,
+    /// 成功时的 JSON 响应
+    #[serde(default, skip_serializing)]
+    pub body: Option<serde_json::Value>,
+}
+
+impl ProbeResult {
+    /// 写入 ValidationResult.details（键名带前缀，如 `profile_status`）
+    pub fn write_details(
+        &self,
+        prefix: &str,
+        details: &mut std::collections::HashMap<String, serde_json::Value>,
+    ) {
+        let key = |name: &str| {
+            if prefix.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}_{}", prefix, name)
+            }
+        };
+        details.insert(key("status"), serde_json::json!(self.status));
+        details.insert(key("latency_ms"), serde_json::json!(self.latency_ms));
+        if let Some(error) = &self.error {
+            details.insert(key("error"), serde_json::json!(error));
+        }
+    }
+}
+
+/// 探测用 HTTP 客户端
+pub fn probe_client() -> Result<Client> {
+    Ok(Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(10))
+        .timeout(std::time::Duration::from_secs(30))
+        .build()?)
+}
+
+/// 发送探测请求并记录状态码与耗时
+pub async fn send_probe(request: RequestBuilder) -> Result<ProbeResult> {
+    let started = std::time::Instant::now();
+    let response = request.send().await?;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+
+    Ok(ProbeResult {
+        valid: status.is_success(),
+        status: status.as_u16(),
+        latency_ms,
+        error: (!status.is_success()).then(|| text.clone()),
+        body: if status.is_success() {
+            serde_json::from_str(&text).ok()
+        } else {
+            None
+        },
+    })
+}
+
+/// 列出模型（`GET /v1/models`），不产生推理费用
+///
+/// 只有 401/403 视为凭证无效，端点不存在等其他错误不影响认证结论。
+pub async fn probe_models<K: AsRef<str>>(
+    base_url: &str,
+    headers: &[(K, String)],
+) -> Result<ProbeResult> {
+    let mut request = probe_client()?.get(format!("{}/v1/models", base_url.trim_end_matches('/')));
+    for (name, value) in headers {
+        request = request.header(name.as_ref(), value);
+    }
+    let mut probe = send_probe(request).await?;
+    probe.valid = probe.status != 401 && probe.status != 403;
+    Ok(probe)
+}
+
+/// 发送最小推理请求（max_tokens = 1）
+pub async fn probe_messages<K: AsRef<str>>(
+    base_url: &str,
+    headers: &[(K, String)],
+    model: &str,
+) -> Result<ProbeResult> {
+    let mut request = probe_client()?
+        .post(format!("{}/v1/messages", base_url.trim_end_matches('/')))
+        .json(&serde_json::json!({
+            "model": model,
+            "max_tokens": 1,
+            "messages": [{ "role": "user", "content": "ping" }]
+        }));
+    for (name, value) in headers {
+        request = request.header(name.as_ref(), value);
+    }
+    send_probe(request).await
+}
+
+/// 发送最小 Chat Completions 请求（max_tokens = 1）
+pub async fn probe_chat_completions<K: AsRef<str>>(
+    base_url: &str,
+    headers: &[(K, String)],
+    model: &str,
+) -> Result<ProbeResult> {
+    let mut request = probe_client()?
+        .post(format!(
+            "{}/v1/chat/completions",
+            base_url.trim_end_matches('/')
+        ))
+        .json(&serde_json::json!({
+            "model": model,
+            "max_tokens": 1,
+            "messages": [{ "role": "user", "content": "ping" }]
+        }));
+    for (name, value) in headers {
+        request = request.header(name.as_ref(), value);
+    }
+    send_probe(request).await
+}
@@ -0,0 +1,188 @@
+//! 插件配置
+//!
+//! 读取 `plugin/config.json` 中的 `settings`，未配置的项使用默认值。
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::RwLock;
+use tracing::info;
+
+/// 配置文件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PluginConfig {
+    #[serde(default)]
+    pub settings: Settings,
+}
+
+/// 插件设置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    #[serde(default)]
+    pub bedrock: BedrockSettings,
+    #[serde(default)]
+    pub health_check: HealthCheckSettings,
+    #[serde(default)]
+    pub pricing: PricingSettings,
+}
+
+/// Bedrock 设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BedrockSettings {
+    /// 凭证未指定区域时使用的默认区域
+    #[serde(default = "default_bedrock_region")]
+    pub default_region: String,
+    /// 模型 ID 前缀，`auto` 或留空时根据凭证区域推导
+    #[serde(default = "default_model_prefix")]
+    pub model_prefix: String,
+}
+
+impl Default for BedrockSettings {
+    fn default() -> Self {
+        Self {
+            default_region: default_bedrock_region(),
+            model_prefix: default_model_prefix(),
+        }
+    }
+}
+
+impl BedrockSettings {
+    /// 显式配置的模型前缀（`auto` 或空字符串视为未配置）
+    pub fn explicit_model_prefix(&self) -> Option<&str> {
+        match self.model_prefix.trim() {
+            "" | "auto" => None,
+            prefix => Some(prefix),
+        }
+    }
+}
+
+/// 健康检查设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheckSettings {
+    /// 是否启用后台探测
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 探测间隔（秒），同时也是熔断后的等待时间
+    #[serde(default = "default_health_interval")]
+    pub interval_seconds: u64,
+    /// 连续失败多少次后标记为不健康
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+    /// 后台探测是否发送推理请求（会产生费用）；关闭时只使用账户资料、模型列表、令牌换取等免费检查
+    #[serde(default)]
+    pub inference_probe: bool,
+    /// 推理探测使用的模型
+    #[serde(default = "default_probe_model")]
+    pub probe_model: String,
+}
+
+impl Default for HealthCheckSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_seconds: default_health_interval(),
+            unhealthy_threshold: default_unhealthy_threshold(),
+            inference_probe: false,
+            probe_model: default_probe_model(),
+        }
+    }
+}
+
+/// 价格设置（覆盖内置价格表，键为模型 ID 前缀）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PricingSettings {
+    /// 对所有后端生效的模型价格
+    #[serde(default)]
+    pub models: HashMap<String, ModelPrice>,
+    /// 按后端（anthropic / bedrock / vertex）覆盖的模型价格，优先于 `models`
+    #[serde(default)]
+    pub backends: HashMap<String, HashMap<String, ModelPrice>>,
+}
+
+/// 模型价格（美元 / 百万 Token），缓存价格未配置时按输入价格的倍数计算
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    /// 输入
+    pub input: f64,
+    /// 输出
+    pub output: f64,
+    /// 缓存写入（5 分钟，默认输入价格的 1.25 倍）
+    #[serde(default)]
+    pub cache_write: Option<f64>,
+    /// 缓存写入（1 小时，默认输入价格的 2 倍）
+    #[serde(default)]
+    pub cache_write_1h: Option<f64>,
+    /// 缓存读取（默认输入价格的 0.1 倍）
+    #[serde(default)]
+    pub cache_read: Option<f64>,
+}
+
+impl ModelPrice {
+    pub const fn new(input: f64, output: f64) -> Self {
+        Self {
+            input,
+            output,
+            cache_write: None,
+            cache_write_1h: None,
+            cache_read: None,
+        }
+    }
+
+    pub fn cache_write(&self) -> f64 {
+        self.cache_write.unwrap_or(self.input * 1.25)
+    }
+
+    pub fn cache_write_1h(&self) -> f64 {
+        self.cache_write_1h.unwrap_or(self.input * 2.0)
+    }
+
+    pub fn cache_read(&self) -> f64 {
+        self.cache_read.unwrap_or(self.input * 0.1)
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_health_interval() -> u64 {
+    300
+}
+
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+
+fn default_probe_model() -> String {
+    crate::auth::oauth::PROBE_MODEL.to_string()
+}
+
+fn default_bedrock_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_model_prefix() -> String {
+    "auto".to_string()
+}
+
+lazy_static::lazy_static! {
+    static ref SETTINGS: RwLock<Settings> = RwLock::new(Settings::default());
+}
+
+/// 从配置文件加载设置
+pub fn load(path: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("读取配置文件失败: {} - {}", path.display(), e))?;
+    let config: PluginConfig = serde_json::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("配置文件格式错误: {} - {}", path.display(), e))?;
+
+    *SETTINGS.write().unwrap() = config.settings;
+    info!("已加载配置文件: {}", path.display());
+    Ok(())
+}
+
+/// 当前设置
+pub fn settings() -> Settings {
+    SETTINGS.read().unwrap().clone()
+}
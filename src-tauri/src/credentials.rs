@@ -1,13 +1,14 @@
 //! 凭证数据结构
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// 认证类型
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum AuthType {
     /// 标准 OAuth 2.0 + PKCE
+    #[default]
     OAuth,
     /// Claude Code CLI 认证
     ClaudeCode,
@@ -17,16 +18,16 @@ pub enum AuthType {
     SetupToken,
     /// AWS Bedrock Claude
     Bedrock,
+    /// AWS Bedrock API Key（Bearer 认证）
+    BedrockApiKey,
+    /// Google Vertex AI Claude
+    Vertex,
+    /// Microsoft Foundry (Azure) Claude
+    Foundry,
     /// 第三方中转服务
     Ccr,
 }
 
-impl Default for AuthType {
-    fn default() -> Self {
-        Self::OAuth
-    }
-}
-
 impl std::fmt::Display for AuthType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -35,11 +36,21 @@ impl std::fmt::Display for AuthType {
             AuthType::Console => write!(f, "console"),
             AuthType::SetupToken => write!(f, "setup_token"),
             AuthType::Bedrock => write!(f, "bedrock"),
+            AuthType::BedrockApiKey => write!(f, "bedrock_api_key"),
+            AuthType::Vertex => write!(f, "vertex"),
+            AuthType::Foundry => write!(f, "foundry"),
             AuthType::Ccr => write!(f, "ccr"),
         }
     }
 }
 
+impl AuthType {
+    /// 是否为 Bedrock 后端（SigV4 或 API Key）
+    pub fn is_bedrock(&self) -> bool {
+        matches!(self, AuthType::Bedrock | AuthType::BedrockApiKey)
+    }
+}
+
 /// Claude OAuth 凭证
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -60,9 +71,18 @@ pub struct ClaudeCredentials {
     pub expire: Option<String>,
     /// 最后刷新时间
     pub last_refresh: Option<String>,
-    /// 是否健康
+    /// 是否健康（健康状态为 unhealthy / probing 时为 false）
     #[serde(default = "default_true")]
     pub is_healthy: bool,
+    /// 健康状态
+    #[serde(skip)]
+    pub health_state: HealthState,
+    /// 连续失败次数
+    #[serde(skip)]
+    pub consecutive_failures: u32,
+    /// 下次允许探测（半开试探）的时间
+    #[serde(skip)]
+    pub next_probe_at: Option<chrono::DateTime<chrono::Utc>>,
     /// 使用次数
     #[serde(default)]
     pub usage_count: u64,
@@ -72,6 +92,31 @@ pub struct ClaudeCredentials {
     /// 最后错误信息
     #[serde(default)]
     pub last_error: Option<String>,
+    /// 自动停用信息（终止性账户错误触发，只能通过 enable_credential 恢复）
+    #[serde(skip)]
+    pub disabled: Option<DisabledState>,
+    /// 按模型停用信息（Token 无权调用的模型，只能通过 enable_credential 恢复）
+    #[serde(skip)]
+    pub blocked_models: HashMap<String, DisabledState>,
+    /// 最近一次响应头报告的限流额度
+    #[serde(skip)]
+    pub rate_limits: RateLimitState,
+    /// 订阅额度窗口（OAuth 凭证，来自 `anthropic-ratelimit-unified-*` 响应头）
+    #[serde(skip)]
+    pub unified_limits: UnifiedRateLimit,
+    /// 按模型累计的用量与估算费用
+    #[serde(skip)]
+    pub usage_totals: HashMap<String, UsageTotals>,
+    /// 通过 discover_models 发现的模型（None 表示尚未发现，不做限制；Bedrock 为区域提供的模型，
+    /// 不代表账户已开通访问权限；CCR 为中转服务报告的模型名）
+    #[serde(skip)]
+    pub available_models: Option<Vec<String>>,
+    /// 最近一次模型发现时间 (RFC3339 格式)
+    #[serde(skip)]
+    pub models_discovered_at: Option<String>,
+    /// 模型发现得到的 Bedrock 基础模型 ID（Anthropic 模型名 -> 不含区域前缀的模型 ID）
+    #[serde(skip)]
+    pub discovered_model_ids: HashMap<String, String>,
 
     // Bedrock 特有字段
     /// AWS Access Key ID
@@ -83,12 +128,104 @@ pub struct ClaudeCredentials {
     /// AWS Region
     #[serde(default = "default_region")]
     pub region: Option<String>,
+    /// AWS 凭证来源（未设置时直接使用 access_key_id / secret_access_key）
+    #[serde(default)]
+    pub credential_source: Option<AwsCredentialSource>,
+    /// STS 端点（默认 https://sts.{region}.amazonaws.com）
+    #[serde(default)]
+    pub sts_endpoint: Option<String>,
+    /// Bedrock 多区域故障转移的有序区域列表（为空时仅使用 region）
+    #[serde(default)]
+    pub regions: Vec<String>,
+    /// 各区域的限流、冷却状态与模型发现结果
+    #[serde(skip)]
+    pub region_states: HashMap<String, RegionState>,
+    /// Bedrock 模型 ID 前缀（如 `eu.anthropic.`，默认根据区域推导）
+    #[serde(default)]
+    pub model_prefix: Option<String>,
+    /// 模型映射（Bedrock: 模型名 -> 模型 ID / 推理配置文件 ARN / 预置吞吐量 ARN；
+    /// Vertex: 模型名 -> Vertex 模型 ID；CCR: 模型名 -> 中转服务别名）
+    #[serde(default)]
+    pub model_map: HashMap<String, String>,
+    /// Bedrock Guardrail 标识符（ID 或 ARN）
+    #[serde(default)]
+    pub guardrail_identifier: Option<String>,
+    /// Bedrock Guardrail 版本（如 `1` 或 `DRAFT`）
+    #[serde(default)]
+    pub guardrail_version: Option<String>,
+    /// Bedrock Guardrail 追踪设置（ENABLED / DISABLED / ENABLED_FULL）
+    #[serde(default)]
+    pub guardrail_trace: Option<String>,
+
+    // Vertex AI 特有字段
+    /// 服务账号 JSON（对象或 JSON 字符串）
+    #[serde(default)]
+    pub service_account_json: Option<serde_json::Value>,
+    /// GCP 项目 ID（默认取服务账号中的 project_id）
+    #[serde(default)]
+    pub project_id: Option<String>,
+    /// Vertex 区域（如 us-east5、europe-west1 或 global）
+    #[serde(default)]
+    pub vertex_region: Option<String>,
+    /// Token 端点（默认取服务账号中的 token_uri）
+    #[serde(default)]
+    pub token_uri: Option<String>,
 
-    // CCR 特有字段
+    // Microsoft Foundry 特有字段
+    /// Foundry 资源端点（如 https://<resource>.services.ai.azure.com）
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// 部署映射（模型名 -> 部署名，为空时直接使用模型名）
+    #[serde(default)]
+    pub deployments: HashMap<String, String>,
+    /// Entra ID 租户 ID
+    #[serde(default)]
+    pub tenant_id: Option<String>,
+    /// Entra ID 客户端 ID
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// Entra ID 客户端密钥
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    /// Entra ID 认证端点（默认 https://login.microsoftonline.com）
+    #[serde(default)]
+    pub authority_host: Option<String>,
+
+    // CCR / Bedrock API Key / Foundry 特有字段
     /// API Key
     pub api_key: Option<String>,
     /// Base URL
     pub base_url: Option<String>,
+    /// CCR 多个 API Key（非空时轮换使用，忽略 api_key）
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    /// CCR 各 API Key 的健康、冷却与使用状态（键为 Key ID）
+    #[serde(skip)]
+    pub api_key_states: HashMap<String, ApiKeyState>,
+    /// CCR API Key 轮换策略
+    #[serde(default)]
+    pub key_rotation: KeyRotation,
+    /// 轮询游标
+    #[serde(skip)]
+    pub key_cursor: usize,
+    /// CCR 中转服务协议（openai 时请求与响应在 Anthropic 与 Chat Completions 之间转换）
+    #[serde(default)]
+    pub upstream_protocol: UpstreamProtocol,
+    /// CCR 认证头模板（如 `Authorization: Bearer {api_key}`，默认 `x-api-key: {api_key}`）
+    #[serde(default)]
+    pub auth_header: Option<String>,
+    /// CCR 额外的静态请求头（如租户 ID）
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
+    /// CCR 允许的模型（支持末尾 `*` 通配，为空时不限制）
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    /// CCR 拒绝的模型（支持末尾 `*` 通配，优先于允许列表）
+    #[serde(default)]
+    pub denied_models: Vec<String>,
+    /// CCR 推理探测使用的模型（未配置时选择第一个允许调用的模型）
+    #[serde(default)]
+    pub probe_model: Option<String>,
 
     // Console 特有字段
     /// Organization ID
@@ -116,21 +253,389 @@ impl Default for ClaudeCredentials {
             expire: None,
             last_refresh: None,
             is_healthy: true,
+            health_state: HealthState::Healthy,
+            consecutive_failures: 0,
+            next_probe_at: None,
             usage_count: 0,
             error_count: 0,
             last_error: None,
+            disabled: None,
+            blocked_models: HashMap::new(),
+            rate_limits: RateLimitState::default(),
+            unified_limits: UnifiedRateLimit::default(),
+            usage_totals: HashMap::new(),
+            available_models: None,
+            models_discovered_at: None,
+            discovered_model_ids: HashMap::new(),
             access_key_id: None,
             secret_access_key: None,
             session_token: None,
             region: default_region(),
+            credential_source: None,
+            sts_endpoint: None,
+            regions: Vec::new(),
+            region_states: HashMap::new(),
+            model_prefix: None,
+            model_map: HashMap::new(),
+            guardrail_identifier: None,
+            guardrail_version: None,
+            guardrail_trace: None,
+            service_account_json: None,
+            project_id: None,
+            vertex_region: None,
+            token_uri: None,
+            endpoint: None,
+            deployments: HashMap::new(),
+            tenant_id: None,
+            client_id: None,
+            client_secret: None,
+            authority_host: None,
             api_key: None,
             base_url: None,
+            api_keys: Vec::new(),
+            api_key_states: HashMap::new(),
+            key_rotation: KeyRotation::RoundRobin,
+            key_cursor: 0,
+            upstream_protocol: UpstreamProtocol::Anthropic,
+            auth_header: None,
+            extra_headers: HashMap::new(),
+            allowed_models: Vec::new(),
+            denied_models: Vec::new(),
+            probe_model: None,
             organization_id: None,
             organization_name: None,
         }
     }
 }
 
+/// AWS 凭证来源
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AwsCredentialSource {
+    /// 使用凭证中的 access_key_id / secret_access_key
+    Static,
+    /// 读取 AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY / AWS_SESSION_TOKEN 环境变量
+    Environment,
+    /// ~/.aws/credentials 与 ~/.aws/config 中的命名 profile
+    Profile {
+        /// Profile 名称（默认 AWS_PROFILE 或 default）
+        #[serde(default)]
+        profile: Option<String>,
+    },
+    /// 执行 credential_process 命令
+    Process {
+        /// 命令行
+        command: String,
+    },
+    /// Web Identity Token 文件（STS AssumeRoleWithWebIdentity）
+    WebIdentity {
+        /// 角色 ARN
+        role_arn: String,
+        /// Token 文件路径
+        token_file: String,
+        /// 会话名称
+        #[serde(default)]
+        session_name: Option<String>,
+    },
+    /// STS AssumeRole
+    AssumeRole {
+        /// 角色 ARN
+        role_arn: String,
+        /// External ID
+        #[serde(default)]
+        external_id: Option<String>,
+        /// 会话名称
+        #[serde(default)]
+        session_name: Option<String>,
+        /// 会话时长（秒）
+        #[serde(default)]
+        duration_seconds: Option<u32>,
+        /// 调用 AssumeRole 时使用的基础凭证来源（默认 Static）
+        #[serde(default)]
+        source: Option<Box<AwsCredentialSource>>,
+    },
+}
+
+/// 凭证健康状态
+///
+/// 连续失败未达阈值时为 degraded，达到阈值后为 unhealthy（熔断）；
+/// 到达探测时间后进入 probing（半开），探测或试探请求成功即恢复 healthy。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthState {
+    #[default]
+    Healthy,
+    Degraded,
+    Unhealthy,
+    Probing,
+}
+
+impl std::fmt::Display for HealthState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HealthState::Healthy => write!(f, "healthy"),
+            HealthState::Degraded => write!(f, "degraded"),
+            HealthState::Unhealthy => write!(f, "unhealthy"),
+            HealthState::Probing => write!(f, "probing"),
+        }
+    }
+}
+
+/// 凭证停用原因（账户级的终止性错误，重试或等待无法恢复）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisabledReason {
+    /// 组织已被停用
+    OrganizationDisabled,
+    /// 账户已被封禁
+    AccountSuspended,
+    /// 余额或计费额度耗尽
+    BillingExhausted,
+    /// Token 缺少所需的权限范围（如 Setup Token）
+    MissingScope,
+    /// Token 无权调用请求的模型（只停用该模型）
+    ModelNotAllowed,
+}
+
+impl std::fmt::Display for DisabledReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisabledReason::OrganizationDisabled => write!(f, "organization_disabled"),
+            DisabledReason::AccountSuspended => write!(f, "account_suspended"),
+            DisabledReason::BillingExhausted => write!(f, "billing_exhausted"),
+            DisabledReason::MissingScope => write!(f, "missing_scope"),
+            DisabledReason::ModelNotAllowed => write!(f, "model_not_allowed"),
+        }
+    }
+}
+
+/// 凭证停用信息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisabledState {
+    /// 停用原因
+    pub reason: DisabledReason,
+    /// 上游错误信息
+    #[serde(default)]
+    pub message: Option<String>,
+    /// 停用时间
+    pub disabled_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// 单个限流维度的额度
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimitQuota {
+    /// 额度上限
+    #[serde(default)]
+    pub limit: Option<u64>,
+    /// 剩余额度
+    #[serde(default)]
+    pub remaining: Option<u64>,
+    /// 额度重置时间
+    #[serde(default)]
+    pub reset_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// 凭证的限流额度（来自 `anthropic-ratelimit-*` 响应头，没有新响应头时按 usage 扣减）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimitState {
+    /// 请求数
+    #[serde(default)]
+    pub requests: Option<RateLimitQuota>,
+    /// 输入 Token
+    #[serde(default)]
+    pub input_tokens: Option<RateLimitQuota>,
+    /// 输出 Token
+    #[serde(default)]
+    pub output_tokens: Option<RateLimitQuota>,
+    /// Token 总量（最严格的限制）
+    #[serde(default)]
+    pub tokens: Option<RateLimitQuota>,
+    /// 最近一次更新时间
+    #[serde(default)]
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// 订阅额度窗口
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageWindow {
+    /// 已用比例（0.0 - 1.0）
+    #[serde(default)]
+    pub utilization: Option<f64>,
+    /// 窗口状态（allowed / allowed_warning / rejected）
+    #[serde(default)]
+    pub status: Option<String>,
+    /// 窗口重置时间
+    #[serde(default)]
+    pub reset_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// 按当前消耗速度预计用尽的时间（仅在预计早于重置时间时有值）
+    #[serde(default)]
+    pub projected_exhaustion_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Pro / Max 订阅的统一限流状态（5 小时与每周滚动窗口）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UnifiedRateLimit {
+    /// 整体状态
+    #[serde(default)]
+    pub status: Option<String>,
+    /// 整体状态为 rejected 时的恢复时间
+    #[serde(default)]
+    pub reset_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// 当前起决定作用的窗口（five_hour / seven_day）
+    #[serde(default)]
+    pub representative_claim: Option<String>,
+    /// 5 小时窗口
+    #[serde(default)]
+    pub five_hour: Option<UsageWindow>,
+    /// 每周窗口
+    #[serde(default)]
+    pub seven_day: Option<UsageWindow>,
+    /// 最近一次更新时间
+    #[serde(default)]
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// 累计用量与估算费用
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    /// 请求数
+    #[serde(default)]
+    pub requests: u64,
+    /// 输入 Token
+    #[serde(default)]
+    pub input_tokens: u64,
+    /// 输出 Token
+    #[serde(default)]
+    pub output_tokens: u64,
+    /// 缓存写入 Token
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+    /// 缓存读取 Token
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+    /// 估算费用（美元）
+    #[serde(default)]
+    pub cost_usd: f64,
+    /// 没有匹配到价格、未计入费用的请求数
+    #[serde(default)]
+    pub unpriced_requests: u64,
+}
+
+impl UsageTotals {
+    /// 累加另一份统计
+    pub fn add(&mut self, other: &UsageTotals) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+        self.cost_usd += other.cost_usd;
+        self.unpriced_requests += other.unpriced_requests;
+    }
+}
+
+/// 单个凭证的用量统计
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialUsage {
+    /// 凭证 ID
+    pub id: String,
+    /// 凭证名称
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 计价后端（anthropic / bedrock / vertex）
+    pub backend: String,
+    /// 合计
+    pub total: UsageTotals,
+    /// 按模型统计
+    pub models: BTreeMap<String, UsageTotals>,
+}
+
+/// 用量统计报告
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReport {
+    /// 合计
+    pub total: UsageTotals,
+    /// 按凭证统计
+    pub credentials: Vec<CredentialUsage>,
+    /// 按模型统计（汇总所有凭证）
+    pub models: BTreeMap<String, UsageTotals>,
+}
+
+/// Bedrock 区域状态
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegionState {
+    /// 连续限流次数
+    #[serde(default)]
+    pub consecutive_throttles: u32,
+    /// 累计限流次数
+    #[serde(default)]
+    pub total_throttles: u64,
+    /// 冷却截止时间
+    #[serde(default)]
+    pub cooldown_until: Option<chrono::DateTime<chrono::Utc>>,
+    /// 模型发现得到的该区域提供的模型（None 表示尚未发现，不做限制）
+    #[serde(default)]
+    pub available_models: Option<Vec<String>>,
+}
+
+/// 中转服务协议
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamProtocol {
+    /// Anthropic Messages
+    #[default]
+    Anthropic,
+    /// OpenAI Chat Completions
+    Openai,
+}
+
+/// API Key 轮换策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyRotation {
+    /// 依次轮询
+    #[default]
+    RoundRobin,
+    /// 选择使用次数最少的 Key
+    LeastUsed,
+}
+
+/// 单个 API Key 的状态
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKeyState {
+    /// 是否健康
+    #[serde(default = "default_true")]
+    pub is_healthy: bool,
+    /// 使用次数
+    #[serde(default)]
+    pub usage_count: u64,
+    /// 错误次数
+    #[serde(default)]
+    pub error_count: u64,
+    /// 连续失败次数
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    /// 冷却截止时间
+    #[serde(default)]
+    pub cooldown_until: Option<chrono::DateTime<chrono::Utc>>,
+    /// 最后错误信息
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+impl Default for ApiKeyState {
+    fn default() -> Self {
+        Self {
+            is_healthy: true,
+            usage_count: 0,
+            error_count: 0,
+            consecutive_failures: 0,
+            cooldown_until: None,
+            last_error: None,
+        }
+    }
+}
+
 /// 获取的凭证
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AcquiredCredential {
@@ -152,6 +657,46 @@ pub struct AcquiredCredential {
     pub metadata: HashMap<String, serde_json::Value>,
 }
 
+/// 凭证列表项（不包含密钥等敏感字段）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialSummary {
+    /// 凭证 ID
+    pub id: String,
+    /// 凭证名称
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 认证方式
+    pub auth_type: String,
+    /// 邮箱
+    #[serde(default)]
+    pub email: Option<String>,
+    /// 健康状态
+    pub health_state: HealthState,
+    /// 是否健康
+    pub is_healthy: bool,
+    /// 使用次数
+    pub usage_count: u64,
+    /// 错误次数
+    pub error_count: u64,
+    /// 最后错误信息
+    #[serde(default)]
+    pub last_error: Option<String>,
+    /// 停用信息（为空表示已启用）
+    #[serde(default)]
+    pub disabled: Option<DisabledState>,
+    /// 按模型停用信息（模型名 -> 停用信息）
+    #[serde(default)]
+    pub blocked_models: HashMap<String, DisabledState>,
+    /// 限流额度
+    #[serde(default)]
+    pub rate_limits: RateLimitState,
+    /// 订阅额度窗口
+    #[serde(default)]
+    pub unified_limits: UnifiedRateLimit,
+    /// 剩余额度比例（限流额度与订阅窗口中的最小值，0.0 - 1.0，未知时为 1.0）
+    pub headroom: f64,
+}
+
 /// 凭证验证结果
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidationResult {
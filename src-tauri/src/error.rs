@@ -0,0 +1,325 @@
+//! 错误类型
+//!
+//! 内部仍通过 anyhow 传递错误，需要区分的失败使用 [`PluginError`]；
+//! JSON-RPC 层从错误链中取回它，映射为独立的错误码，并在 `data` 中附带稳定的 `code` 与上下文。
+
+use serde_json::{json, Value};
+
+/// 未分类错误的 JSON-RPC 错误码
+pub const INTERNAL_ERROR_CODE: i32 = -32000;
+
+/// 插件错误
+#[derive(Debug, thiserror::Error)]
+pub enum PluginError {
+    /// 不支持的模型（或当前凭证没有该模型的映射、部署、权限）
+    #[error("{}", .reason.clone().unwrap_or_else(|| format!("不支持的模型: {}", .model)))]
+    UnsupportedModel {
+        model: String,
+        reason: Option<String>,
+    },
+
+    /// 没有可用的健康凭证
+    #[error("没有可用的健康凭证{}", .reason.as_deref().map(|r| format!(": {}", r)).unwrap_or_default())]
+    NoHealthyCredential {
+        model: String,
+        reason: Option<String>,
+        /// 最早可能恢复的等待时间（秒）
+        retry_after: Option<u64>,
+    },
+
+    /// 凭证不存在
+    #[error("凭证不存在: {credential_id}")]
+    CredentialNotFound { credential_id: String },
+
+    /// CCR 凭证的所有 API Key 都在冷却中
+    #[error("中转服务 {name} 的所有 API Key 都在冷却中")]
+    KeysCoolingDown {
+        credential_id: String,
+        name: String,
+        retry_after: Option<u64>,
+    },
+
+    /// refresh_token 已被撤销或失效，需要重新授权
+    #[error("refresh_token 已失效，请重新授权: {reason}")]
+    RefreshRevoked {
+        credential_id: Option<String>,
+        reason: String,
+    },
+
+    /// 该认证方式不支持（或不需要）刷新
+    #[error("{reason}")]
+    RefreshNotSupported {
+        credential_id: Option<String>,
+        auth_type: String,
+        reason: String,
+    },
+
+    /// 令牌刷新或云厂商凭证换取失败（可重试，refresh_token 失效见 RefreshRevoked）
+    #[error("{reason}")]
+    RefreshFailed {
+        credential_id: Option<String>,
+        status: Option<u16>,
+        reason: String,
+    },
+
+    /// 凭证配置无效或缺少字段
+    #[error("{reason}")]
+    InvalidConfig { reason: String },
+
+    /// 请求参数无效
+    #[error("{reason}")]
+    InvalidParams { reason: String },
+
+    /// 该认证方式不支持此操作
+    #[error("{reason}")]
+    UnsupportedOperation { reason: String },
+
+    /// 上游（Anthropic、OAuth、云厂商）请求失败
+    #[error("{context}: {status} - {body}")]
+    Upstream {
+        context: String,
+        status: u16,
+        body: String,
+    },
+
+    /// 流式转换会话不存在
+    #[error("流式转换会话不存在: {stream_id}")]
+    StreamNotFound { stream_id: String },
+}
+
+impl PluginError {
+    pub fn invalid_config(reason: impl Into<String>) -> Self {
+        PluginError::InvalidConfig {
+            reason: reason.into(),
+        }
+    }
+
+    pub fn invalid_params(reason: impl Into<String>) -> Self {
+        PluginError::InvalidParams {
+            reason: reason.into(),
+        }
+    }
+
+    pub fn unsupported_model(model: &str, reason: impl Into<String>) -> Self {
+        PluginError::UnsupportedModel {
+            model: model.to_string(),
+            reason: Some(reason.into()),
+        }
+    }
+
+    pub fn refresh_error(reason: impl Into<String>) -> Self {
+        PluginError::RefreshFailed {
+            credential_id: None,
+            status: None,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn unsupported(reason: impl Into<String>) -> Self {
+        PluginError::UnsupportedOperation {
+            reason: reason.into(),
+        }
+    }
+
+    pub fn upstream(context: impl Into<String>, status: impl Into<u16>, body: String) -> Self {
+        PluginError::Upstream {
+            context: context.into(),
+            status: status.into(),
+            body,
+        }
+    }
+
+    /// 令牌刷新失败：授权服务器返回 invalid_grant 时视为 refresh_token 已失效
+    pub fn refresh_failed(context: &str, status: u16, body: String) -> Self {
+        if matches!(status, 400 | 401) && body.contains("invalid_grant") {
+            PluginError::RefreshRevoked {
+                credential_id: None,
+                reason: body,
+            }
+        } else {
+            PluginError::RefreshFailed {
+                credential_id: None,
+                status: Some(status),
+                reason: format!("{}: {} - {}", context, status, body),
+            }
+        }
+    }
+
+    pub fn refresh_not_supported(auth_type: impl ToString, reason: impl Into<String>) -> Self {
+        PluginError::RefreshNotSupported {
+            credential_id: None,
+            auth_type: auth_type.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn credential_not_found(credential_id: &str) -> Self {
+        PluginError::CredentialNotFound {
+            credential_id: credential_id.to_string(),
+        }
+    }
+
+    /// 稳定的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            PluginError::UnsupportedModel { .. } => "unsupported_model",
+            PluginError::NoHealthyCredential { .. } => "no_healthy_credential",
+            PluginError::CredentialNotFound { .. } => "credential_not_found",
+            PluginError::KeysCoolingDown { .. } => "keys_cooling_down",
+            PluginError::RefreshRevoked { .. } => "refresh_revoked",
+            PluginError::RefreshNotSupported { .. } => "refresh_not_supported",
+            PluginError::RefreshFailed { .. } => "refresh_failed",
+            PluginError::InvalidConfig { .. } => "invalid_config",
+            PluginError::InvalidParams { .. } => "invalid_params",
+            PluginError::UnsupportedOperation { .. } => "unsupported_operation",
+            PluginError::Upstream { .. } => "upstream_error",
+            PluginError::StreamNotFound { .. } => "stream_not_found",
+        }
+    }
+
+    /// JSON-RPC 错误码（-32001 起，-32000 保留给未分类错误）
+    pub fn rpc_code(&self) -> i32 {
+        match self {
+            PluginError::UnsupportedModel { .. } => -32001,
+            PluginError::NoHealthyCredential { .. } => -32002,
+            PluginError::CredentialNotFound { .. } => -32003,
+            PluginError::KeysCoolingDown { .. } => -32004,
+            PluginError::RefreshRevoked { .. } => -32005,
+            PluginError::RefreshNotSupported { .. } => -32006,
+            PluginError::InvalidConfig { .. } => -32007,
+            PluginError::UnsupportedOperation { .. } => -32008,
+            PluginError::Upstream { .. } => -32009,
+            PluginError::StreamNotFound { .. } => -32010,
+            PluginError::RefreshFailed { .. } => -32011,
+            // 与 JSON-RPC 标准的 Invalid params 一致
+            PluginError::InvalidParams { .. } => -32602,
+        }
+    }
+
+    /// JSON-RPC 错误的 `data`：错误码以及凭证 ID、重试等待时间、原因等上下文
+    pub fn data(&self) -> Value {
+        let mut data = match self {
+            PluginError::UnsupportedModel { model, reason } => {
+                json!({ "model": model, "reason": reason })
+            }
+            PluginError::NoHealthyCredential {
+                model,
+                reason,
+                retry_after,
+            } => json!({ "model": model, "reason": reason, "retry_after": retry_after }),
+            PluginError::CredentialNotFound { credential_id } => {
+                json!({ "credential_id": credential_id })
+            }
+            PluginError::KeysCoolingDown {
+                credential_id,
+                retry_after,
+                ..
+            } => json!({ "credential_id": credential_id, "retry_after": retry_after }),
+            PluginError::RefreshRevoked {
+                credential_id,
+                reason,
+            } => json!({ "credential_id": credential_id, "reason": reason }),
+            PluginError::RefreshNotSupported {
+                credential_id,
+                auth_type,
+                reason,
+            } => {
+                json!({ "credential_id": credential_id, "auth_type": auth_type, "reason": reason })
+            }
+            PluginError::RefreshFailed {
+                credential_id,
+                status,
+                reason,
+            } => json!({ "credential_id": credential_id, "status": status, "reason": reason }),
+            PluginError::InvalidConfig { reason }
+            | PluginError::InvalidParams { reason }
+            | PluginError::UnsupportedOperation { reason } => {
+                json!({ "reason": reason })
+            }
+            PluginError::Upstream { status, body, .. } => {
+                json!({ "status": status, "reason": body })
+            }
+            PluginError::StreamNotFound { stream_id } => json!({ "stream_id": stream_id }),
+        };
+        data["code"] = json!(self.code());
+        data
+    }
+
+    /// 补充凭证 ID（底层模块不知道凭证 ID 时由调用方填入）
+    pub fn with_credential(mut self, id: &str) -> Self {
+        match &mut self {
+            PluginError::RefreshRevoked { credential_id, .. }
+            | PluginError::RefreshFailed { credential_id, .. }
+            | PluginError::RefreshNotSupported { credential_id, .. } => {
+                credential_id.get_or_insert_with(|| id.to_string());
+            }
+            _ => {}
+        }
+        self
+    }
+}
+
+/// 从错误链中取回 [`PluginError`]
+pub fn find_plugin_error(error: &anyhow::Error) -> Option<&PluginError> {
+    error.chain().find_map(|e| e.downcast_ref::<PluginError>())
+}
+
+/// 为错误链中的 [`PluginError`] 补充凭证 ID
+pub fn attach_credential(error: anyhow::Error, credential_id: &str) -> anyhow::Error {
+    match error.downcast::<PluginError>() {
+        Ok(error) => error.with_credential(credential_id).into(),
+        Err(error) => error,
+    }
+}
+
+/// 转换为 JSON-RPC 错误的（code, message, data）
+pub fn to_rpc_error(error: &anyhow::Error) -> (i32, String, Value) {
+    match find_plugin_error(error) {
+        Some(plugin_error) => (
+            plugin_error.rpc_code(),
+            error.to_string(),
+            plugin_error.data(),
+        ),
+        None => (
+            INTERNAL_ERROR_CODE,
+            error.to_string(),
+            json!({ "code": "internal_error" }),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rpc_error_mapping() {
+        let error: anyhow::Error = PluginError::KeysCoolingDown {
+            credential_id: "cred-1".to_string(),
+            name: "relay".to_string(),
+            retry_after: Some(30),
+        }
+        .into();
+        let (code, message, data) = to_rpc_error(&error.context("获取凭证失败"));
+        assert_eq!(code, -32004);
+        assert_eq!(message, "获取凭证失败");
+        assert_eq!(
+            data,
+            json!({ "code": "keys_cooling_down", "credential_id": "cred-1", "retry_after": 30 })
+        );
+
+        let refresh = attach_credential(
+            PluginError::RefreshRevoked {
+                credential_id: None,
+                reason: "invalid_grant".to_string(),
+            }
+            .into(),
+            "cred-2",
+        );
+        assert_eq!(to_rpc_error(&refresh).2["credential_id"], "cred-2");
+
+        let (code, _, data) = to_rpc_error(&anyhow::anyhow!("boom"));
+        assert_eq!(code, INTERNAL_ERROR_CODE);
+        assert_eq!(data["code"], "internal_error");
+    }
+}
@@ -0,0 +1,222 @@
+//! 凭证健康检查
+//!
+//! 根据连续失败次数维护健康状态（healthy / degraded / unhealthy / probing），
+//! 并由后台任务按配置的间隔重新探测不健康的凭证。
+
+use crate::config::HealthCheckSettings;
+use crate::credentials::{ClaudeCredentials, HealthState};
+use crate::provider::{validate_credential_with, CREDENTIALS};
+use chrono::{DateTime, Duration, Utc};
+use tracing::{debug, info, warn};
+
+/// 设置健康状态，并同步 `is_healthy`
+fn set_state(credential: &mut ClaudeCredentials, state: HealthState) {
+    credential.health_state = state;
+    credential.is_healthy = matches!(state, HealthState::Healthy | HealthState::Degraded);
+}
+
+/// 记录一次成功调用或探测
+pub fn record_success(credential: &mut ClaudeCredentials) {
+    credential.consecutive_failures = 0;
+    credential.next_probe_at = None;
+    set_state(credential, HealthState::Healthy);
+}
+
+/// 记录一次凭证级失败，返回新的健康状态
+///
+/// 半开状态下的失败直接熔断；否则连续失败达到阈值后熔断，未达阈值时降级。
+pub fn record_failure(
+    credential: &mut ClaudeCredentials,
+    settings: &HealthCheckSettings,
+    now: DateTime<Utc>,
+) -> HealthState {
+    credential.consecutive_failures += 1;
+
+    let trip = credential.health_state == HealthState::Probing
+        || credential.consecutive_failures >= settings.unhealthy_threshold.max(1);
+    if trip {
+        credential.next_probe_at = Some(now + Duration::seconds(settings.interval_seconds as i64));
+        set_state(credential, HealthState::Unhealthy);
+    } else {
+        set_state(credential, HealthState::Degraded);
+    }
+    credential.health_state
+}
+
+/// 熔断凭证是否可以进入半开状态
+///
+/// 试探进行中的凭证在超时（一个探测间隔）前不会再次放行；已停用的凭证不参与探测。
+pub fn probe_due(credential: &ClaudeCredentials, now: DateTime<Utc>) -> bool {
+    credential.disabled.is_none()
+        && matches!(
+            credential.health_state,
+            HealthState::Unhealthy | HealthState::Probing
+        )
+        && credential.next_probe_at.is_none_or(|at| at <= now)
+}
+
+/// 为熔断凭证占用半开试探名额，成功时进入 probing 状态
+pub fn begin_trial(
+    credential: &mut ClaudeCredentials,
+    settings: &HealthCheckSettings,
+    now: DateTime<Utc>,
+) -> bool {
+    if !probe_due(credential, now) {
+        return false;
+    }
+    credential.next_probe_at = Some(now + Duration::seconds(settings.interval_seconds as i64));
+    set_state(credential, HealthState::Probing);
+    true
+}
+
+/// 探测所有到期的不健康凭证，返回恢复的凭证 ID
+pub async fn probe_unhealthy_credentials() -> Vec<String> {
+    let settings = crate::config::settings().health_check;
+    let now = Utc::now();
+
+    let due: Vec<String> = {
+        let mut creds = CREDENTIALS.write().await;
+        creds
+            .iter_mut()
+            .filter_map(|(id, c)| begin_trial(c, &settings, now).then(|| id.clone()))
+            .collect()
+    };
+
+    let mut restored = Vec::new();
+    for id in due {
+        let valid = match validate_credential_with(&id, true, settings.inference_probe).await {
+            Ok(result) => result.valid,
+            Err(e) => {
+                debug!("健康探测失败: {} - {}", id, e);
+                false
+            }
+        };
+
+        let mut creds = CREDENTIALS.write().await;
+        let Some(credential) = creds.get_mut(&id) else {
+            continue;
+        };
+        if valid {
+            record_success(credential);
+            info!("凭证探测成功，已恢复: {}", id);
+            restored.push(id);
+        } else {
+            record_failure(credential, &settings, Utc::now());
+            warn!("凭证探测失败，保持不健康: {}", id);
+        }
+    }
+
+    restored
+}
+
+/// 启动后台健康检查任务
+pub fn spawn_health_checker() {
+    let settings = crate::config::settings().health_check;
+    if !settings.enabled {
+        info!("健康检查已禁用");
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            settings.interval_seconds.max(1),
+        ));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            probe_unhealthy_credentials().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failures_degrade_then_trip() {
+        let settings = HealthCheckSettings {
+            enabled: true,
+            interval_seconds: 60,
+            unhealthy_threshold: 3,
+            ..Default::default()
+        };
+        let now = Utc::now();
+        let mut credential = ClaudeCredentials::default();
+
+        assert_eq!(
+            record_failure(&mut credential, &settings, now),
+            HealthState::Degraded
+        );
+        assert!(credential.is_healthy);
+        record_failure(&mut credential, &settings, now);
+        assert_eq!(
+            record_failure(&mut credential, &settings, now),
+            HealthState::Unhealthy
+        );
+        assert!(!credential.is_healthy);
+
+        // 熔断期间不允许试探
+        assert!(!begin_trial(&mut credential, &settings, now));
+
+        // 到期后只放行一个试探请求，试探失败立即重新熔断
+        let later = now + Duration::seconds(61);
+        assert!(begin_trial(&mut credential, &settings, later));
+        assert!(!begin_trial(&mut credential, &settings, later));
+        assert_eq!(
+            record_failure(&mut credential, &settings, later),
+            HealthState::Unhealthy
+        );
+        assert_eq!(
+            credential.next_probe_at,
+            Some(later + Duration::seconds(60))
+        );
+
+        assert!(begin_trial(
+            &mut credential,
+            &settings,
+            later + Duration::seconds(61)
+        ));
+        record_success(&mut credential);
+        assert_eq!(credential.health_state, HealthState::Healthy);
+        assert_eq!(credential.consecutive_failures, 0);
+        assert!(credential.is_healthy);
+    }
+
+    #[tokio::test]
+    async fn test_prober_restores_unhealthy_credential() {
+        let _pool = crate::test_support::isolated_pool().await;
+        // 默认不发送推理请求，只检查 /v1/models
+        let (url, captured) =
+            crate::test_support::spawn_stub(200, "application/json", r#"{"data":[]}"#).await;
+        let id = crate::provider::create_credential(
+            "ccr",
+            serde_json::json!({ "api_key": "relay-key", "base_url": url }),
+        )
+        .await
+        .unwrap();
+        {
+            let mut creds = CREDENTIALS.write().await;
+            let credential = creds.get_mut(&id).unwrap();
+            set_state(credential, HealthState::Unhealthy);
+            credential.consecutive_failures = 3;
+        }
+        let validation = crate::provider::validate_credential(&id, false)
+            .await
+            .unwrap();
+        assert!(!validation.valid);
+        assert_eq!(
+            validation.message.as_deref(),
+            Some("凭证不健康 (unhealthy)")
+        );
+        assert_eq!(validation.details["health_state"], "unhealthy");
+
+        let restored = probe_unhealthy_credentials().await;
+        assert!(restored.contains(&id));
+        assert_eq!(captured.await.unwrap().path, "/v1/models");
+
+        let creds = CREDENTIALS.read().await;
+        assert_eq!(creds[&id].health_state, HealthState::Healthy);
+        assert!(creds[&id].is_healthy);
+    }
+}
@@ -1,17 +1,27 @@
 //! Claude Provider CLI - OAuth Provider Plugin for ProxyCast
 //!
 //! 这是一个独立的 CLI 工具，通过 JSON-RPC 与 ProxyCast 通信。
-//! 支持 OAuth、Claude Code、Console、Setup Token、Bedrock、CCR 多种认证方式。
+//! 支持 OAuth、Claude Code、Console、Setup Token、Bedrock、Bedrock API Key、Vertex AI、Foundry、CCR 多种认证方式。
 
 mod auth;
+mod config;
 mod credentials;
+mod error;
+mod health;
 mod provider;
+mod quota;
 mod token_refresh;
+mod transform;
+mod usage;
 
+#[cfg(test)]
+mod test_support;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
 use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 use std::io::{self, BufRead, Write};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 /// Claude Provider CLI
 #[derive(Parser)]
@@ -25,6 +35,10 @@ struct Cli {
     /// Run in JSON-RPC mode (stdin/stdout)
     #[arg(long)]
     json_rpc: bool,
+
+    /// Path to config.json (defaults to $CLAUDE_PROVIDER_CONFIG)
+    #[arg(long)]
+    config: Option<std::path::PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -43,6 +57,9 @@ enum Commands {
     Validate {
         #[arg(long)]
         credential_id: String,
+        /// Send real probe requests instead of checking fields only
+        #[arg(long)]
+        deep: bool,
     },
     /// Refresh token
     Refresh {
@@ -100,6 +117,21 @@ impl JsonRpcResponse {
             id,
         }
     }
+
+    /// 由 anyhow 错误构造，带上 PluginError 的错误码与结构化 data
+    fn from_error(id: serde_json::Value, error: &anyhow::Error) -> Self {
+        let (code, message, data) = error::to_rpc_error(error);
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(JsonRpcError {
+                code,
+                message,
+                data: Some(data),
+            }),
+            id,
+        }
+    }
 }
 
 #[tokio::main]
@@ -115,6 +147,16 @@ async fn main() -> anyhow::Result<()> {
 
     let cli = Cli::parse();
 
+    let config_path = cli
+        .config
+        .clone()
+        .or_else(|| std::env::var_os("CLAUDE_PROVIDER_CONFIG").map(Into::into));
+    if let Some(path) = config_path {
+        if let Err(e) = config::load(&path) {
+            warn!("{}，使用默认配置", e);
+        }
+    }
+
     if cli.json_rpc {
         run_json_rpc_mode().await?;
     } else if let Some(command) = cli.command {
@@ -131,9 +173,12 @@ async fn main() -> anyhow::Result<()> {
                 let params = auth::oauth::generate_oauth_params(setup);
                 println!("{}", serde_json::to_string_pretty(&params)?);
             }
-            Commands::Validate { credential_id } => {
+            Commands::Validate {
+                credential_id,
+                deep,
+            } => {
                 info!("Validating credential: {}", credential_id);
-                match provider::validate_credential(&credential_id).await {
+                match provider::validate_credential(&credential_id, deep).await {
                     Ok(result) => println!("{}", serde_json::to_string_pretty(&result)?),
                     Err(e) => eprintln!("Error: {}", e),
                 }
@@ -159,6 +204,9 @@ async fn main() -> anyhow::Result<()> {
 async fn run_json_rpc_mode() -> anyhow::Result<()> {
     info!("Starting Claude Provider in JSON-RPC mode");
 
+    // 后台探测不健康的凭证
+    health::spawn_health_checker();
+
     let stdin = io::stdin();
     let mut stdout = io::stdout();
 
@@ -213,7 +261,7 @@ async fn handle_request(request: JsonRpcRequest) -> JsonRpcResponse {
                 Ok(credential) => {
                     JsonRpcResponse::success(id, serde_json::to_value(credential).unwrap())
                 }
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
             }
         }
         "release_credential" => {
@@ -221,21 +269,47 @@ async fn handle_request(request: JsonRpcRequest) -> JsonRpcResponse {
             let result = &request.params["result"];
             match provider::release_credential(credential_id, result.clone()).await {
                 Ok(_) => JsonRpcResponse::success(id, serde_json::json!({})),
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
             }
         }
         "validate_credential" => {
             let credential_id = request.params["credential_id"].as_str().unwrap_or("");
-            match provider::validate_credential(credential_id).await {
+            let deep = request.params["deep"].as_bool().unwrap_or(false);
+            match provider::validate_credential(credential_id, deep).await {
+                Ok(result) => JsonRpcResponse::success(id, serde_json::to_value(result).unwrap()),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
+            }
+        }
+        "discover_models" => {
+            let credential_id = request.params["credential_id"].as_str().unwrap_or("");
+            match provider::discover_models(credential_id).await {
                 Ok(result) => JsonRpcResponse::success(id, serde_json::to_value(result).unwrap()),
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
+            }
+        }
+        "list_credentials" => {
+            let credentials = provider::list_credentials().await;
+            JsonRpcResponse::success(id, serde_json::json!({ "credentials": credentials }))
+        }
+        "get_usage_stats" => {
+            let credential_id = request.params["credential_id"].as_str();
+            match provider::usage_stats(credential_id).await {
+                Ok(report) => JsonRpcResponse::success(id, serde_json::to_value(report).unwrap()),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
+            }
+        }
+        "enable_credential" => {
+            let credential_id = request.params["credential_id"].as_str().unwrap_or("");
+            match provider::enable_credential(credential_id).await {
+                Ok(_) => JsonRpcResponse::success(id, serde_json::json!({})),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
             }
         }
         "refresh_token" => {
             let credential_id = request.params["credential_id"].as_str().unwrap_or("");
             match provider::refresh_token(credential_id).await {
                 Ok(result) => JsonRpcResponse::success(id, serde_json::to_value(result).unwrap()),
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
             }
         }
         "create_credential" => {
@@ -245,7 +319,7 @@ async fn handle_request(request: JsonRpcRequest) -> JsonRpcResponse {
                 Ok(credential_id) => {
                     JsonRpcResponse::success(id, serde_json::json!({ "credential_id": credential_id }))
                 }
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
             }
         }
         "generate_oauth_params" => {
@@ -259,7 +333,7 @@ async fn handle_request(request: JsonRpcRequest) -> JsonRpcResponse {
             let state = request.params["state"].as_str().unwrap_or("");
             match auth::oauth::exchange_authorization_code(code, code_verifier, state).await {
                 Ok(tokens) => JsonRpcResponse::success(id, serde_json::to_value(tokens).unwrap()),
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
             }
         }
         "oauth_with_cookie" => {
@@ -267,25 +341,103 @@ async fn handle_request(request: JsonRpcRequest) -> JsonRpcResponse {
             let is_setup = request.params["is_setup_token"].as_bool().unwrap_or(false);
             match auth::oauth::oauth_with_cookie(session_key, is_setup).await {
                 Ok(tokens) => JsonRpcResponse::success(id, serde_json::to_value(tokens).unwrap()),
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
             }
         }
         "transform_request" => {
             let request_body = request.params["request"].clone();
-            match provider::transform_request(request_body).await {
-                Ok(transformed) => {
-                    JsonRpcResponse::success(id, serde_json::json!({ "request": transformed }))
+            let credential_id = request.params["credential_id"].as_str();
+            let source_format = match request.params["source_format"]
+                .as_str()
+                .map(str::parse)
+                .transpose()
+            {
+                Ok(format) => format,
+                Err(e) => {
+                    return JsonRpcResponse::error(id, -32602, format!("Invalid params: {}", e))
                 }
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            };
+            match provider::transform_request(request_body, credential_id, source_format).await {
+                Ok(transformed) => JsonRpcResponse::success(id, serde_json::json!(transformed)),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
             }
         }
         "transform_response" => {
             let response_body = request.params["response"].clone();
-            match provider::transform_response(response_body).await {
+            let credential_id = request.params["credential_id"].as_str();
+            let source_format = match request.params["source_format"]
+                .as_str()
+                .map(str::parse)
+                .transpose()
+            {
+                Ok(format) => format.unwrap_or_default(),
+                Err(e) => {
+                    return JsonRpcResponse::error(id, -32602, format!("Invalid params: {}", e))
+                }
+            };
+            let include_usage = request.params["include_usage"].as_bool().unwrap_or(false);
+            match provider::transform_response(
+                response_body,
+                credential_id,
+                source_format,
+                include_usage,
+            )
+            .await
+            {
                 Ok(transformed) => {
                     JsonRpcResponse::success(id, serde_json::json!({ "response": transformed }))
                 }
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
+            }
+        }
+        "open_stream_transform" => {
+            let credential_id = request.params["credential_id"].as_str();
+            let source_format = match request.params["source_format"]
+                .as_str()
+                .map(str::parse)
+                .transpose()
+            {
+                Ok(format) => format.unwrap_or_default(),
+                Err(e) => {
+                    return JsonRpcResponse::error(id, -32602, format!("Invalid params: {}", e))
+                }
+            };
+            let include_usage = request.params["include_usage"].as_bool().unwrap_or(false);
+            match provider::open_stream_transform(credential_id, source_format, include_usage).await
+            {
+                Ok(stream_id) => {
+                    JsonRpcResponse::success(id, serde_json::json!({ "stream_id": stream_id }))
+                }
+                Err(e) => JsonRpcResponse::from_error(id, &e),
+            }
+        }
+        "feed_stream_chunk" => {
+            let stream_id = request.params["stream_id"].as_str().unwrap_or("");
+            // 按字节切分的上游数据以 chunk_base64 传入，可截断在多字节字符中间；
+            // chunk 为 JSON 字符串，调用方须在字符边界切分
+            let chunk = match request.params["chunk_base64"].as_str() {
+                Some(encoded) => match STANDARD.decode(encoded) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        return JsonRpcResponse::error(id, -32602, format!("Invalid params: {}", e))
+                    }
+                },
+                None => request.params["chunk"]
+                    .as_str()
+                    .unwrap_or("")
+                    .as_bytes()
+                    .to_vec(),
+            };
+            match provider::feed_stream_chunk(stream_id, &chunk).await {
+                Ok(output) => JsonRpcResponse::success(id, serde_json::json!({ "chunk": output })),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
+            }
+        }
+        "close_stream_transform" => {
+            let stream_id = request.params["stream_id"].as_str().unwrap_or("");
+            match provider::close_stream_transform(stream_id).await {
+                Ok(output) => JsonRpcResponse::success(id, serde_json::json!({ "chunk": output })),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
             }
         }
         "apply_risk_control" => {
@@ -295,13 +447,26 @@ async fn handle_request(request: JsonRpcRequest) -> JsonRpcResponse {
                 Ok(_) => {
                     JsonRpcResponse::success(id, serde_json::json!({ "request": request_body }))
                 }
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+                Err(e) => JsonRpcResponse::from_error(id, &e),
+            }
+        }
+        "sign_request" => {
+            let credential_id = request.params["credential_id"].as_str().unwrap_or("");
+            match serde_json::from_value(request.params.clone()) {
+                Ok(params) => match provider::sign_request(credential_id, params).await {
+                    Ok(signed) => {
+                        JsonRpcResponse::success(id, serde_json::to_value(signed).unwrap())
+                    }
+                    Err(e) => JsonRpcResponse::from_error(id, &e),
+                },
+                Err(e) => JsonRpcResponse::error(id, -32602, format!("Invalid params: {}", e)),
             }
         }
         "parse_error" => {
             let status = request.params["status"].as_u64().unwrap_or(0) as u16;
             let body = request.params["body"].as_str().unwrap_or("");
-            let error = provider::parse_error(status, body);
+            let headers = provider::normalize_headers(&request.params["headers"]);
+            let error = provider::parse_error(status, body, &headers);
             JsonRpcResponse::success(id, serde_json::to_value(error).unwrap_or_default())
         }
         _ => JsonRpcResponse::error(id, -32601, format!("Method not found: {}", request.method)),
@@ -353,6 +518,27 @@ fn get_plugin_info() -> serde_json::Value {
                 "category": "api_key",
                 "icon": "Cloud"
             },
+            {
+                "id": "bedrock_api_key",
+                "display_name": "AWS Bedrock API Key",
+                "description": "使用 Bedrock API Key（Bearer 认证）",
+                "category": "api_key",
+                "icon": "Cloud"
+            },
+            {
+                "id": "vertex",
+                "display_name": "Google Vertex AI",
+                "description": "使用 Vertex AI 服务账号访问 Claude",
+                "category": "api_key",
+                "icon": "Cloud"
+            },
+            {
+                "id": "foundry",
+                "display_name": "Microsoft Foundry",
+                "description": "使用 Azure Foundry API Key 或 Entra ID 访问 Claude",
+                "category": "api_key",
+                "icon": "Cloud"
+            },
             {
                 "id": "ccr",
                 "display_name": "CCR (中转服务)",
@@ -2,11 +2,57 @@
 //!
 //! 实现凭证管理、模型支持检查等核心功能。
 
-use crate::credentials::{AcquiredCredential, AuthType, ClaudeCredentials, ValidationResult};
-use crate::token_refresh::TokenRefreshResult;
+use crate::auth::aws_credentials::resolve_bedrock_credentials;
+use crate::auth::bedrock::{
+    bedrock_base_id, build_bedrock_invoke_url, build_bedrock_runtime_base_url,
+    build_guardrail_headers, compute_offered_models, is_guardrail_intervention,
+    list_foundation_models, list_inference_profiles, record_region_success, record_region_throttle,
+    resolve_bedrock_model, select_region, sign_aws_request, to_bedrock_body,
+    validate_bedrock_credentials, BedrockAuth, BedrockModelOptions, BedrockModelTarget,
+    GUARDRAIL_TRACE_VALUES,
+};
+use crate::auth::ccr::{
+    api_key_id, build_ccr_headers, ccr_inference_path, is_key_available, is_model_allowed,
+    list_ccr_models, parse_auth_header_template, probe_ccr_messages, record_key_failure,
+    record_key_success, resolve_ccr_model, select_api_key, validate_ccr_credentials,
+    CCRCredentials,
+};
+use crate::auth::foundry::{
+    build_foundry_base_url, build_foundry_headers, probe_foundry, resolve_deployment, FOUNDRY_SCOPE,
+};
+use crate::auth::oauth::{
+    oauth_inference_headers, probe_oauth_profile, profile_account_info, ANTHROPIC_API_BASE,
+};
+use crate::auth::probe::{probe_messages, probe_models};
+use crate::auth::vertex::{
+    build_vertex_base_url, build_vertex_url, map_to_vertex_model, to_vertex_body,
+    ServiceAccountKey, DEFAULT_VERTEX_REGION, VERTEX_SCOPE,
+};
+use crate::credentials::{
+    AcquiredCredential, AuthType, AwsCredentialSource, ClaudeCredentials, CredentialSummary,
+    CredentialUsage, DisabledReason, DisabledState, HealthState, UpstreamProtocol, UsageReport,
+    UsageTotals, ValidationResult,
+};
+use crate::error::{attach_credential, PluginError};
+use crate::health::{begin_trial, probe_due, record_failure, record_success};
+use crate::quota::{
+    credential_headroom, near_unified_limit, record_rate_limit_headers, record_unified_headers,
+    record_usage, unified_blocked_until, UsageTokens, RATE_LIMIT_HEADERS,
+};
+use crate::token_refresh::{is_token_expired, TokenRefreshResult};
+use crate::transform::openai_chat::{
+    anthropic_to_openai_request, openai_sse_to_anthropic, openai_to_anthropic_response,
+    OpenAiToAnthropicStream,
+};
+use crate::transform::stream::StreamTransform;
+use crate::transform::{
+    detect_source_format, from_anthropic_response, to_anthropic_request, SourceFormat,
+};
+use crate::usage::{normalize_model, pricing_backend, resolve_price, usage_totals, UNKNOWN_MODEL};
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
@@ -30,11 +76,34 @@ pub struct ProviderError {
     pub status_code: Option<u16>,
     pub retryable: bool,
     pub cooldown_seconds: Option<u64>,
+    /// 上游请求 ID（便于向 Anthropic 反馈问题）
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// 触发的限流维度（requests / input_tokens / output_tokens / tokens）
+    #[serde(default)]
+    pub rate_limit_type: Option<String>,
+    /// 终止性账户错误的停用原因（release_credential 时据此停用凭证）
+    #[serde(default)]
+    pub disable_reason: Option<DisabledReason>,
 }
 
 lazy_static::lazy_static! {
-    static ref CREDENTIALS: Arc<RwLock<HashMap<String, ClaudeCredentials>>> =
+    pub(crate) static ref CREDENTIALS: Arc<RwLock<HashMap<String, ClaudeCredentials>>> =
         Arc::new(RwLock::new(HashMap::new()));
+    static ref STREAM_SESSIONS: Arc<RwLock<HashMap<String, StreamSession>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+    /// 按凭证 ID 串行化令牌刷新，避免并发请求重复换取令牌
+    static ref REFRESH_LOCKS: std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>> =
+        std::sync::Mutex::new(HashMap::new());
+}
+
+/// 流式转换会话的最长保留时间（秒），超时未关闭的会话在下次打开、输入或关闭会话时清理
+const STREAM_SESSION_TTL_SECONDS: i64 = 3600;
+
+/// 流式转换会话
+struct StreamSession {
+    transform: StreamTransform,
+    opened_at: chrono::DateTime<Utc>,
 }
 
 /// 列出支持的模型
@@ -99,82 +168,288 @@ pub fn supports_model(model: &str) -> bool {
 /// 获取凭证
 pub async fn acquire_credential(model: &str) -> Result<AcquiredCredential> {
     if !supports_model(model) {
-        anyhow::bail!("不支持的模型: {}", model);
+        return Err(PluginError::UnsupportedModel {
+            model: model.to_string(),
+            reason: None,
+        }
+        .into());
     }
 
-    let creds = CREDENTIALS.read().await;
+    let mut creds = CREDENTIALS.write().await;
+    let now = Utc::now();
 
-    // 查找健康的凭证
-    let healthy_creds: Vec<_> = creds.iter().filter(|(_, c)| c.is_healthy).collect();
+    // 查找健康（或到期可半开试探）且能服务该模型的凭证
+    let mut unavailable_reason = None;
+    let mut retry_after: Option<u64> = None;
+    let healthy_creds: Vec<_> = creds
+        .iter()
+        .filter(|(id, c)| {
+            if c.disabled.is_some() {
+                return false;
+            }
+            // 订阅额度窗口用尽的凭证在重置前不可用
+            if let Some(until) = unified_blocked_until(&c.unified_limits, now) {
+                let wait = (until - now).num_seconds().max(1) as u64;
+                retry_after = Some(retry_after.map_or(wait, |r| r.min(wait)));
+                unavailable_reason = Some(format!(
+                    "凭证 {} 的订阅额度窗口已用尽",
+                    c.name.as_deref().unwrap_or("(未命名)")
+                ));
+                return false;
+            }
+            if !c.is_healthy && !probe_due(c, now) {
+                // 熔断中的凭证在下一次半开试探时可能恢复
+                if let Some(at) = c.next_probe_at {
+                    let wait = (at - now).num_seconds().max(1) as u64;
+                    retry_after = Some(retry_after.map_or(wait, |r| r.min(wait)));
+                }
+                return false;
+            }
+            match check_model_available(c, model).and_then(|_| check_ccr_keys(id, c, now)) {
+                Ok(()) => true,
+                Err(e) => {
+                    if let Some(PluginError::KeysCoolingDown {
+                        retry_after: Some(wait),
+                        ..
+                    }) = e.downcast_ref::<PluginError>()
+                    {
+                        retry_after = Some(retry_after.map_or(*wait, |r| r.min(*wait)));
+                    }
+                    unavailable_reason = Some(e.to_string());
+                    false
+                }
+            }
+        })
+        .collect();
 
     if healthy_creds.is_empty() {
-        anyhow::bail!("没有可用的健康凭证");
+        return Err(PluginError::NoHealthyCredential {
+            model: model.to_string(),
+            reason: unavailable_reason,
+            retry_after,
+        }
+        .into());
+    }
+
+    // 优先放行半开试探，其次选择健康凭证，最后才使用降级凭证；
+    // 同一等级内避开接近订阅窗口上限的凭证，再选择剩余额度最多的
+    let (id, _) = healthy_creds
+        .iter()
+        .min_by_key(|(_, c)| {
+            let tier = match c.health_state {
+                HealthState::Unhealthy | HealthState::Probing => 0,
+                HealthState::Healthy => 1,
+                HealthState::Degraded => 2,
+            };
+            let headroom_permille = (credential_headroom(c, now) * 1000.0) as u32;
+            (
+                tier,
+                near_unified_limit(&c.unified_limits, now),
+                std::cmp::Reverse(headroom_permille),
+            )
+        })
+        .unwrap();
+    let id = (*id).clone();
+    let credential = creds.get_mut(&id).unwrap();
+    if begin_trial(credential, &crate::config::settings().health_check, now) {
+        info!("凭证进入半开试探: {}", id);
     }
 
-    // 选择第一个健康凭证
-    let (id, credential) = healthy_creds.first().unwrap();
+    // CCR 多 Key 凭证按轮换策略选择本次使用的 Key
+    let mut selected_key = None;
+    if credential.auth_type == AuthType::Ccr && !credential.api_keys.is_empty() {
+        let index = select_api_key(
+            &credential.api_keys,
+            &credential.api_key_states,
+            credential.key_rotation,
+            &mut credential.key_cursor,
+            now,
+        )
+        .ok_or_else(|| check_ccr_keys(&id, credential, now).unwrap_err())?;
+        selected_key = Some(credential.api_keys[index].clone());
+    }
+
+    let mut credential = credential.clone();
+    drop(creds);
+    if let Some(key) = selected_key {
+        credential.api_key = Some(key);
+    }
+
+    // Vertex / Foundry Entra ID 访问令牌过期前自动刷新
+    if uses_access_token(&credential) {
+        credential = ensure_access_token(&id).await?;
+    }
+    let credential = &credential;
+
+    let mut metadata = HashMap::new();
 
     // 根据认证类型构建请求头和 base_url
-    let (base_url, headers) = match credential.auth_type {
-        AuthType::OAuth | AuthType::ClaudeCode | AuthType::Console | AuthType::SetupToken => {
-            let token = credential
-                .access_token
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("凭证没有有效的 access_token"))?;
+    let (base_url, headers) =
+        match credential.auth_type {
+            AuthType::OAuth | AuthType::ClaudeCode | AuthType::Console | AuthType::SetupToken => {
+                let token = credential
+                    .access_token
+                    .as_ref()
+                    .ok_or_else(|| PluginError::invalid_config("凭证没有有效的 access_token"))?;
 
-            let mut headers = HashMap::new();
-            headers.insert("Authorization".to_string(), format!("Bearer {}", token));
-            headers.insert("Content-Type".to_string(), "application/json".to_string());
-            headers.insert("anthropic-version".to_string(), "2023-06-01".to_string());
+                let headers = oauth_inference_headers(token)
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value))
+                    .collect();
 
-            (Some("https://api.anthropic.com".to_string()), headers)
-        }
-        AuthType::Bedrock => {
-            // Bedrock 需要 AWS 签名，这里只返回基本信息
-            let region = credential.region.as_deref().unwrap_or("us-east-1");
-            let base_url = format!("https://bedrock-runtime.{}.amazonaws.com", region);
+                (Some(ANTHROPIC_API_BASE.to_string()), headers)
+            }
+            AuthType::Bedrock => {
+                // Bedrock 需要 AWS 签名，这里只返回基本信息
+                let region = select_bedrock_region(credential, Some(model));
+                let base_url = build_bedrock_runtime_base_url(&region);
+                let target = bedrock_model_target(credential, model, &region)?;
 
-            let mut headers = HashMap::new();
-            headers.insert("Content-Type".to_string(), "application/json".to_string());
+                let mut headers = bedrock_extra_headers(credential);
+                headers.insert("Content-Type".to_string(), "application/json".to_string());
 
-            (Some(base_url), headers)
-        }
-        AuthType::Ccr => {
-            let api_key = credential
-                .api_key
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("CCR 凭证没有 api_key"))?;
-            let base_url = credential
-                .base_url
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("CCR 凭证没有 base_url"))?;
+                // 请求体确定后需通过 sign_request 获取签名头
+                metadata.insert("region".to_string(), serde_json::json!(region));
+                metadata.insert("signing".to_string(), serde_json::json!("aws_sigv4"));
+                metadata.insert("model_id".to_string(), serde_json::json!(target.model_id));
+                metadata.insert("target_kind".to_string(), serde_json::json!(target.kind));
 
-            let mut headers = HashMap::new();
-            headers.insert("x-api-key".to_string(), api_key.clone());
-            headers.insert("Content-Type".to_string(), "application/json".to_string());
-            headers.insert("anthropic-version".to_string(), "2023-06-01".to_string());
+                (Some(base_url), headers)
+            }
+            AuthType::BedrockApiKey => {
+                let api_key = credential.api_key.as_ref().ok_or_else(|| {
+                    PluginError::invalid_config("Bedrock API Key 凭证没有 api_key")
+                })?;
+                let region = select_bedrock_region(credential, Some(model));
+                let base_url = build_bedrock_runtime_base_url(&region);
+                let target = bedrock_model_target(credential, model, &region)?;
 
-            (Some(base_url.clone()), headers)
-        }
-    };
+                let mut headers = bedrock_extra_headers(credential);
+                headers.insert("Authorization".to_string(), format!("Bearer {}", api_key));
+                headers.insert("Content-Type".to_string(), "application/json".to_string());
+
+                metadata.insert("region".to_string(), serde_json::json!(region));
+                metadata.insert("model_id".to_string(), serde_json::json!(target.model_id));
+                metadata.insert("target_kind".to_string(), serde_json::json!(target.kind));
+
+                (Some(base_url), headers)
+            }
+            AuthType::Vertex => {
+                let token = credential.access_token.as_ref().ok_or_else(|| {
+                    PluginError::invalid_config("Vertex 凭证没有有效的 access_token")
+                })?;
+                let (project_id, region) = vertex_location(credential)?;
+                let model_id = map_to_vertex_model(model, &credential.model_map);
+
+                let mut headers = HashMap::new();
+                headers.insert("Authorization".to_string(), format!("Bearer {}", token));
+                headers.insert("Content-Type".to_string(), "application/json".to_string());
+
+                // 请求体需转换为 rawPredict 格式，可通过 sign_request 获取完整请求
+                metadata.insert("project_id".to_string(), serde_json::json!(project_id));
+                metadata.insert("region".to_string(), serde_json::json!(region));
+                metadata.insert("model_id".to_string(), serde_json::json!(model_id));
+                metadata.insert(
+                    "url".to_string(),
+                    serde_json::json!(build_vertex_url(&region, &project_id, &model_id, false)),
+                );
+                metadata.insert(
+                    "stream_url".to_string(),
+                    serde_json::json!(build_vertex_url(&region, &project_id, &model_id, true)),
+                );
+
+                (Some(build_vertex_base_url(&region)), headers)
+            }
+            AuthType::Foundry => {
+                let endpoint = credential
+                    .endpoint
+                    .as_ref()
+                    .ok_or_else(|| PluginError::invalid_config("Foundry 凭证没有 endpoint"))?;
+                let deployment = resolve_deployment(model, &credential.deployments)?;
+                let headers = foundry_headers(credential)?;
+
+                // 请求体中的 model 需替换为部署名
+                metadata.insert("deployment".to_string(), serde_json::json!(deployment));
+
+                (Some(build_foundry_base_url(endpoint)), headers)
+            }
+            AuthType::Ccr => {
+                let ccr = ccr_credentials(credential)?;
+                let headers = build_ccr_headers(&ccr)?.into_iter().collect();
+
+                // 释放凭证时回传 key_id 以更新该 Key 的状态
+                metadata.insert(
+                    "key_id".to_string(),
+                    serde_json::json!(api_key_id(&ccr.api_key)),
+                );
+                // OpenAI 协议的中转服务需调用 Chat Completions，请求与响应经 transform_* 转换
+                metadata.insert(
+                    "upstream_protocol".to_string(),
+                    serde_json::json!(credential.upstream_protocol),
+                );
+                metadata.insert(
+                    "path".to_string(),
+                    serde_json::json!(ccr_inference_path(credential.upstream_protocol)),
+                );
+
+                // 中转服务使用别名时，请求体中的 model 需替换（transform_request 会自动处理）
+                metadata.insert(
+                    "model".to_string(),
+                    serde_json::json!(resolve_ccr_model(model, &credential.model_map)),
+                );
+
+                (Some(ccr.base_url), headers)
+            }
+        };
 
     Ok(AcquiredCredential {
-        id: (*id).clone(),
+        id,
         name: credential.name.clone(),
         auth_type: credential.auth_type.to_string(),
         base_url,
         headers,
-        metadata: HashMap::new(),
+        metadata,
     })
 }
 
 /// 释放凭证
+///
+/// `result.headers` 为上游响应头，`result.usage` 为 Anthropic usage 对象，用于更新限流额度；
+/// 同时按 `result.model`（或 `result.metadata.model`）累计用量与估算费用。
 pub async fn release_credential(credential_id: &str, result: serde_json::Value) -> Result<()> {
     let mut creds = CREDENTIALS.write().await;
 
     if let Some(credential) = creds.get_mut(credential_id) {
         credential.usage_count += 1;
 
+        if result["usage"].is_object() {
+            record_usage_totals(credential, &result);
+        }
+
+        let headers = normalize_headers(&result["headers"]);
+        if record_unified_headers(&mut credential.unified_limits, &headers, Utc::now()) {
+            if let Some(until) = unified_blocked_until(&credential.unified_limits, Utc::now()) {
+                warn!(
+                    "凭证订阅额度窗口已用尽: {}，重置时间 {}",
+                    credential_id,
+                    until.to_rfc3339()
+                );
+            }
+        }
+        if !record_rate_limit_headers(&mut credential.rate_limits, &headers, Utc::now()) {
+            if let Some(usage) = UsageTokens::from_usage(&result["usage"]) {
+                record_usage(&mut credential.rate_limits, usage);
+            }
+        }
+
+        if credential.auth_type.is_bedrock() {
+            update_region_state(credential_id, credential, &result);
+        }
+        // 多 Key 凭证的错误由对应 Key 承担，不影响凭证整体健康
+        let key_scoped = credential.auth_type == AuthType::Ccr
+            && update_api_key_state(credential_id, credential, &result)
+            && credential.api_keys.len() > 1;
+
         if let Some(error) = result.get("error") {
             credential.error_count += 1;
             credential.last_error = error
@@ -182,16 +457,58 @@ pub async fn release_credential(credential_id: &str, result: serde_json::Value)
                 .and_then(|m| m.as_str())
                 .map(String::from);
 
-            if error
+            // 终止性账户错误直接停用凭证（多 Key 凭证的错误只影响对应的 Key）
+            let disable_reason = serde_json::from_value(error["disable_reason"].clone())
+                .ok()
+                .or_else(|| {
+                    classify_terminal_error(
+                        error["status_code"]
+                            .as_u64()
+                            .or_else(|| error["status"].as_u64())
+                            .unwrap_or(0) as u16,
+                        error["error_type"].as_str().and_then(anthropic_error_kind),
+                        error["message"].as_str().unwrap_or_default(),
+                    )
+                });
+            if let Some(reason) = disable_reason.filter(|_| !key_scoped) {
+                let state = DisabledState {
+                    reason,
+                    message: credential.last_error.clone(),
+                    disabled_at: Utc::now(),
+                };
+                // 模型无权使用只停用该模型；无法确定模型时停用整个凭证
+                match result_model(&result).filter(|_| reason == DisabledReason::ModelNotAllowed) {
+                    Some(model) => {
+                        warn!(
+                            "凭证模型已自动停用: {} ({}: {})",
+                            credential_id, model, reason
+                        );
+                        credential.blocked_models.insert(model.to_string(), state);
+                    }
+                    None => {
+                        warn!("凭证已自动停用: {} ({})", credential_id, reason);
+                        credential.disabled = Some(state);
+                    }
+                }
+                return Ok(());
+            }
+
+            // 只有凭证级错误计入连续失败；半开试探中的任何错误都会重新熔断
+            let mark_unhealthy = error
                 .get("mark_unhealthy")
                 .and_then(|v| v.as_bool())
                 .unwrap_or(false)
-            {
-                credential.is_healthy = false;
-                warn!("凭证标记为不健康: {}", credential_id);
+                && !key_scoped;
+            if mark_unhealthy || credential.health_state == HealthState::Probing {
+                let settings = crate::config::settings().health_check;
+                let state = record_failure(credential, &settings, Utc::now());
+                warn!(
+                    "凭证连续失败 {} 次，状态: {} ({})",
+                    credential.consecutive_failures, state, credential_id
+                );
             }
         } else {
-            credential.is_healthy = true;
+            record_success(credential);
             credential.last_error = None;
             debug!("凭证使用成功: {}", credential_id);
         }
@@ -200,52 +517,622 @@ pub async fn release_credential(credential_id: &str, result: serde_json::Value)
     Ok(())
 }
 
-/// 验证凭证
-pub async fn validate_credential(credential_id: &str) -> Result<ValidationResult> {
+/// 调用结果中的模型名
+fn result_model(result: &serde_json::Value) -> Option<&str> {
+    result["model"]
+        .as_str()
+        .or_else(|| result["metadata"]["model"].as_str())
+}
+
+/// 按模型累计本次调用的用量与估算费用
+fn record_usage_totals(credential: &mut ClaudeCredentials, result: &serde_json::Value) {
+    let model = result_model(result)
+        .map(normalize_model)
+        .unwrap_or_else(|| UNKNOWN_MODEL.to_string());
+    let price = resolve_price(
+        pricing_backend(credential.auth_type),
+        &model,
+        &crate::config::settings().pricing,
+    );
+    let totals = usage_totals(&result["usage"], price.as_ref());
+    credential
+        .usage_totals
+        .entry(model)
+        .or_default()
+        .add(&totals);
+}
+
+/// 用量统计（可只统计指定凭证）
+pub async fn usage_stats(credential_id: Option<&str>) -> Result<UsageReport> {
     let creds = CREDENTIALS.read().await;
+    if let Some(id) = credential_id {
+        if !creds.contains_key(id) {
+            return Err(PluginError::credential_not_found(id).into());
+        }
+    }
 
-    if let Some(credential) = creds.get(credential_id) {
-        let is_valid = match credential.auth_type {
-            AuthType::OAuth | AuthType::ClaudeCode | AuthType::Console | AuthType::SetupToken => {
-                credential.access_token.is_some()
+    let mut report = UsageReport {
+        total: UsageTotals::default(),
+        credentials: Vec::new(),
+        models: BTreeMap::new(),
+    };
+    for (id, credential) in creds.iter() {
+        if credential_id.is_some_and(|filter| filter != id) {
+            continue;
+        }
+        let mut total = UsageTotals::default();
+        for (model, totals) in &credential.usage_totals {
+            total.add(totals);
+            report.models.entry(model.clone()).or_default().add(totals);
+        }
+        report.total.add(&total);
+        report.credentials.push(CredentialUsage {
+            id: id.clone(),
+            name: credential.name.clone(),
+            backend: pricing_backend(credential.auth_type).to_string(),
+            total,
+            models: credential
+                .usage_totals
+                .iter()
+                .map(|(model, totals)| (model.clone(), totals.clone()))
+                .collect(),
+        });
+    }
+    report.credentials.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(report)
+}
+
+/// 根据调用结果更新 Bedrock 区域状态
+///
+/// 区域取自 `result.region` 或 `result.metadata.region`；限流错误使该区域进入冷却。
+fn update_region_state(
+    credential_id: &str,
+    credential: &mut ClaudeCredentials,
+    result: &serde_json::Value,
+) {
+    let Some(region) = result["region"]
+        .as_str()
+        .or_else(|| result["metadata"]["region"].as_str())
+    else {
+        return;
+    };
+    let state = credential
+        .region_states
+        .entry(region.to_string())
+        .or_default();
+
+    match result.get("error") {
+        Some(error) if is_throttling_error(error) => {
+            let until =
+                record_region_throttle(state, error["cooldown_seconds"].as_u64(), Utc::now());
+            warn!(
+                "Bedrock 区域限流: {} {}，冷却至 {}",
+                credential_id,
+                region,
+                until.to_rfc3339()
+            );
+        }
+        Some(_) => {}
+        None => record_region_success(state),
+    }
+}
+
+/// 根据调用结果更新 CCR API Key 状态，返回是否找到对应的 Key
+///
+/// Key 取自 `result.key_id` 或 `result.metadata.key_id`；单 Key 凭证默认对应 api_key。
+fn update_api_key_state(
+    credential_id: &str,
+    credential: &mut ClaudeCredentials,
+    result: &serde_json::Value,
+) -> bool {
+    let key_id = match result["key_id"]
+        .as_str()
+        .or_else(|| result["metadata"]["key_id"].as_str())
+    {
+        Some(key_id) => key_id.to_string(),
+        None if credential.api_keys.len() <= 1 => match ccr_key_pool(credential).first() {
+            Some(key) => api_key_id(key),
+            None => return false,
+        },
+        None => return false,
+    };
+    if !ccr_key_pool(credential)
+        .iter()
+        .any(|k| api_key_id(k) == key_id)
+    {
+        return false;
+    }
+
+    let state = credential.api_key_states.entry(key_id.clone()).or_default();
+    match result.get("error") {
+        Some(error) => {
+            let settings = crate::config::settings().health_check;
+            let status = error["status_code"]
+                .as_u64()
+                .or_else(|| error["status"].as_u64());
+            let throttled =
+                is_throttling_error(error).then(|| error["cooldown_seconds"].as_u64().unwrap_or(0));
+            let fatal = matches!(status, Some(401) | Some(403));
+            if throttled.is_none() && !fatal && !error["mark_unhealthy"].as_bool().unwrap_or(false)
+            {
+                state.usage_count += 1;
+                state.error_count += 1;
+                return true;
             }
-            AuthType::Bedrock => {
-                credential.access_key_id.is_some() && credential.secret_access_key.is_some()
+            record_key_failure(
+                state,
+                error["message"].as_str().map(String::from),
+                throttled,
+                fatal,
+                settings.unhealthy_threshold,
+                settings.interval_seconds,
+                Utc::now(),
+            );
+            if !state.is_healthy {
+                warn!("CCR API Key 标记为不健康: {} ({})", key_id, credential_id);
             }
-            AuthType::Ccr => credential.api_key.is_some() && credential.base_url.is_some(),
-        };
+        }
+        None => record_key_success(state),
+    }
+    true
+}
 
-        Ok(ValidationResult {
-            valid: is_valid && credential.is_healthy,
-            message: if is_valid {
-                Some("凭证有效".to_string())
-            } else {
-                Some("凭证配置不完整".to_string())
-            },
-            details: HashMap::new(),
-        })
-    } else {
-        Ok(ValidationResult {
+/// 是否为限流错误
+fn is_throttling_error(error: &serde_json::Value) -> bool {
+    let status = error["status_code"]
+        .as_u64()
+        .or_else(|| error["status"].as_u64());
+    let error_type = error["error_type"].as_str().unwrap_or("");
+    let message = error["message"].as_str().unwrap_or("");
+
+    status == Some(429)
+        || error_type == "rate_limit"
+        || error_type == "throttling"
+        || message.contains("ThrottlingException")
+        || message.contains("Too many requests")
+}
+
+/// 验证凭证
+///
+/// `deep = false` 时只检查配置字段与 Token 有效期；`deep = true` 时按认证类型发送真实探测请求，
+/// 并在 `details` 中返回耗时、HTTP 状态码、权限范围与账户信息。
+pub async fn validate_credential(credential_id: &str, deep: bool) -> Result<ValidationResult> {
+    validate_credential_with(credential_id, deep, true).await
+}
+
+/// 验证凭证
+///
+/// `inference = false` 时深度验证只使用不产生推理费用的探测（账户资料、`/v1/models`、
+/// 令牌换取、ListFoundationModels），供后台健康检查使用。
+pub(crate) async fn validate_credential_with(
+    credential_id: &str,
+    deep: bool,
+    inference: bool,
+) -> Result<ValidationResult> {
+    let Some(credential) = CREDENTIALS.read().await.get(credential_id).cloned() else {
+        return Ok(ValidationResult {
             valid: false,
             message: Some("凭证不存在".to_string()),
             details: HashMap::new(),
-        })
+        });
+    };
+
+    let is_valid = match credential.auth_type {
+        AuthType::OAuth | AuthType::ClaudeCode | AuthType::Console | AuthType::SetupToken => {
+            credential.access_token.is_some()
+        }
+        AuthType::Bedrock => has_aws_credentials(&credential),
+        AuthType::BedrockApiKey => credential.api_key.is_some(),
+        AuthType::Vertex => vertex_location(&credential).is_ok(),
+        AuthType::Foundry => credential.endpoint.is_some() && has_foundry_auth(&credential),
+        AuthType::Ccr => !ccr_key_pool(&credential).is_empty() && credential.base_url.is_some(),
+    };
+
+    let mut details = HashMap::new();
+    details.insert("deep".to_string(), serde_json::json!(deep));
+    if credential.expire.is_some() {
+        details.insert(
+            "token_expired".to_string(),
+            serde_json::json!(is_token_expired(credential.expire.as_deref())),
+        );
+    }
+
+    if !is_valid {
+        return Ok(ValidationResult {
+            valid: false,
+            message: Some("凭证配置不完整".to_string()),
+            details,
+        });
+    }
+    if !deep {
+        if let Some(disabled) = &credential.disabled {
+            details.insert("disabled".to_string(), serde_json::json!(disabled));
+            return Ok(ValidationResult {
+                valid: false,
+                message: Some(format!("凭证已停用: {}", disabled.reason)),
+                details,
+            });
+        }
+        if !credential.is_healthy {
+            details.insert(
+                "health_state".to_string(),
+                serde_json::json!(credential.health_state),
+            );
+            details.insert(
+                "next_probe_at".to_string(),
+                serde_json::json!(credential.next_probe_at),
+            );
+            return Ok(ValidationResult {
+                valid: false,
+                message: Some(format!("凭证不健康 ({})", credential.health_state)),
+                details,
+            });
+        }
+        return Ok(ValidationResult {
+            valid: true,
+            message: Some("凭证有效".to_string()),
+            details,
+        });
+    }
+
+    details.insert("inference".to_string(), serde_json::json!(inference));
+    let probed = match credential.auth_type {
+        AuthType::OAuth | AuthType::ClaudeCode | AuthType::Console | AuthType::SetupToken => {
+            probe_oauth_credential(&credential, inference, &mut details).await
+        }
+        AuthType::Bedrock | AuthType::BedrockApiKey => {
+            probe_bedrock_credential(credential_id, &credential, &mut details).await
+        }
+        AuthType::Vertex => probe_vertex_credential(credential_id, &mut details).await,
+        AuthType::Foundry => {
+            probe_foundry_credential(credential_id, &credential, inference, &mut details).await
+        }
+        AuthType::Ccr => probe_ccr_credential(&credential, inference, &mut details).await,
+    };
+    let valid = probed.unwrap_or_else(|e| {
+        details.insert("error".to_string(), serde_json::json!(e.to_string()));
+        false
+    });
+
+    Ok(ValidationResult {
+        valid,
+        message: Some(if valid {
+            "凭证有效".to_string()
+        } else {
+            "凭证探测失败".to_string()
+        }),
+        details,
+    })
+}
+
+/// 探测 OAuth 类凭证
+///
+/// 优先读取账户资料；Setup Token 或缺少 user:profile 权限时改用最小推理请求，
+/// 不允许推理探测时改用 `/v1/models`。
+async fn probe_oauth_credential(
+    credential: &ClaudeCredentials,
+    inference: bool,
+    details: &mut HashMap<String, serde_json::Value>,
+) -> Result<bool> {
+    let token = credential
+        .access_token
+        .as_deref()
+        .ok_or_else(|| PluginError::invalid_config("凭证没有有效的 access_token"))?;
+    let mut scopes = Vec::new();
+
+    if credential.auth_type != AuthType::SetupToken {
+        let profile = probe_oauth_profile(ANTHROPIC_API_BASE, token).await?;
+        profile.write_details("profile", details);
+        if let Some(body) = profile.body.as_ref().filter(|_| profile.valid) {
+            scopes.push("user:profile");
+            details.insert("account".to_string(), profile_account_info(body));
+            if !inference {
+                details.insert("scopes".to_string(), serde_json::json!(scopes));
+                return Ok(true);
+            }
+        } else if profile.status != 403 {
+            details.insert("scopes".to_string(), serde_json::json!(scopes));
+            return Ok(false);
+        }
+    }
+
+    if !inference {
+        let models = probe_models(ANTHROPIC_API_BASE, &oauth_inference_headers(token)).await?;
+        models.write_details("models", details);
+        details.insert("scopes".to_string(), serde_json::json!(scopes));
+        return Ok(models.valid);
+    }
+
+    let inference = probe_messages(
+        ANTHROPIC_API_BASE,
+        &oauth_inference_headers(token),
+        &probe_model(),
+    )
+    .await?;
+    inference.write_details("inference", details);
+    if inference.valid {
+        scopes.push("user:inference");
     }
+    details.insert("scopes".to_string(), serde_json::json!(scopes));
+
+    Ok(inference.valid)
 }
 
-/// 刷新 Token
-pub async fn refresh_token(credential_id: &str) -> Result<TokenRefreshResult> {
-    let mut creds = CREDENTIALS.write().await;
+/// 通过签名的 ListFoundationModels 请求探测 Bedrock 凭证
+async fn probe_bedrock_credential(
+    credential_id: &str,
+    credential: &ClaudeCredentials,
+    details: &mut HashMap<String, serde_json::Value>,
+) -> Result<bool> {
+    let region = select_bedrock_region(credential, None);
+    let auth = bedrock_auth(credential_id, credential, &region).await?;
+    let probe = validate_bedrock_credentials(&auth, None).await?;
 
-    if let Some(credential) = creds.get_mut(credential_id) {
-        // 调用 token_refresh 模块
-        let result = crate::token_refresh::refresh_token(credential).await?;
+    probe.write_details("", details);
+    details.insert("region".to_string(), serde_json::json!(region));
+    if let Some(models) = probe
+        .body
+        .as_ref()
+        .and_then(|b| b["modelSummaries"].as_array())
+    {
+        details.insert("model_count".to_string(), serde_json::json!(models.len()));
+    }
+
+    Ok(probe.valid)
+}
+
+/// 通过重新换取访问令牌探测 Vertex 凭证
+async fn probe_vertex_credential(
+    credential_id: &str,
+    details: &mut HashMap<String, serde_json::Value>,
+) -> Result<bool> {
+    let started = std::time::Instant::now();
+    let (credential, result) = {
+        let lock = refresh_lock(credential_id);
+        let _guard = lock.lock().await;
+        refresh_detached(credential_id).await?
+    };
+    let (project_id, region) = vertex_location(&credential)?;
+
+    details.insert(
+        "latency_ms".to_string(),
+        serde_json::json!(started.elapsed().as_millis() as u64),
+    );
+    details.insert("scopes".to_string(), serde_json::json!([VERTEX_SCOPE]));
+    details.insert(
+        "account".to_string(),
+        serde_json::json!({
+            "email": result.email,
+            "project_id": project_id,
+            "region": region,
+        }),
+    );
+
+    Ok(true)
+}
+
+/// 通过最小推理请求探测 Foundry 凭证
+///
+/// 使用部署映射中的第一个部署探测；未配置部署时直接使用探测模型名。
+/// 不允许推理探测时改用 `/v1/models` 检查认证。
+async fn probe_foundry_credential(
+    credential_id: &str,
+    credential: &ClaudeCredentials,
+    inference: bool,
+    details: &mut HashMap<String, serde_json::Value>,
+) -> Result<bool> {
+    let credential = if uses_access_token(credential) {
+        details.insert("scopes".to_string(), serde_json::json!([FOUNDRY_SCOPE]));
+        ensure_access_token(credential_id).await?
+    } else {
+        credential.clone()
+    };
+    let endpoint = credential
+        .endpoint
+        .as_ref()
+        .ok_or_else(|| PluginError::invalid_config("Foundry 凭证没有 endpoint"))?;
+    let deployment = credential
+        .deployments
+        .values()
+        .min()
+        .cloned()
+        .unwrap_or_else(probe_model);
+
+    let headers = build_foundry_headers(
+        credential.api_key.as_deref(),
+        credential.access_token.as_deref(),
+    )?;
+    if !inference {
+        let models = probe_models(&build_foundry_base_url(endpoint), &headers).await?;
+        models.write_details("models", details);
+        return Ok(models.valid);
+    }
+    let probe = probe_foundry(&build_foundry_base_url(endpoint), &headers, &deployment).await?;
+
+    probe.write_details("", details);
+    details.insert("deployment".to_string(), serde_json::json!(deployment));
+
+    Ok(probe.valid)
+}
+
+/// 推理探测使用的模型
+fn probe_model() -> String {
+    crate::config::settings().health_check.probe_model
+}
+
+/// 通过 /v1/models 与最小推理请求探测 CCR 凭证（不允许推理探测时只检查 /v1/models）
+async fn probe_ccr_credential(
+    credential: &ClaudeCredentials,
+    inference: bool,
+    details: &mut HashMap<String, serde_json::Value>,
+) -> Result<bool> {
+    let ccr = ccr_credentials(credential)?;
+
+    let models = validate_ccr_credentials(&ccr).await?;
+    models.write_details("models", details);
+    if !models.valid || !inference {
+        return Ok(models.valid);
+    }
+
+    // 优先使用凭证配置的探测模型，否则选择第一个允许调用的模型
+    let model = match &credential.probe_model {
+        Some(model) => model.clone(),
+        None => {
+            let candidates = std::iter::once(probe_model())
+                .chain(credential.available_models.iter().flatten().cloned());
+            let mut allowed =
+                candidates.filter(|model| check_model_available(credential, model).is_ok());
+            match allowed.next() {
+                Some(model) => resolve_ccr_model(&model, &credential.model_map),
+                None => {
+                    details.insert(
+                        "messages_skipped".to_string(),
+                        serde_json::json!("没有允许调用的探测模型"),
+                    );
+                    return Ok(true);
+                }
+            }
+        }
+    };
+    let messages = probe_ccr_messages(&ccr, &model).await?;
+    messages.write_details("messages", details);
+
+    Ok(messages.valid)
+}
 
-        info!("Token 刷新成功: {}", credential_id);
-        Ok(result)
+/// CCR 凭证的 Key 池（api_keys 为空时使用 api_key）
+fn ccr_key_pool(credential: &ClaudeCredentials) -> Vec<String> {
+    if credential.api_keys.is_empty() {
+        credential.api_key.iter().cloned().collect()
     } else {
-        anyhow::bail!("凭证不存在: {}", credential_id)
+        credential.api_keys.clone()
+    }
+}
+
+/// CCR 多 Key 凭证是否还有未在冷却中的 Key
+fn check_ccr_keys(
+    credential_id: &str,
+    credential: &ClaudeCredentials,
+    now: chrono::DateTime<Utc>,
+) -> Result<()> {
+    if credential.auth_type != AuthType::Ccr || credential.api_keys.is_empty() {
+        return Ok(());
+    }
+    let available = credential
+        .api_keys
+        .iter()
+        .any(|k| is_key_available(credential.api_key_states.get(&api_key_id(k)), now));
+    if !available {
+        // 最早结束冷却的 Key 的剩余时间
+        let retry_after = credential
+            .api_keys
+            .iter()
+            .filter_map(|k| {
+                credential
+                    .api_key_states
+                    .get(&api_key_id(k))?
+                    .cooldown_until
+            })
+            .map(|until| (until - now).num_seconds().max(1) as u64)
+            .min();
+        return Err(PluginError::KeysCoolingDown {
+            credential_id: credential_id.to_string(),
+            name: credential
+                .name
+                .clone()
+                .unwrap_or_else(|| "(未命名)".to_string()),
+            retry_after,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// CCR 凭证的连接信息（api_key 未指定时使用 Key 池中的第一个）
+fn ccr_credentials(credential: &ClaudeCredentials) -> Result<CCRCredentials> {
+    Ok(CCRCredentials {
+        api_key: credential
+            .api_key
+            .clone()
+            .or_else(|| credential.api_keys.first().cloned())
+            .ok_or_else(|| PluginError::invalid_config("CCR 凭证没有 api_key"))?,
+        base_url: credential
+            .base_url
+            .clone()
+            .ok_or_else(|| PluginError::invalid_config("CCR 凭证没有 base_url"))?,
+        name: credential.name.clone(),
+        auth_header: credential.auth_header.clone(),
+        extra_headers: credential.extra_headers.clone(),
+        upstream_protocol: credential.upstream_protocol,
+    })
+}
+
+/// 列出所有凭证（按 ID 排序，包含健康与停用状态）
+pub async fn list_credentials() -> Vec<CredentialSummary> {
+    let creds = CREDENTIALS.read().await;
+    let now = Utc::now();
+    let mut summaries: Vec<CredentialSummary> = creds
+        .iter()
+        .map(|(id, c)| CredentialSummary {
+            id: id.clone(),
+            name: c.name.clone(),
+            auth_type: c.auth_type.to_string(),
+            email: c.email.clone(),
+            health_state: c.health_state,
+            is_healthy: c.is_healthy,
+            usage_count: c.usage_count,
+            error_count: c.error_count,
+            last_error: c.last_error.clone(),
+            disabled: c.disabled.clone(),
+            blocked_models: c.blocked_models.clone(),
+            rate_limits: c.rate_limits.clone(),
+            unified_limits: c.unified_limits.clone(),
+            headroom: credential_headroom(c, now),
+        })
+        .collect();
+    summaries.sort_by(|a, b| a.id.cmp(&b.id));
+    summaries
+}
+
+/// 重新启用凭证（管理员操作），同时重置健康状态
+pub async fn enable_credential(credential_id: &str) -> Result<()> {
+    let mut creds = CREDENTIALS.write().await;
+    let credential = creds
+        .get_mut(credential_id)
+        .ok_or_else(|| PluginError::credential_not_found(credential_id))?;
+
+    if let Some(disabled) = credential.disabled.take() {
+        info!(
+            "凭证已重新启用: {} (原停用原因: {})",
+            credential_id, disabled.reason
+        );
     }
+    if !credential.blocked_models.is_empty() {
+        info!(
+            "凭证模型已重新启用: {} ({})",
+            credential_id,
+            credential
+                .blocked_models
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        credential.blocked_models.clear();
+    }
+    record_success(credential);
+    credential.last_error = None;
+    Ok(())
+}
+
+/// 刷新 Token
+pub async fn refresh_token(credential_id: &str) -> Result<TokenRefreshResult> {
+    let lock = refresh_lock(credential_id);
+    let _guard = lock.lock().await;
+    let (_, result) = refresh_detached(credential_id).await?;
+
+    info!("Token 刷新成功: {}", credential_id);
+    Ok(result)
 }
 
 /// 创建凭证
@@ -256,14 +1143,37 @@ pub async fn create_credential(auth_type: &str, config: serde_json::Value) -> Re
         "console" => AuthType::Console,
         "setup_token" => AuthType::SetupToken,
         "bedrock" => AuthType::Bedrock,
+        "bedrock_api_key" => AuthType::BedrockApiKey,
+        "vertex" => AuthType::Vertex,
+        "foundry" => AuthType::Foundry,
         "ccr" => AuthType::Ccr,
-        _ => anyhow::bail!("不支持的认证类型: {}", auth_type),
+        _ => {
+            return Err(
+                PluginError::invalid_config(format!("不支持的认证类型: {}", auth_type)).into(),
+            )
+        }
     };
 
-    let mut claude_config: ClaudeCredentials = serde_json::from_value(config)?;
+    let mut claude_config: ClaudeCredentials = serde_json::from_value(config)
+        .map_err(|e| PluginError::invalid_config(format!("凭证配置格式错误: {}", e)))?;
     claude_config.auth_type = auth_type_enum;
+    validate_credential_config(&claude_config)
+        .map_err(|e| PluginError::invalid_config(format!("{:#}", e)))?;
 
-    // 验证必要字段
+    // 生成凭证 ID
+    let credential_id = uuid::Uuid::new_v4().to_string();
+
+    // 存储凭证
+    let mut creds = CREDENTIALS.write().await;
+    creds.insert(credential_id.clone(), claude_config);
+
+    info!("创建凭证成功: {} (类型: {})", credential_id, auth_type);
+    Ok(credential_id)
+}
+
+/// 验证凭证必要字段
+fn validate_credential_config(claude_config: &ClaudeCredentials) -> Result<()> {
+    let auth_type_enum = claude_config.auth_type;
     match auth_type_enum {
         AuthType::OAuth | AuthType::ClaudeCode | AuthType::Console => {
             if claude_config.refresh_token.is_none() && claude_config.access_token.is_none() {
@@ -276,80 +1186,1905 @@ pub async fn create_credential(auth_type: &str, config: serde_json::Value) -> Re
             }
         }
         AuthType::Bedrock => {
-            if claude_config.access_key_id.is_none() || claude_config.secret_access_key.is_none() {
-                anyhow::bail!("Bedrock 凭证需要 access_key_id 和 secret_access_key");
+            if !has_aws_credentials(claude_config) {
+                anyhow::bail!(
+                    "Bedrock 凭证需要 access_key_id 和 secret_access_key，或指定 credential_source"
+                );
+            }
+        }
+        AuthType::BedrockApiKey => {
+            if claude_config.api_key.is_none()
+                || (claude_config.region.is_none() && claude_config.regions.is_empty())
+            {
+                anyhow::bail!("Bedrock API Key 凭证需要 api_key 和 region");
+            }
+        }
+        AuthType::Vertex => {
+            let key = claude_config.service_account_json.as_ref().ok_or_else(|| {
+                PluginError::invalid_config("Vertex 凭证需要 service_account_json")
+            })?;
+            ServiceAccountKey::from_value(key)
+                .map_err(|e| anyhow::anyhow!("service_account_json 格式错误: {}", e))?;
+            vertex_location(claude_config)?;
+        }
+        AuthType::Foundry => {
+            if claude_config.endpoint.is_none() || !has_foundry_auth(claude_config) {
+                anyhow::bail!(
+                    "Foundry 凭证需要 endpoint，以及 api_key 或 tenant_id / client_id / client_secret"
+                );
             }
         }
         AuthType::Ccr => {
-            if claude_config.api_key.is_none() || claude_config.base_url.is_none() {
-                anyhow::bail!("CCR 凭证需要 api_key 和 base_url");
+            if ccr_key_pool(claude_config).is_empty() || claude_config.base_url.is_none() {
+                anyhow::bail!("CCR 凭证需要 api_key（或 api_keys）和 base_url");
+            }
+            if let Some(template) = &claude_config.auth_header {
+                parse_auth_header_template(template)?;
             }
         }
     }
 
-    // 生成凭证 ID
-    let credential_id = uuid::Uuid::new_v4().to_string();
-
-    // 存储凭证
-    let mut creds = CREDENTIALS.write().await;
-    creds.insert(credential_id.clone(), claude_config);
-
-    info!("创建凭证成功: {} (类型: {})", credential_id, auth_type);
-    Ok(credential_id)
+    if auth_type_enum.is_bedrock() {
+        validate_guardrail_config(claude_config)?;
+        if claude_config.regions.iter().any(|r| r.trim().is_empty()) {
+            anyhow::bail!("regions 中不能包含空区域");
+        }
+    }
+    Ok(())
 }
 
-/// 转换请求
-pub async fn transform_request(request: serde_json::Value) -> Result<serde_json::Value> {
-    // Claude Provider 直接使用 Anthropic 格式，无需转换
-    Ok(request)
+/// 校验 Bedrock Guardrail 配置
+fn validate_guardrail_config(credential: &ClaudeCredentials) -> Result<()> {
+    if credential.guardrail_identifier.is_none()
+        && (credential.guardrail_version.is_some() || credential.guardrail_trace.is_some())
+    {
+        anyhow::bail!("配置 guardrail_version 或 guardrail_trace 时需要 guardrail_identifier");
+    }
+    if let Some(trace) = &credential.guardrail_trace {
+        if !GUARDRAIL_TRACE_VALUES.contains(&trace.as_str()) {
+            anyhow::bail!(
+                "guardrail_trace 取值无效: {}（可选 {}）",
+                trace,
+                GUARDRAIL_TRACE_VALUES.join(" / ")
+            );
+        }
+    }
+    Ok(())
 }
 
-/// 转换响应
-pub async fn transform_response(response: serde_json::Value) -> Result<serde_json::Value> {
-    // 响应转换
-    Ok(response)
+/// Bedrock 凭证是否配置了可用的 AWS 凭证来源
+fn has_aws_credentials(credential: &ClaudeCredentials) -> bool {
+    match credential.credential_source {
+        None | Some(AwsCredentialSource::Static) => {
+            credential.access_key_id.is_some() && credential.secret_access_key.is_some()
+        }
+        Some(AwsCredentialSource::AssumeRole { ref source, .. })
+            if matches!(source.as_deref(), None | Some(AwsCredentialSource::Static)) =>
+        {
+            credential.access_key_id.is_some() && credential.secret_access_key.is_some()
+        }
+        Some(_) => true,
+    }
 }
 
-/// 应用风控
-pub async fn apply_risk_control(
-    _request: &mut serde_json::Value,
-    _credential_id: &str,
-) -> Result<()> {
-    // Claude Provider 暂不需要特殊风控
-    Ok(())
+/// 是否使用需定期换取的访问令牌（Vertex 服务账号、Foundry Entra ID）
+fn uses_access_token(credential: &ClaudeCredentials) -> bool {
+    match credential.auth_type {
+        AuthType::Vertex => true,
+        AuthType::Foundry => credential.api_key.is_none(),
+        _ => false,
+    }
 }
 
-/// 解析错误
-pub fn parse_error(status: u16, body: &str) -> Option<ProviderError> {
-    match status {
-        401 => Some(ProviderError {
-            error_type: "authentication".to_string(),
-            message: "Token 已过期或无效".to_string(),
-            status_code: Some(status),
-            retryable: true,
-            cooldown_seconds: Some(0),
+/// 确保访问令牌有效（过期前 5 分钟自动重新换取），返回最新凭证
+///
+/// 同一凭证的并发调用只会换取一次令牌，其余调用等待后直接使用新令牌。
+async fn ensure_access_token(credential_id: &str) -> Result<ClaudeCredentials> {
+    let lock = refresh_lock(credential_id);
+    let _guard = lock.lock().await;
+
+    let credential = CREDENTIALS
+        .read()
+        .await
+        .get(credential_id)
+        .cloned()
+        .ok_or_else(|| PluginError::credential_not_found(credential_id))?;
+    if credential.access_token.is_some() && !is_token_expired(credential.expire.as_deref()) {
+        return Ok(credential);
+    }
+
+    let (credential, _) = refresh_detached(credential_id).await?;
+    debug!("访问令牌已刷新: {}", credential_id);
+    Ok(credential)
+}
+
+/// 凭证的刷新锁
+fn refresh_lock(credential_id: &str) -> Arc<tokio::sync::Mutex<()>> {
+    REFRESH_LOCKS
+        .lock()
+        .unwrap()
+        .entry(credential_id.to_string())
+        .or_default()
+        .clone()
+}
+
+/// 在凭证副本上刷新令牌，换取期间不持有 CREDENTIALS 锁，完成后只写回令牌相关字段
+///
+/// 调用方需持有该凭证的刷新锁（见 [`refresh_lock`]）。
+async fn refresh_detached(credential_id: &str) -> Result<(ClaudeCredentials, TokenRefreshResult)> {
+    let mut refreshed = CREDENTIALS
+        .read()
+        .await
+        .get(credential_id)
+        .cloned()
+        .ok_or_else(|| PluginError::credential_not_found(credential_id))?;
+    let result = crate::token_refresh::refresh_token(&mut refreshed)
+        .await
+        .map_err(|e| attach_credential(e, credential_id))?;
+
+    let mut creds = CREDENTIALS.write().await;
+    let stored = creds
+        .get_mut(credential_id)
+        .ok_or_else(|| PluginError::credential_not_found(credential_id))?;
+    stored.access_token = refreshed.access_token;
+    stored.refresh_token = refreshed.refresh_token;
+    stored.expire = refreshed.expire;
+    stored.last_refresh = refreshed.last_refresh;
+    stored.last_error = refreshed.last_error;
+    stored.email = refreshed.email;
+    Ok((stored.clone(), result))
+}
+
+/// Foundry 凭证是否配置了 API Key 或完整的 Entra ID 客户端凭证
+fn has_foundry_auth(credential: &ClaudeCredentials) -> bool {
+    credential.api_key.is_some()
+        || (credential.tenant_id.is_some()
+            && credential.client_id.is_some()
+            && credential.client_secret.is_some())
+}
+
+/// Foundry 认证请求头
+fn foundry_headers(credential: &ClaudeCredentials) -> Result<HashMap<String, String>> {
+    Ok(build_foundry_headers(
+        credential.api_key.as_deref(),
+        credential.access_token.as_deref(),
+    )?
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value))
+    .collect())
+}
+
+/// Vertex 凭证的项目 ID 与区域
+fn vertex_location(credential: &ClaudeCredentials) -> Result<(String, String)> {
+    let project_id = match &credential.project_id {
+        Some(project_id) => project_id.clone(),
+        None => credential
+            .service_account_json
+            .as_ref()
+            .and_then(|key| ServiceAccountKey::from_value(key).ok())
+            .and_then(|key| key.project_id)
+            .ok_or_else(|| PluginError::invalid_config("Vertex 凭证缺少 project_id"))?,
+    };
+    let region = credential
+        .vertex_region
+        .clone()
+        .unwrap_or_else(|| DEFAULT_VERTEX_REGION.to_string());
+    Ok((project_id, region))
+}
+
+/// Bedrock 凭证区域（未配置时使用 config.json 中的默认区域）
+fn bedrock_region(credential: &ClaudeCredentials) -> String {
+    credential
+        .region
+        .clone()
+        .unwrap_or_else(|| crate::config::settings().bedrock.default_region)
+}
+
+/// 选择 Bedrock 凭证当前最健康（且提供指定模型）的区域
+fn select_bedrock_region(credential: &ClaudeCredentials, model: Option<&str>) -> String {
+    if credential.regions.is_empty() {
+        return bedrock_region(credential);
+    }
+    select_region(
+        &credential.regions,
+        &credential.region_states,
+        model,
+        Utc::now(),
+    )
+    .map(String::from)
+    .unwrap_or_else(|| bedrock_region(credential))
+}
+
+/// 构建 Bedrock 凭证在指定区域的认证方式
+async fn bedrock_auth(
+    credential_id: &str,
+    credential: &ClaudeCredentials,
+    region: &str,
+) -> Result<BedrockAuth> {
+    match credential.auth_type {
+        AuthType::BedrockApiKey => Ok(BedrockAuth::ApiKey {
+            api_key: credential
+                .api_key
+                .clone()
+                .ok_or_else(|| PluginError::invalid_config("Bedrock API Key 凭证没有 api_key"))?,
+            region: region.to_string(),
         }),
-        403 => Some(ProviderError {
-            error_type: "authorization".to_string(),
-            message: "权限不足".to_string(),
+        _ => {
+            let mut aws = resolve_bedrock_credentials(credential_id, credential).await?;
+            aws.region = region.to_string();
+            Ok(BedrockAuth::SigV4(aws))
+        }
+    }
+}
+
+/// 解析 Bedrock 凭证在指定区域对应的模型调用目标
+fn bedrock_model_target(
+    credential: &ClaudeCredentials,
+    model: &str,
+    region: &str,
+) -> Result<BedrockModelTarget> {
+    let settings = crate::config::settings();
+    let options = BedrockModelOptions {
+        region,
+        model_prefix: credential
+            .model_prefix
+            .as_deref()
+            .or(settings.bedrock.explicit_model_prefix()),
+        model_map: Some(&credential.model_map),
+        discovered: Some(&credential.discovered_model_ids),
+    };
+    resolve_bedrock_model(model, &options)
+}
+
+/// Bedrock 凭证的附加请求头（Guardrail 等）
+fn bedrock_extra_headers(credential: &ClaudeCredentials) -> HashMap<String, String> {
+    build_guardrail_headers(
+        credential.guardrail_identifier.as_deref(),
+        credential.guardrail_version.as_deref(),
+        credential.guardrail_trace.as_deref(),
+    )
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v))
+    .collect()
+}
+
+/// 检查凭证能否服务指定模型
+fn check_model_available(credential: &ClaudeCredentials, model: &str) -> Result<()> {
+    if let Some(blocked) = credential.blocked_models.get(model) {
+        return Err(PluginError::unsupported_model(
+            model,
+            format!(
+                "凭证 {} 的模型 {} 已停用: {}",
+                credential.name.as_deref().unwrap_or("(未命名)"),
+                model,
+                blocked.reason
+            ),
+        )
+        .into());
+    }
+    if credential.auth_type.is_bedrock() {
+        bedrock_model_target(
+            credential,
+            model,
+            &select_bedrock_region(credential, Some(model)),
+        )?;
+    }
+    if credential.auth_type == AuthType::Foundry {
+        resolve_deployment(model, &credential.deployments)?;
+    }
+
+    // CCR 中转服务按允许/拒绝列表过滤，并以别名匹配其报告的模型
+    let mut served_as = model.to_string();
+    if credential.auth_type == AuthType::Ccr {
+        if !is_model_allowed(model, &credential.allowed_models, &credential.denied_models) {
+            return Err(PluginError::unsupported_model(
+                model,
+                format!(
+                    "中转服务 {} 不允许模型 {}",
+                    credential.name.as_deref().unwrap_or("(未命名)"),
+                    model
+                ),
+            )
+            .into());
+        }
+        served_as = resolve_ccr_model(model, &credential.model_map);
+    }
+
+    if let Some(models) = &credential.available_models {
+        if !models.iter().any(|m| m == model || *m == served_as) {
+            return Err(PluginError::unsupported_model(
+                model,
+                format!(
+                    "凭证 {} 的已发现模型中不包含 {}",
+                    credential.name.as_deref().unwrap_or("(未命名)"),
+                    model
+                ),
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// 模型发现结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelDiscoveryResult {
+    /// 发现的模型（Bedrock 为区域提供的模型，CCR 为中转服务报告的模型）
+    pub models: Vec<String>,
+    /// 发现时间 (RFC3339 格式)
+    pub discovered_at: String,
+    /// 额外信息（如模型对应的调用目标）
+    #[serde(default)]
+    pub details: HashMap<String, serde_json::Value>,
+}
+
+/// 发现凭证提供的模型并保存
+///
+/// Bedrock 的结果来自列表接口，只反映区域提供的模型，不确认账户的模型访问权限。
+pub async fn discover_models(credential_id: &str) -> Result<ModelDiscoveryResult> {
+    let credential = CREDENTIALS
+        .read()
+        .await
+        .get(credential_id)
+        .cloned()
+        .ok_or_else(|| PluginError::credential_not_found(credential_id))?;
+
+    let mut model_ids = HashMap::new();
+    let mut region_models = HashMap::new();
+    let (models, details): (Vec<String>, HashMap<String, serde_json::Value>) =
+        match credential.auth_type {
+            AuthType::Bedrock | AuthType::BedrockApiKey => {
+                // 模型在各区域的可用性不同，逐个区域发现
+                let regions = if credential.regions.is_empty() {
+                    vec![bedrock_region(&credential)]
+                } else {
+                    credential.regions.clone()
+                };
+                let settings = crate::config::settings();
+                let mut details: HashMap<String, serde_json::Value> = HashMap::new();
+                for region in &regions {
+                    let auth = bedrock_auth(credential_id, &credential, region).await?;
+                    let foundation_models = list_foundation_models(&auth).await?;
+                    let inference_profiles = list_inference_profiles(&auth).await?;
+
+                    let options = BedrockModelOptions {
+                        region,
+                        model_prefix: credential
+                            .model_prefix
+                            .as_deref()
+                            .or(settings.bedrock.explicit_model_prefix()),
+                        model_map: Some(&credential.model_map),
+                        discovered: None,
+                    };
+                    let discovered =
+                        compute_offered_models(&foundation_models, &inference_profiles, &options);
+
+                    for m in &discovered {
+                        let detail = details.entry(m.model.clone()).or_insert_with(|| {
+                            let mut detail = serde_json::json!(m.target);
+                            detail["regions"] = serde_json::json!([]);
+                            detail
+                        });
+                        if let Some(found_in) = detail["regions"].as_array_mut() {
+                            found_in.push(serde_json::json!(region));
+                        }
+                        // 记录基础模型 ID，列表中新出现的模型也能按区域映射调用
+                        if !credential.model_map.contains_key(&m.model) {
+                            if let Some(base_id) = bedrock_base_id(&m.target.model_id) {
+                                model_ids
+                                    .entry(m.model.clone())
+                                    .or_insert_with(|| base_id.to_string());
+                            }
+                        }
+                    }
+                    region_models.insert(
+                        region.clone(),
+                        discovered.into_iter().map(|m| m.model).collect(),
+                    );
+                }
+
+                let mut models: Vec<String> = details.keys().cloned().collect();
+                models.sort_unstable();
+                (models, details)
+            }
+            AuthType::Ccr => {
+                let reported = list_ccr_models(&ccr_credentials(&credential)?).await?;
+                let models = reported
+                    .iter()
+                    .filter_map(|m| m["id"].as_str().map(String::from))
+                    .collect();
+                let details = reported
+                    .into_iter()
+                    .filter_map(|m| Some((m["id"].as_str()?.to_string(), m)))
+                    .collect();
+                (models, details)
+            }
+            _ => {
+                return Err(PluginError::unsupported(format!(
+                    "{} 类型凭证不支持模型发现",
+                    credential.auth_type
+                ))
+                .into())
+            }
+        };
+
+    let discovered_at = chrono::Utc::now().to_rfc3339();
+    if let Some(stored) = CREDENTIALS.write().await.get_mut(credential_id) {
+        stored.available_models = Some(models.clone());
+        stored.models_discovered_at = Some(discovered_at.clone());
+        stored.discovered_model_ids = model_ids;
+        for (region, models) in region_models {
+            stored
+                .region_states
+                .entry(region)
+                .or_default()
+                .available_models = Some(models);
+        }
+    }
+
+    info!("模型发现完成: {} ({} 个模型)", credential_id, models.len());
+    Ok(ModelDiscoveryResult {
+        models,
+        discovered_at,
+        details,
+    })
+}
+
+/// 签名请求参数
+#[derive(Debug, Clone, Deserialize)]
+pub struct SignRequestParams {
+    /// HTTP 方法
+    #[serde(default = "default_sign_method")]
+    pub method: String,
+    /// 请求 URL（未指定时根据 model 构建）
+    #[serde(default)]
+    pub url: Option<String>,
+    /// 请求的 Anthropic 模型名（默认取请求体中的 model）
+    #[serde(default)]
+    pub model: Option<String>,
+    /// 是否流式调用（默认取请求体中的 stream）
+    #[serde(default)]
+    pub stream: Option<bool>,
+    /// 请求体（JSON 对象或原始字符串）
+    #[serde(default)]
+    pub body: serde_json::Value,
+}
+
+fn default_sign_method() -> String {
+    "POST".to_string()
+}
+
+/// 签名后的请求
+#[derive(Debug, Clone, Serialize)]
+pub struct SignedRequest {
+    pub url: String,
+    pub headers: HashMap<String, String>,
+    /// 实际需要发送的请求体
+    pub body: String,
+    /// 选中的区域（释放凭证时回传以跟踪区域限流）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+}
+
+/// 签名请求
+///
+/// Bedrock 凭证会选择最健康的区域，按配置的凭证来源解析 AWS 密钥，
+/// 将模型映射为调用 URL，转换请求体并生成 SigV4 签名头；
+/// Vertex 凭证会刷新访问令牌，并改写为 `:rawPredict` / `:streamRawPredict` 请求；
+/// Foundry 凭证会将请求体中的模型替换为部署名。
+pub async fn sign_request(credential_id: &str, params: SignRequestParams) -> Result<SignedRequest> {
+    let credential = CREDENTIALS
+        .read()
+        .await
+        .get(credential_id)
+        .cloned()
+        .ok_or_else(|| PluginError::credential_not_found(credential_id))?;
+    let credential = &credential;
+
+    match credential.auth_type {
+        AuthType::Foundry => {
+            let credential = if uses_access_token(credential) {
+                ensure_access_token(credential_id).await?
+            } else {
+                credential.clone()
+            };
+            let endpoint = credential
+                .endpoint
+                .as_ref()
+                .ok_or_else(|| PluginError::invalid_config("Foundry 凭证没有 endpoint"))?;
+            let mut body = params.body;
+            if let Some(model) = params
+                .model
+                .clone()
+                .or_else(|| body["model"].as_str().map(String::from))
+            {
+                let deployment = resolve_deployment(&model, &credential.deployments)?;
+                if let Some(obj) = body.as_object_mut() {
+                    obj.insert("model".to_string(), serde_json::json!(deployment));
+                }
+            }
+            let url = params
+                .url
+                .unwrap_or_else(|| format!("{}/v1/messages", build_foundry_base_url(endpoint)));
+            let body = match body {
+                serde_json::Value::Null => String::new(),
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+
+            Ok(SignedRequest {
+                url,
+                headers: foundry_headers(&credential)?,
+                body,
+                region: None,
+            })
+        }
+        AuthType::Vertex => {
+            let credential = ensure_access_token(credential_id).await?;
+            let token = credential
+                .access_token
+                .clone()
+                .ok_or_else(|| PluginError::invalid_config("Vertex 凭证没有有效的 access_token"))?;
+            let mut body = params.body;
+            let stream = params
+                .stream
+                .or_else(|| body["stream"].as_bool())
+                .unwrap_or(false);
+            let url = match params.url {
+                Some(url) => url,
+                None => {
+                    let model = params
+                        .model
+                        .clone()
+                        .or_else(|| body["model"].as_str().map(String::from))
+                        .ok_or_else(|| PluginError::invalid_params("签名请求需要 url 或 model"))?;
+                    let (project_id, region) = vertex_location(&credential)?;
+                    let model_id = map_to_vertex_model(&model, &credential.model_map);
+                    build_vertex_url(&region, &project_id, &model_id, stream)
+                }
+            };
+
+            to_vertex_body(&mut body);
+            let body = match body {
+                serde_json::Value::Null => String::new(),
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+
+            let mut headers = HashMap::new();
+            headers.insert("Authorization".to_string(), format!("Bearer {}", token));
+            headers.insert("Content-Type".to_string(), "application/json".to_string());
+
+            Ok(SignedRequest {
+                url,
+                headers,
+                body,
+                region: credential.vertex_region.clone(),
+            })
+        }
+        AuthType::Bedrock | AuthType::BedrockApiKey => {
+            let mut body = params.body;
+            let model = params
+                .model
+                .clone()
+                .or_else(|| body["model"].as_str().map(String::from));
+            let region = select_bedrock_region(credential, model.as_deref());
+            let url = match params.url {
+                Some(url) => url,
+                None => {
+                    let model = model
+                        .ok_or_else(|| PluginError::invalid_params("签名请求需要 url 或 model"))?;
+                    let stream = params
+                        .stream
+                        .or_else(|| body["stream"].as_bool())
+                        .unwrap_or(false);
+                    let target = bedrock_model_target(credential, &model, &region)?;
+                    build_bedrock_invoke_url(&region, &target.model_id, stream)
+                }
+            };
+
+            to_bedrock_body(&mut body);
+            let body = match body {
+                serde_json::Value::Null => String::new(),
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+
+            let mut headers = bedrock_extra_headers(credential);
+            match bedrock_auth(credential_id, credential, &region).await? {
+                BedrockAuth::SigV4(aws) => {
+                    let signature = sign_aws_request(&params.method, &url, &aws, body.as_bytes())?;
+                    headers.insert("Authorization".to_string(), signature.authorization);
+                    headers.insert("x-amz-date".to_string(), signature.x_amz_date);
+                    if let Some(token) = signature.x_amz_security_token {
+                        headers.insert("x-amz-security-token".to_string(), token);
+                    }
+                }
+                BedrockAuth::ApiKey { api_key, .. } => {
+                    headers.insert("Authorization".to_string(), format!("Bearer {}", api_key));
+                }
+            }
+            headers.insert("Content-Type".to_string(), "application/json".to_string());
+
+            debug!("Bedrock 请求签名完成: {}", credential_id);
+            Ok(SignedRequest {
+                url,
+                headers,
+                body,
+                region: Some(region),
+            })
+        }
+        _ => Err(
+            PluginError::unsupported(format!("{} 类型凭证不需要签名", credential.auth_type)).into(),
+        ),
+    }
+}
+
+/// 请求转换结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransformedRequest {
+    /// 转换后的请求体
+    pub request: serde_json::Value,
+    /// 入口请求格式（转换响应时需传回）
+    pub source_format: SourceFormat,
+    /// Chat Completions 请求是否设置了 `stream_options.include_usage`（转换响应、打开流式会话时需传回）
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+/// 转换请求
+///
+/// 入口为 OpenAI Chat Completions / Responses 或 Gemini 格式时（显式指定或自动识别）先转换为 Anthropic 格式；
+/// 指定 CCR 凭证时再按其别名映射改写模型名，OpenAI 协议的中转服务转换为 Chat Completions。
+pub async fn transform_request(
+    request: serde_json::Value,
+    credential_id: Option<&str>,
+    source_format: Option<SourceFormat>,
+) -> Result<TransformedRequest> {
+    let source_format = source_format.unwrap_or_else(|| detect_source_format(&request));
+    let include_usage = source_format == SourceFormat::OpenaiChat
+        && request["stream_options"]["include_usage"].as_bool() == Some(true);
+    let mut request = to_anthropic_request(source_format, request);
+
+    if let Some(credential_id) = credential_id.filter(|id| !id.is_empty()) {
+        let creds = CREDENTIALS.read().await;
+        let credential = creds
+            .get(credential_id)
+            .ok_or_else(|| PluginError::credential_not_found(credential_id))?;
+
+        if credential.auth_type == AuthType::Ccr {
+            if let Some(model) = request["model"].as_str() {
+                let alias = resolve_ccr_model(model, &credential.model_map);
+                request["model"] = serde_json::json!(alias);
+            }
+            if credential.upstream_protocol == UpstreamProtocol::Openai {
+                request = anthropic_to_openai_request(&request);
+            }
+        }
+    }
+
+    Ok(TransformedRequest {
+        request,
+        source_format,
+        include_usage,
+    })
+}
+
+/// 转换响应
+///
+/// 指定 OpenAI 协议的 CCR 凭证时，将 Chat Completions 响应转换回 Anthropic 格式：
+/// 完整响应对象转换为 Messages 响应，SSE 文本转换为 Anthropic SSE 文本，
+/// chunk 数组转换为 Anthropic 流式事件数组。
+/// 最后按入口格式转换：Chat Completions 入口转换为 chat.completion 对象，
+/// Responses 入口转换为 response 对象或 Responses 流式事件，
+/// Gemini 入口转换为 GenerateContentResponse 或 streamGenerateContent 响应块。
+pub async fn transform_response(
+    response: serde_json::Value,
+    credential_id: Option<&str>,
+    source_format: SourceFormat,
+    include_usage: bool,
+) -> Result<serde_json::Value> {
+    let response = if upstream_protocol(credential_id).await == UpstreamProtocol::Openai {
+        openai_upstream_to_anthropic(response)
+    } else {
+        response
+    };
+
+    Ok(from_anthropic_response(
+        source_format,
+        response,
+        include_usage,
+    ))
+}
+
+/// 凭证的上游协议（仅 CCR 凭证可能为 OpenAI 协议）
+async fn upstream_protocol(credential_id: Option<&str>) -> UpstreamProtocol {
+    match credential_id.filter(|id| !id.is_empty()) {
+        Some(credential_id) => CREDENTIALS
+            .read()
+            .await
+            .get(credential_id)
+            .filter(|c| c.auth_type == AuthType::Ccr)
+            .map(|c| c.upstream_protocol)
+            .unwrap_or_default(),
+        None => UpstreamProtocol::Anthropic,
+    }
+}
+
+/// 打开流式转换会话，返回会话 ID
+///
+/// 上游协议由凭证决定，输出格式由入口格式决定；两者都是 Anthropic 时原样转发事件。
+pub async fn open_stream_transform(
+    credential_id: Option<&str>,
+    source_format: SourceFormat,
+    include_usage: bool,
+) -> Result<String> {
+    let openai_upstream = upstream_protocol(credential_id).await == UpstreamProtocol::Openai;
+    let stream_id = uuid::Uuid::new_v4().to_string();
+    let now = Utc::now();
+
+    let mut sessions = STREAM_SESSIONS.write().await;
+    prune_stream_sessions(&mut sessions, now);
+    sessions.insert(
+        stream_id.clone(),
+        StreamSession {
+            transform: StreamTransform::new(openai_upstream, source_format, include_usage),
+            opened_at: now,
+        },
+    );
+    debug!(
+        "打开流式转换会话: {} (source_format={})",
+        stream_id, source_format
+    );
+    Ok(stream_id)
+}
+
+/// 输入一段上游 SSE 数据，返回转换后的 SSE 文本
+///
+/// 数据按原始字节输入：被截断在多字节 UTF-8 字符中间的尾部字节保留在会话中，与下一块拼接。
+pub async fn feed_stream_chunk(stream_id: &str, chunk: &[u8]) -> Result<String> {
+    let mut sessions = STREAM_SESSIONS.write().await;
+    prune_stream_sessions(&mut sessions, Utc::now());
+    let session = sessions
+        .get_mut(stream_id)
+        .ok_or_else(|| PluginError::StreamNotFound {
+            stream_id: stream_id.to_string(),
+        })?;
+    Ok(session.transform.feed(chunk))
+}
+
+/// 关闭流式转换会话，返回剩余的 SSE 文本
+pub async fn close_stream_transform(stream_id: &str) -> Result<String> {
+    let mut sessions = STREAM_SESSIONS.write().await;
+    prune_stream_sessions(&mut sessions, Utc::now());
+    let mut session = sessions
+        .remove(stream_id)
+        .ok_or_else(|| PluginError::StreamNotFound {
+            stream_id: stream_id.to_string(),
+        })?;
+    Ok(session.transform.close())
+}
+
+/// 清理超时未关闭的流式转换会话
+fn prune_stream_sessions(sessions: &mut HashMap<String, StreamSession>, now: DateTime<Utc>) {
+    sessions.retain(|_, s| (now - s.opened_at).num_seconds() < STREAM_SESSION_TTL_SECONDS);
+}
+
+/// OpenAI 协议中转服务的响应转换为 Anthropic 格式
+fn openai_upstream_to_anthropic(response: serde_json::Value) -> serde_json::Value {
+    match response {
+        serde_json::Value::String(text) => {
+            serde_json::Value::String(openai_sse_to_anthropic(&text))
+        }
+        serde_json::Value::Array(chunks) => {
+            let mut stream = OpenAiToAnthropicStream::new();
+            let mut events: Vec<_> = chunks.iter().flat_map(|c| stream.push_chunk(c)).collect();
+            events.extend(stream.finish());
+            serde_json::Value::Array(events)
+        }
+        response if response.get("choices").is_some() => openai_to_anthropic_response(&response),
+        // 错误等其他响应原样返回
+        response => response,
+    }
+}
+
+/// 应用风控
+pub async fn apply_risk_control(
+    _request: &mut serde_json::Value,
+    _credential_id: &str,
+) -> Result<()> {
+    // Claude Provider 暂不需要特殊风控
+    Ok(())
+}
+
+/// 上游错误响应体中的字段
+#[derive(Debug, Default)]
+struct UpstreamError {
+    /// Anthropic 错误类型（如 `overloaded_error`）
+    error_type: Option<String>,
+    message: Option<String>,
+    request_id: Option<String>,
+}
+
+/// 解析上游错误响应体
+///
+/// 支持 Anthropic 格式 `{"type":"error","error":{"type":...,"message":...},"request_id":...}`，
+/// 以及 Bedrock（顶层 `message`）与 Vertex（`error.message` / `error.status`）的错误结构。
+fn parse_upstream_error(body: &str) -> UpstreamError {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(body) else {
+        return UpstreamError::default();
+    };
+    let error = &value["error"];
+    let text = |v: &serde_json::Value| v.as_str().map(str::to_string);
+    UpstreamError {
+        error_type: text(&error["type"]),
+        message: text(&error["message"])
+            .or_else(|| text(&value["message"]))
+            .or_else(|| text(&value["Message"])),
+        request_id: text(&value["request_id"]),
+    }
+}
+
+/// Anthropic 错误响应中的 `error.type`
+const ANTHROPIC_ERROR_TYPES: &[&str] = &[
+    "invalid_request_error",
+    "authentication_error",
+    "billing_error",
+    "permission_error",
+    "not_found_error",
+    "request_too_large",
+    "rate_limit_error",
+    "api_error",
+    "timeout_error",
+    "overloaded_error",
+];
+
+/// 是否为模型不存在或当前凭证无权使用该模型（Anthropic `model: xxx`、Bedrock / Vertex 的模型标识错误）
+fn is_model_not_found(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.starts_with("model:")
+        || (message.contains("model")
+            && (message.contains("not found")
+                || message.contains("does not exist")
+                || message.contains("identifier is invalid")
+                || message.contains("not allowed")
+                || message.contains("does not have access")
+                || message.contains("don't have access")))
+}
+
+/// 终止性账户错误：允许的 Anthropic 错误类型、错误信息前缀（小写）、停用原因
+const TERMINAL_ERRORS: &[(&[&str], &str, DisabledReason)] = &[
+    (
+        &["invalid_request_error", "permission_error"],
+        "this organization has been disabled",
+        DisabledReason::OrganizationDisabled,
+    ),
+    (
+        &["authentication_error", "permission_error"],
+        "your account has been disabled",
+        DisabledReason::AccountSuspended,
+    ),
+    (
+        &["authentication_error", "permission_error"],
+        "your account has been suspended",
+        DisabledReason::AccountSuspended,
+    ),
+    (
+        &["invalid_request_error", "billing_error"],
+        "your credit balance is too low",
+        DisabledReason::BillingExhausted,
+    ),
+    (
+        &["permission_error"],
+        "oauth token does not meet scope requirement",
+        DisabledReason::MissingScope,
+    ),
+];
+
+/// 调用结果中 `error_type` 对应的 Anthropic 错误类型
+///
+/// 兼容 [`ProviderError`] 的归一化类型与直接转发的上游错误类型；无法对应时返回 None，只按错误信息匹配。
+fn anthropic_error_kind(error_type: &str) -> Option<&str> {
+    match error_type {
+        "authentication" => Some("authentication_error"),
+        "authorization" => Some("permission_error"),
+        "credit_balance_too_low" => Some("billing_error"),
+        "invalid_request" => Some("invalid_request_error"),
+        kind if ANTHROPIC_ERROR_TYPES.contains(&kind) => Some(kind),
+        _ => None,
+    }
+}
+
+/// 是否为 Token 无权调用请求的模型（而非模型不存在）
+fn is_model_not_allowed(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("model")
+        && (message.contains("not allowed")
+            || message.contains("does not have access")
+            || message.contains("don't have access"))
+}
+
+/// 识别终止性账户错误（组织停用、账户封禁、余额耗尽、权限范围不足、模型无权使用）
+///
+/// 仅针对 400 / 401 / 402 / 403 错误，按上游 `error.type` 与已知的错误信息匹配；
+/// 未提供错误类型时只按错误信息匹配。这类错误重试或等待都无法恢复。
+/// 模型无权使用（403 / permission_error）只停用该模型，其他模型仍可使用该凭证。
+pub fn classify_terminal_error(
+    status: u16,
+    error_type: Option<&str>,
+    message: &str,
+) -> Option<DisabledReason> {
+    if !matches!(status, 400..=403) {
+        return None;
+    }
+    if error_type == Some("billing_error") {
+        return Some(DisabledReason::BillingExhausted);
+    }
+    if (error_type == Some("permission_error") || (error_type.is_none() && status == 403))
+        && is_model_not_allowed(message)
+    {
+        return Some(DisabledReason::ModelNotAllowed);
+    }
+    let message = message.trim().to_lowercase();
+    TERMINAL_ERRORS
+        .iter()
+        .find(|(types, prefix, _)| {
+            error_type.is_none_or(|t| types.contains(&t)) && message.starts_with(prefix)
+        })
+        .map(|(_, _, reason)| *reason)
+}
+
+/// 将 JSON 形式的响应头转换为小写键名的映射（数组值取第一个）
+pub fn normalize_headers(headers: &serde_json::Value) -> HashMap<String, String> {
+    headers
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, value)| {
+            let value = match value {
+                serde_json::Value::Array(values) => values.first()?.as_str()?,
+                value => value.as_str()?,
+            };
+            Some((name.to_lowercase(), value.to_string()))
+        })
+        .collect()
+}
+
+/// 解析 `retry-after`（秒数或 HTTP 日期）
+fn parse_retry_after(value: &str, now: chrono::DateTime<Utc>) -> Option<u64> {
+    if let Ok(seconds) = value.trim().parse::<f64>() {
+        return Some(seconds.max(0.0).ceil() as u64);
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some((at.with_timezone(&Utc) - now).num_seconds().max(0) as u64)
+}
+
+/// 根据限流响应头计算冷却时间，返回（冷却秒数，触发的限流维度）
+///
+/// 剩余额度为 0 的维度中取最晚的重置时间；`retry-after` 存在时以其为准。
+/// 没有可用的响应头时返回 `None`。
+fn rate_limit_cooldown(
+    headers: &HashMap<String, String>,
+    now: chrono::DateTime<Utc>,
+) -> Option<(Option<u64>, Option<String>)> {
+    let exhausted = RATE_LIMIT_HEADERS
+        .iter()
+        .filter(|(_, prefix)| {
+            headers
+                .get(&format!("{}-remaining", prefix))
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                == Some(0)
+        })
+        .filter_map(|(kind, prefix)| {
+            let reset = headers.get(&format!("{}-reset", prefix))?;
+            let reset = chrono::DateTime::parse_from_rfc3339(reset.trim()).ok()?;
+            let seconds = (reset.with_timezone(&Utc) - now).num_milliseconds().max(0);
+            Some((kind.to_string(), (seconds as u64).div_ceil(1000)))
+        })
+        .max_by_key(|(_, seconds)| *seconds);
+
+    let retry_after = headers
+        .get("retry-after")
+        .and_then(|v| parse_retry_after(v, now));
+
+    match (retry_after, exhausted) {
+        (None, None) => None,
+        (retry_after, exhausted) => {
+            let (kind, reset_seconds) = exhausted.unzip();
+            Some((retry_after.or(reset_seconds), kind))
+        }
+    }
+}
+
+/// 解析错误
+///
+/// 优先按响应体中的 Anthropic 错误类型分类，无法解析时按状态码分类，并保留上游 request_id。
+/// 状态码为 2xx 时仅在响应体带错误类型（如流中途的 `error` 事件）时返回错误。
+/// 传入响应头时，限流与过载错误按 `retry-after` 与 `anthropic-ratelimit-*-reset` 计算冷却时间，
+/// `request-id` 响应头优先于响应体中的 request_id。
+pub fn parse_error(
+    status: u16,
+    body: &str,
+    headers: &HashMap<String, String>,
+) -> Option<ProviderError> {
+    parse_error_at(status, body, headers, Utc::now())
+}
+
+fn parse_error_at(
+    status: u16,
+    body: &str,
+    headers: &HashMap<String, String>,
+    now: chrono::DateTime<Utc>,
+) -> Option<ProviderError> {
+    let header_request_id = headers.get("request-id").cloned();
+
+    // Bedrock Guardrail 拦截可能以任意状态码返回
+    if is_guardrail_intervention(body) {
+        return Some(ProviderError {
+            error_type: "guardrail_intervened".to_string(),
+            message: "请求被 Bedrock Guardrail 拦截".to_string(),
             status_code: Some(status),
             retryable: false,
             cooldown_seconds: None,
-        }),
-        429 => Some(ProviderError {
-            error_type: "rate_limit".to_string(),
-            message: "请求过于频繁".to_string(),
-            status_code: Some(status),
-            retryable: true,
-            cooldown_seconds: Some(60),
-        }),
-        500..=599 => Some(ProviderError {
-            error_type: "server_error".to_string(),
-            message: format!("服务器错误: {}", body),
-            status_code: Some(status),
-            retryable: true,
-            cooldown_seconds: Some(10),
-        }),
+            request_id: header_request_id.or(parse_upstream_error(body).request_id),
+            rate_limit_type: None,
+            disable_reason: None,
+        });
+    }
+
+    let upstream = parse_upstream_error(body);
+    // 只信任 Anthropic 定义的错误类型，OpenAI 协议中转等返回的其他类型按状态码分类
+    let body_kind = upstream
+        .error_type
+        .as_deref()
+        .filter(|kind| ANTHROPIC_ERROR_TYPES.contains(kind));
+    let kind = body_kind.or(match status {
+        400 => Some("invalid_request_error"),
+        401 => Some("authentication_error"),
+        402 => Some("billing_error"),
+        403 => Some("permission_error"),
+        404 => Some("not_found_error"),
+        413 => Some("request_too_large"),
+        429 => Some("rate_limit_error"),
+        529 => Some("overloaded_error"),
+        500..=599 => Some("api_error"),
         _ => None,
+    })?;
+    let upstream_message = upstream.message.clone().unwrap_or_default();
+    let disable_reason = classify_terminal_error(status, body_kind, &upstream_message);
+
+    let (error_type, message, retryable, mut cooldown_seconds) = match kind {
+        "authentication_error" => ("authentication", "Token 已过期或无效", true, Some(0)),
+        "rate_limit_error" => ("rate_limit", "请求过于频繁", true, Some(60)),
+        "overloaded_error" => ("overloaded", "上游服务过载", true, Some(10)),
+        "request_too_large" => ("request_too_large", "请求体过大", false, None),
+        // 余额耗尽对本凭证不可恢复：release 时凭证被停用，之后的重试只会落到其他凭证
+        "invalid_request_error" | "billing_error"
+            if disable_reason == Some(DisabledReason::BillingExhausted) =>
+        {
+            ("credit_balance_too_low", "账户余额不足", true, None)
+        }
+        // 模型可能仅在部分凭证（区域、中转服务、订阅）上可用，允许切换凭证重试
+        "invalid_request_error" | "not_found_error" | "permission_error"
+            if is_model_not_found(&upstream_message) =>
+        {
+            ("model_not_found", "模型不存在或不可用", true, None)
+        }
+        "permission_error" => ("authorization", "权限不足", false, None),
+        "invalid_request_error" => ("invalid_request", "请求参数无效", false, None),
+        "not_found_error" => ("not_found", "资源不存在", false, None),
+        _ => ("server_error", "服务器错误", true, Some(10)),
+    };
+
+    // 终止性错误会停用当前凭证，允许切换其他凭证重试
+    let retryable = retryable || disable_reason.is_some();
+
+    let mut rate_limit_type = None;
+    if matches!(error_type, "rate_limit" | "overloaded") {
+        if let Some((cooldown, kind)) = rate_limit_cooldown(headers, now) {
+            cooldown_seconds = cooldown.or(cooldown_seconds);
+            rate_limit_type = kind;
+        }
+    }
+
+    Some(ProviderError {
+        error_type: error_type.to_string(),
+        message: upstream.message.unwrap_or_else(|| match error_type {
+            "server_error" if !body.is_empty() => format!("{}: {}", message, body),
+            _ => message.to_string(),
+        }),
+        status_code: Some(status),
+        retryable,
+        cooldown_seconds,
+        request_id: header_request_id.or(upstream.request_id),
+        rate_limit_type,
+        disable_reason,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_guardrail_intervention() {
+        let error = parse_error(
+            200,
+            r#"{"amazon-bedrock-guardrailAction":"INTERVENED","content":[{"type":"text","text":"blocked"}]}"#, &HashMap::new())
+        .unwrap();
+        assert_eq!(error.error_type, "guardrail_intervened");
+        assert!(!error.retryable);
+
+        assert!(parse_error(200, r#"{"content":[]}"#, &HashMap::new()).is_none());
+        assert_eq!(
+            parse_error(429, "", &HashMap::new()).unwrap().error_type,
+            "rate_limit"
+        );
+    }
+
+    #[test]
+    fn test_parse_error_anthropic_error_types() {
+        let body = |kind: &str, message: &str| {
+            serde_json::json!({
+                "type": "error",
+                "error": { "type": kind, "message": message },
+                "request_id": "req_011CTest"
+            })
+            .to_string()
+        };
+
+        let overloaded = parse_error(
+            529,
+            &body("overloaded_error", "Overloaded"),
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(overloaded.error_type, "overloaded");
+        assert_eq!(overloaded.request_id.as_deref(), Some("req_011CTest"));
+        assert!(overloaded.retryable);
+
+        let model = parse_error(
+            404,
+            &body("not_found_error", "model: claude-nonexistent"),
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(model.error_type, "model_not_found");
+        assert_eq!(model.message, "model: claude-nonexistent");
+
+        let credit = parse_error(
+            400,
+            &body(
+                "invalid_request_error",
+                "Your credit balance is too low to access the Anthropic API.",
+            ),
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(credit.error_type, "credit_balance_too_low");
+        assert_eq!(
+            credit.disable_reason,
+            Some(DisabledReason::BillingExhausted)
+        );
+
+        let invalid = parse_error(
+            400,
+            &body("invalid_request_error", "max_tokens: required"),
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(invalid.error_type, "invalid_request");
+        assert!(!invalid.retryable);
+        assert_eq!(
+            parse_error(413, "", &HashMap::new()).unwrap().error_type,
+            "request_too_large"
+        );
+        assert_eq!(
+            parse_error(403, "", &HashMap::new()).unwrap().error_type,
+            "authorization"
+        );
+
+        // 流中途的错误事件以 200 返回
+        let streamed = parse_error(
+            200,
+            &body("rate_limit_error", "Rate limited"),
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(streamed.error_type, "rate_limit");
+
+        // OpenAI 协议中转的错误类型不是 Anthropic 类型，按状态码分类
+        let openai_body = |kind: &str| {
+            serde_json::json!({ "error": { "type": kind, "message": "upstream error" } })
+                .to_string()
+        };
+        let throttled = parse_error(429, &openai_body("requests"), &HashMap::new()).unwrap();
+        assert_eq!(throttled.error_type, "rate_limit");
+        assert_eq!(throttled.cooldown_seconds, Some(60));
+        let unauthorized =
+            parse_error(401, &openai_body("invalid_api_key"), &HashMap::new()).unwrap();
+        assert_eq!(unauthorized.error_type, "authentication");
+        assert_eq!(unauthorized.message, "upstream error");
+    }
+
+    #[test]
+    fn test_parse_error_rate_limit_headers() {
+        let now = Utc::now();
+        let reset = |seconds: i64| (now + chrono::Duration::seconds(seconds)).to_rfc3339();
+        let mut headers: HashMap<String, String> = [
+            ("request-id", "req_header".to_string()),
+            ("anthropic-ratelimit-requests-remaining", "12".to_string()),
+            ("anthropic-ratelimit-requests-reset", reset(5)),
+            (
+                "anthropic-ratelimit-input-tokens-remaining",
+                "0".to_string(),
+            ),
+            ("anthropic-ratelimit-input-tokens-reset", reset(42)),
+            (
+                "anthropic-ratelimit-output-tokens-remaining",
+                "0".to_string(),
+            ),
+            ("anthropic-ratelimit-output-tokens-reset", reset(17)),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+
+        let error = parse_error_at(429, "", &headers, now).unwrap();
+        assert_eq!(error.cooldown_seconds, Some(42));
+        assert_eq!(error.rate_limit_type.as_deref(), Some("input_tokens"));
+        assert_eq!(error.request_id.as_deref(), Some("req_header"));
+
+        // retry-after 优先
+        headers.insert("retry-after".to_string(), "7".to_string());
+        let error = parse_error_at(429, "", &headers, now).unwrap();
+        assert_eq!(error.cooldown_seconds, Some(7));
+        assert_eq!(error.rate_limit_type.as_deref(), Some("input_tokens"));
+
+        let headers = normalize_headers(&serde_json::json!({ "Retry-After": ["3"] }));
+        let error = parse_error_at(529, "", &headers, now).unwrap();
+        assert_eq!(error.cooldown_seconds, Some(3));
+        assert_eq!(error.rate_limit_type, None);
+    }
+
+    #[tokio::test]
+    async fn test_create_bedrock_api_key_requires_key() {
+        let result = create_credential(
+            "bedrock_api_key",
+            serde_json::json!({ "region": "us-west-2" }),
+        )
+        .await;
+        let error = result.unwrap_err();
+        assert_eq!(
+            crate::error::find_plugin_error(&error).map(|e| e.code()),
+            Some("invalid_config")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_ignores_runtime_state_in_config() {
+        let _pool = crate::test_support::isolated_pool().await;
+        let id = create_credential(
+            "ccr",
+            serde_json::json!({
+                "api_key": "sk-runtime",
+                "base_url": "http://127.0.0.1:9",
+                "key_cursor": 7,
+                "usage_totals": { "claude-sonnet-4-5": { "requests": 3 } },
+                "health_state": "unhealthy",
+                "consecutive_failures": 5,
+                "disabled": {
+                    "reason": "account_suspended",
+                    "disabled_at": "2025-01-01T00:00:00Z"
+                },
+                "available_models": ["claude-haiku-4-5"],
+                "region_states": { "us-east-1": { "consecutive_throttles": 2 } }
+            }),
+        )
+        .await
+        .unwrap();
+
+        let creds = CREDENTIALS.read().await;
+        let credential = creds.get(&id).unwrap();
+        assert_eq!(credential.key_cursor, 0);
+        assert!(credential.usage_totals.is_empty());
+        assert!(credential.region_states.is_empty());
+        assert_eq!(credential.health_state, HealthState::Healthy);
+        assert_eq!(credential.consecutive_failures, 0);
+        assert!(credential.disabled.is_none());
+        assert!(credential.available_models.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_validate_foundry_probes_deployment() {
+        let _pool = crate::test_support::isolated_pool().await;
+        let (endpoint, captured) = crate::test_support::spawn_stub(
+            401,
+            "application/json",
+            r#"{"error":{"code":"401","message":"Access denied"}}"#,
+        )
+        .await;
+        let id = create_credential(
+            "foundry",
+            serde_json::json!({
+                "endpoint": endpoint,
+                "api_key": "foundry-key",
+                "deployments": { "claude-sonnet-4-5-20250929": "sonnet-prod" }
+            }),
+        )
+        .await
+        .unwrap();
+
+        let result = validate_credential(&id, true).await.unwrap();
+        assert!(!result.valid);
+        assert_eq!(result.details["status"], 401);
+
+        let request = captured.await.unwrap();
+        assert_eq!(request.header("x-api-key"), Some("foundry-key"));
+        assert!(request.body.contains("sonnet-prod"));
+    }
+
+    #[tokio::test]
+    async fn test_foundry_entra_token_refreshed_once_for_concurrent_callers() {
+        let _pool = crate::test_support::isolated_pool().await;
+        // 桩服务只响应一次，第二次换取令牌会连接失败
+        let (authority, captured) = crate::test_support::spawn_stub(
+            200,
+            "application/json",
+            r#"{"access_token":"entra-token","expires_in":3600}"#,
+        )
+        .await;
+        let id = create_credential(
+            "foundry",
+            serde_json::json!({
+                "endpoint": "https://res.services.ai.azure.com",
+                "tenant_id": "tenant-1",
+                "client_id": "client-1",
+                "client_secret": "secret",
+                "authority_host": authority
+            }),
+        )
+        .await
+        .unwrap();
+
+        let (first, second) = tokio::join!(ensure_access_token(&id), ensure_access_token(&id));
+        assert_eq!(first.unwrap().access_token.as_deref(), Some("entra-token"));
+        assert_eq!(second.unwrap().access_token.as_deref(), Some("entra-token"));
+        assert_eq!(captured.await.unwrap().path, "/tenant-1/oauth2/v2.0/token");
+        assert_eq!(
+            CREDENTIALS.read().await[&id].access_token.as_deref(),
+            Some("entra-token")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ccr_alias_and_discovered_models_gate_acquire() {
+        let _pool = crate::test_support::isolated_pool().await;
+        let (url, _captured) = crate::test_support::spawn_stub(
+            200,
+            "application/json",
+            r#"{"data":[{"id":"sonnet-4.5","type":"model"}],"has_more":false}"#,
+        )
+        .await;
+        let id = create_credential(
+            "ccr",
+            serde_json::json!({
+                "api_key": "relay-key",
+                "base_url": url,
+                "model_map": { "claude-sonnet-4-5-20250929": "sonnet-4.5" },
+                "denied_models": ["claude-3-*"]
+            }),
+        )
+        .await
+        .unwrap();
+
+        let discovered = discover_models(&id).await.unwrap();
+        assert_eq!(discovered.models, vec!["sonnet-4.5"]);
+
+        let creds = CREDENTIALS.read().await;
+        let credential = &creds[&id];
+        assert!(check_model_available(credential, "claude-sonnet-4-5-20250929").is_ok());
+        assert!(check_model_available(credential, "claude-opus-4-5-20251101").is_err());
+        assert!(check_model_available(credential, "claude-3-5-haiku-20241022").is_err());
+        drop(creds);
+
+        let request = transform_request(
+            serde_json::json!({ "model": "claude-sonnet-4-5-20250929", "max_tokens": 1 }),
+            Some(&id),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(request.source_format, SourceFormat::Anthropic);
+        assert_eq!(request.request["model"], "sonnet-4.5");
+    }
+
+    #[tokio::test]
+    async fn test_ccr_probe_uses_first_allowed_model() {
+        let _pool = crate::test_support::isolated_pool().await;
+        let (url, captured) = crate::test_support::spawn_stub_sequence(vec![
+            (200, "application/json", r#"{"data":[]}"#),
+            (
+                200,
+                "application/json",
+                r#"{"type":"message","content":[]}"#,
+            ),
+        ])
+        .await;
+        let id = create_credential(
+            "ccr",
+            serde_json::json!({
+                "api_key": "relay-key",
+                "base_url": url,
+                "denied_models": ["claude-opus-*", "claude-haiku-*"]
+            }),
+        )
+        .await
+        .unwrap();
+        CREDENTIALS
+            .write()
+            .await
+            .get_mut(&id)
+            .unwrap()
+            .available_models = Some(vec![
+            "claude-opus-probe-test".to_string(),
+            "claude-sonnet-probe-test".to_string(),
+        ]);
+
+        let result = validate_credential(&id, true).await.unwrap();
+        assert!(result.valid);
+        let requests = captured.await.unwrap();
+        assert_eq!(requests[1].path, "/v1/messages");
+        assert!(requests[1]
+            .body
+            .contains("\"model\":\"claude-sonnet-probe-test\""));
+    }
+
+    #[tokio::test]
+    async fn test_ccr_key_rotation_and_release() {
+        let _pool = crate::test_support::isolated_pool().await;
+        let id = create_credential(
+            "ccr",
+            serde_json::json!({
+                "name": "rotation-relay",
+                "base_url": "https://relay.example.com",
+                "api_keys": ["rotation-key-a", "rotation-key-b"],
+                "allowed_models": ["claude-haiku-4-5-20251001"]
+            }),
+        )
+        .await
+        .unwrap();
+
+        let first = acquire_credential("claude-haiku-4-5-20251001")
+            .await
+            .unwrap();
+        let second = acquire_credential("claude-haiku-4-5-20251001")
+            .await
+            .unwrap();
+        assert_eq!(first.id, id);
+        assert_eq!(first.headers["x-api-key"], "rotation-key-a");
+        assert_eq!(second.headers["x-api-key"], "rotation-key-b");
+
+        // 限流只冷却对应的 Key，凭证本身保持健康
+        release_credential(
+            &id,
+            serde_json::json!({
+                "metadata": first.metadata,
+                "error": { "status_code": 429, "message": "rate limited", "mark_unhealthy": true }
+            }),
+        )
+        .await
+        .unwrap();
+        let third = acquire_credential("claude-haiku-4-5-20251001")
+            .await
+            .unwrap();
+        assert_eq!(third.headers["x-api-key"], "rotation-key-b");
+
+        release_credential(
+            &id,
+            serde_json::json!({
+                "metadata": third.metadata,
+                "error": { "status_code": 429, "message": "rate limited" }
+            }),
+        )
+        .await
+        .unwrap();
+
+        let creds = CREDENTIALS.read().await;
+        let credential = &creds[&id];
+        assert_eq!(credential.health_state, HealthState::Healthy);
+        let state = &credential.api_key_states[&api_key_id("rotation-key-a")];
+        assert!(state.cooldown_until.is_some());
+        assert_eq!(state.error_count, 1);
+
+        // 所有 Key 冷却时给出凭证 ID 与最早恢复时间
+        let error = check_ccr_keys(&id, credential, Utc::now()).unwrap_err();
+        match error.downcast_ref::<PluginError>() {
+            Some(PluginError::KeysCoolingDown {
+                credential_id,
+                retry_after,
+                ..
+            }) => {
+                assert_eq!(credential_id, &id);
+                assert!(retry_after.is_some_and(|s| s > 0));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_acquire_prefers_rate_limit_headroom() {
+        let _pool = crate::test_support::isolated_pool().await;
+        let mut ids = Vec::new();
+        for name in ["headroom-a", "headroom-b"] {
+            let id = create_credential(
+                "ccr",
+                serde_json::json!({
+                    "name": name,
+                    "base_url": "https://relay.example.com",
+                    "api_key": format!("{}-key", name),
+                    "allowed_models": ["claude-headroom-test"]
+                }),
+            )
+            .await
+            .unwrap();
+            ids.push(id);
+        }
+
+        // 两个凭证都报告额度，剩余较多的优先
+        for (id, remaining) in ids.iter().zip(["5", "80"]) {
+            release_credential(
+                id,
+                serde_json::json!({
+                    "headers": {
+                        "anthropic-ratelimit-requests-limit": "100",
+                        "anthropic-ratelimit-requests-remaining": remaining
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+        }
+        let acquired = acquire_credential("claude-headroom-test").await.unwrap();
+        assert_eq!(acquired.id, ids[1]);
+
+        let summaries = list_credentials().await;
+        let summary = summaries.iter().find(|s| s.id == ids[0]).unwrap();
+        assert_eq!(summary.headroom, 0.05);
+        assert_eq!(
+            summary.rate_limits.requests.as_ref().unwrap().remaining,
+            Some(5)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_release_accumulates_usage_and_cost() {
+        let _pool = crate::test_support::isolated_pool().await;
+        let id = create_credential(
+            "ccr",
+            serde_json::json!({
+                "name": "usage-relay",
+                "base_url": "https://relay.example.com",
+                "api_key": "usage-key",
+                "allowed_models": ["claude-usage-test"]
+            }),
+        )
+        .await
+        .unwrap();
+
+        for _ in 0..2 {
+            release_credential(
+                &id,
+                serde_json::json!({
+                    "model": "claude-haiku-4-5-20251001",
+                    "usage": { "input_tokens": 500000, "output_tokens": 100000, "cache_read_input_tokens": 1000000 }
+                }),
+            )
+            .await
+            .unwrap();
+        }
+        release_credential(&id, serde_json::json!({ "usage": { "input_tokens": 10 } }))
+            .await
+            .unwrap();
+
+        let report = usage_stats(Some(&id)).await.unwrap();
+        assert_eq!(report.credentials.len(), 1);
+        let haiku = &report.models["claude-haiku-4-5-20251001"];
+        assert_eq!(haiku.requests, 2);
+        assert_eq!(haiku.input_tokens, 1_000_000);
+        // 每次 0.5 + 0.5 + 0.1 美元
+        assert!((haiku.cost_usd - 2.2).abs() < 1e-9);
+        assert_eq!(report.models[UNKNOWN_MODEL].unpriced_requests, 1);
+        assert_eq!(report.total.requests, 3);
+        assert!(usage_stats(Some("missing")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stream_transform_session() {
+        let stream_id = open_stream_transform(None, SourceFormat::Gemini, false)
+            .await
+            .unwrap();
+        let first = feed_stream_chunk(
+            &stream_id,
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"model\":\"m\"}}\n\nevent: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,".as_bytes(),
+        )
+        .await
+        .unwrap();
+        assert!(first.is_empty());
+
+        let second = feed_stream_chunk(
+            &stream_id,
+            "\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n".as_bytes(),
+        )
+        .await
+        .unwrap();
+        assert!(second.starts_with("data: {"));
+        assert!(second.contains("\"text\":\"Hi\""));
+
+        close_stream_transform(&stream_id).await.unwrap();
+        assert!(feed_stream_chunk(&stream_id, b"data: {}\n\n")
+            .await
+            .is_err());
+
+        // 超时的会话在输入时清理
+        let expired = open_stream_transform(None, SourceFormat::Anthropic, false)
+            .await
+            .unwrap();
+        STREAM_SESSIONS
+            .write()
+            .await
+            .get_mut(&expired)
+            .unwrap()
+            .opened_at -= chrono::Duration::seconds(STREAM_SESSION_TTL_SECONDS + 1);
+        assert!(feed_stream_chunk(&expired, b"data: {}\n\n").await.is_err());
+        assert!(!STREAM_SESSIONS.read().await.contains_key(&expired));
+    }
+
+    #[tokio::test]
+    async fn test_credit_balance_error_retries_on_other_credential() {
+        let _pool = crate::test_support::isolated_pool().await;
+        let error = parse_error(
+            400,
+            r#"{"type":"error","error":{"type":"invalid_request_error","message":"Your credit balance is too low to access the Anthropic API. Please go to Plans & Billing to upgrade or purchase credits."}}"#,
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert!(error.retryable);
+        assert_eq!(error.cooldown_seconds, None);
+
+        let id = create_credential(
+            "ccr",
+            serde_json::json!({
+                "api_key": "credit-key",
+                "base_url": "https://relay.example.com",
+                "allowed_models": ["claude-credit-test"]
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            acquire_credential("claude-credit-test").await.unwrap().id,
+            id
+        );
+
+        // 调用方按 retryable 重试前先 release：凭证被停用，重试不会再选中它
+        release_credential(&id, serde_json::json!({ "error": error }))
+            .await
+            .unwrap();
+        assert_eq!(
+            CREDENTIALS.read().await[&id]
+                .disabled
+                .as_ref()
+                .map(|d| d.reason),
+            Some(DisabledReason::BillingExhausted)
+        );
+        let retry = acquire_credential("claude-credit-test").await.unwrap_err();
+        assert_eq!(
+            crate::error::find_plugin_error(&retry).map(|e| e.code()),
+            Some("no_healthy_credential")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_terminal_error_disables_until_enabled() {
+        let _pool = crate::test_support::isolated_pool().await;
+        assert_eq!(
+            classify_terminal_error(
+                403,
+                Some("permission_error"),
+                "This organization has been disabled."
+            ),
+            Some(DisabledReason::OrganizationDisabled)
+        );
+        assert_eq!(
+            classify_terminal_error(
+                403,
+                Some("permission_error"),
+                "OAuth token does not meet scope requirement user:inference"
+            ),
+            Some(DisabledReason::MissingScope)
+        );
+        assert_eq!(
+            classify_terminal_error(402, Some("billing_error"), "Payment required"),
+            Some(DisabledReason::BillingExhausted)
+        );
+        assert_eq!(
+            classify_terminal_error(429, None, "Your account has been suspended."),
+            None
+        );
+        assert_eq!(
+            classify_terminal_error(403, None, "Permission denied"),
+            None
+        );
+        // 只按已知错误信息匹配，不按关键词猜测
+        assert_eq!(
+            classify_terminal_error(
+                400,
+                Some("invalid_request_error"),
+                "billing address is invalid for this account, which is disabled for tools"
+            ),
+            None
+        );
+        // 错误类型不符时不停用
+        assert_eq!(
+            classify_terminal_error(
+                400,
+                Some("invalid_request_error"),
+                "Your account has been disabled"
+            ),
+            None
+        );
+
+        // 模型无权使用：切换凭证重试，并只停用该模型
+        let denied = parse_error(
+            403,
+            r#"{"type":"error","error":{"type":"permission_error","message":"Your organization does not have access to model claude-opus-4-1"}}"#,
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(denied.error_type, "model_not_found");
+        assert!(denied.retryable);
+        assert_eq!(denied.disable_reason, Some(DisabledReason::ModelNotAllowed));
+        let missing = parse_error(
+            404,
+            r#"{"type":"error","error":{"type":"not_found_error","message":"model: claude-opus-9"}}"#,
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(missing.disable_reason, None);
+        // Bedrock 列表接口提供、但账户未开通访问权限的模型
+        let bedrock_denied = parse_error(
+            403,
+            r#"{"message":"You don't have access to the model with the specified model ID."}"#,
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(bedrock_denied.error_type, "model_not_found");
+        assert_eq!(
+            bedrock_denied.disable_reason,
+            Some(DisabledReason::ModelNotAllowed)
+        );
+
+        let id = create_credential(
+            "ccr",
+            serde_json::json!({
+                "api_key": "suspended-key",
+                "base_url": "https://relay.example.com"
+            }),
+        )
+        .await
+        .unwrap();
+        // 转发的 ProviderError 不带 disable_reason 时按 error_type / status 重新识别
+        let mut forwarded = serde_json::to_value(&denied).unwrap();
+        forwarded["disable_reason"] = serde_json::Value::Null;
+        release_credential(
+            &id,
+            serde_json::json!({ "model": "claude-opus-4-1", "error": forwarded }),
+        )
+        .await
+        .unwrap();
+        {
+            let creds = CREDENTIALS.read().await;
+            let credential = &creds[&id];
+            assert!(credential.disabled.is_none());
+            assert!(check_model_available(credential, "claude-opus-4-1").is_err());
+            assert!(check_model_available(credential, "claude-sonnet-4-5").is_ok());
+        }
+        // 成功调用不会恢复被停用的模型
+        release_credential(&id, serde_json::json!({ "model": "claude-sonnet-4-5" }))
+            .await
+            .unwrap();
+        assert!(CREDENTIALS.read().await[&id]
+            .blocked_models
+            .contains_key("claude-opus-4-1"));
+
+        release_credential(
+            &id,
+            serde_json::json!({
+                "error": {
+                    "status": 403,
+                    "error_type": "authorization",
+                    "message": "Your account has been suspended."
+                }
+            }),
+        )
+        .await
+        .unwrap();
+
+        let summary = list_credentials()
+            .await
+            .into_iter()
+            .find(|c| c.id == id)
+            .unwrap();
+        let disabled = summary.disabled.unwrap();
+        assert_eq!(disabled.reason, DisabledReason::AccountSuspended);
+        assert_eq!(
+            disabled.message.as_deref(),
+            Some("Your account has been suspended.")
+        );
+
+        // 成功调用不会自动恢复，只能由管理员重新启用
+        release_credential(&id, serde_json::json!({}))
+            .await
+            .unwrap();
+        assert!(CREDENTIALS.read().await[&id].disabled.is_some());
+        let validation = validate_credential(&id, false).await.unwrap();
+        assert!(!validation.valid);
+        assert_eq!(
+            validation.message.as_deref(),
+            Some("凭证已停用: account_suspended")
+        );
+
+        enable_credential(&id).await.unwrap();
+        assert!(CREDENTIALS.read().await[&id].disabled.is_none());
+        assert!(CREDENTIALS.read().await[&id].blocked_models.is_empty());
+        assert!(validate_credential(&id, false).await.unwrap().valid);
+    }
+
+    #[tokio::test]
+    async fn test_failure_categories_map_to_rpc_codes() {
+        let rpc_code = |error: anyhow::Error| crate::error::to_rpc_error(&error).0;
+
+        // 配置错误
+        let bad_key = crate::auth::vertex::ServiceAccountKey::from_value(&serde_json::json!({
+            "client_email": "sa@example.iam.gserviceaccount.com",
+            "private_key": "not a pem"
+        }))
+        .and_then(|key| crate::auth::vertex::sign_jwt(&key, "aud", Utc::now()).map(|_| key));
+        assert_eq!(rpc_code(bad_key.unwrap_err()), -32007);
+        assert_eq!(
+            rpc_code(
+                create_credential("unknown", serde_json::json!({}))
+                    .await
+                    .unwrap_err()
+            ),
+            -32007
+        );
+
+        // 不支持的模型
+        let deployments = HashMap::from([("claude-a".to_string(), "a".to_string())]);
+        let missing = crate::auth::foundry::resolve_deployment("claude-b", &deployments);
+        assert_eq!(rpc_code(missing.unwrap_err()), -32001);
+
+        // 凭证不存在
+        assert_eq!(
+            rpc_code(enable_credential("no-such-credential").await.unwrap_err()),
+            -32003
+        );
+
+        // 刷新失败与 refresh_token 失效
+        let (authority, _) = crate::test_support::spawn_stub(500, "text/plain", "oops").await;
+        let failed = crate::auth::foundry::fetch_entra_token(Some(&authority), "t", "c", "s").await;
+        assert_eq!(rpc_code(failed.unwrap_err()), -32011);
+        let (authority, _) = crate::test_support::spawn_stub(
+            400,
+            "application/json",
+            r#"{"error":"invalid_grant"}"#,
+        )
+        .await;
+        let revoked =
+            crate::auth::foundry::fetch_entra_token(Some(&authority), "t", "c", "s").await;
+        assert_eq!(rpc_code(revoked.unwrap_err()), -32005);
     }
 }
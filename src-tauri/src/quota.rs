@@ -0,0 +1,382 @@
+//! 限流额度跟踪
+//!
+//! 根据响应中的 `anthropic-ratelimit-*-limit / -remaining / -reset` 记录每个凭证的剩余请求数与
+//! Token 额度；响应没有带限流头时按 usage 扣减上一次记录的剩余额度。
+//! Pro / Max 订阅另有 5 小时与每周滚动窗口（`anthropic-ratelimit-unified-*`），记录已用比例、
+//! 重置时间，并按当前消耗速度推算用尽时间；窗口用尽的凭证在重置前不参与选择。
+//! 选择凭证时以剩余额度比例（headroom）作为同一健康等级内的优先依据，接近窗口上限的凭证排在后面。
+
+use crate::credentials::{
+    ClaudeCredentials, RateLimitQuota, RateLimitState, UnifiedRateLimit, UsageWindow,
+};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+/// 限流维度及对应的 `anthropic-ratelimit-*` 响应头前缀
+pub const RATE_LIMIT_HEADERS: &[(&str, &str)] = &[
+    ("requests", "anthropic-ratelimit-requests"),
+    ("input_tokens", "anthropic-ratelimit-input-tokens"),
+    ("output_tokens", "anthropic-ratelimit-output-tokens"),
+    ("tokens", "anthropic-ratelimit-tokens"),
+];
+
+/// 订阅窗口名称、`anthropic-ratelimit-unified-*` 响应头前缀及窗口长度（小时）
+pub const UNIFIED_WINDOWS: &[(&str, &str, i64)] = &[
+    ("five_hour", "anthropic-ratelimit-unified-5h", 5),
+    ("seven_day", "anthropic-ratelimit-unified-7d", 7 * 24),
+];
+
+/// 订阅窗口已用比例达到该值时视为接近上限
+pub const NEAR_LIMIT_UTILIZATION: f64 = 0.9;
+
+/// 一次响应消耗的 Token
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UsageTokens {
+    /// 计入输入限额的 Token（含缓存写入）
+    pub input: u64,
+    pub output: u64,
+}
+
+impl UsageTokens {
+    /// 解析 Anthropic `usage` 对象
+    pub fn from_usage(usage: &serde_json::Value) -> Option<Self> {
+        let usage = usage.as_object()?;
+        let field = |name: &str| usage.get(name).and_then(|v| v.as_u64()).unwrap_or(0);
+        Some(Self {
+            input: field("input_tokens") + field("cache_creation_input_tokens"),
+            output: field("output_tokens"),
+        })
+    }
+}
+
+fn quota_mut<'a>(state: &'a mut RateLimitState, kind: &str) -> &'a mut Option<RateLimitQuota> {
+    match kind {
+        "requests" => &mut state.requests,
+        "input_tokens" => &mut state.input_tokens,
+        "output_tokens" => &mut state.output_tokens,
+        _ => &mut state.tokens,
+    }
+}
+
+/// 根据响应头更新限流额度，返回响应头中是否带有限流信息
+pub fn record_rate_limit_headers(
+    state: &mut RateLimitState,
+    headers: &HashMap<String, String>,
+    now: DateTime<Utc>,
+) -> bool {
+    let number = |name: String| headers.get(&name)?.trim().parse::<u64>().ok();
+    let mut updated = false;
+    for (kind, prefix) in RATE_LIMIT_HEADERS {
+        let limit = number(format!("{}-limit", prefix));
+        let remaining = number(format!("{}-remaining", prefix));
+        if limit.is_none() && remaining.is_none() {
+            continue;
+        }
+        let reset_at = headers
+            .get(&format!("{}-reset", prefix))
+            .and_then(|v| DateTime::parse_from_rfc3339(v.trim()).ok())
+            .map(|at| at.with_timezone(&Utc));
+        *quota_mut(state, kind) = Some(RateLimitQuota {
+            limit,
+            remaining,
+            reset_at,
+        });
+        updated = true;
+    }
+    if updated {
+        state.updated_at = Some(now);
+    }
+    updated
+}
+
+/// 响应没有带限流头时，按本次消耗扣减已记录的剩余额度
+pub fn record_usage(state: &mut RateLimitState, usage: UsageTokens) {
+    let consumed = [
+        ("requests", 1),
+        ("input_tokens", usage.input),
+        ("output_tokens", usage.output),
+        ("tokens", usage.input + usage.output),
+    ];
+    for (kind, amount) in consumed {
+        if let Some(remaining) = quota_mut(state, kind)
+            .as_mut()
+            .and_then(|q| q.remaining.as_mut())
+        {
+            *remaining = remaining.saturating_sub(amount);
+        }
+    }
+}
+
+/// 剩余额度比例：各维度 remaining / limit 的最小值
+///
+/// 重置时间已过或没有数据的维度视为额度充足（1.0）。
+pub fn headroom(state: &RateLimitState, now: DateTime<Utc>) -> f64 {
+    [
+        &state.requests,
+        &state.input_tokens,
+        &state.output_tokens,
+        &state.tokens,
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|q| q.reset_at.is_none_or(|at| at > now))
+    .filter_map(|q| match (q.remaining, q.limit) {
+        (Some(remaining), Some(limit)) if limit > 0 => {
+            Some((remaining as f64 / limit as f64).min(1.0))
+        }
+        (Some(0), _) => Some(0.0),
+        _ => None,
+    })
+    .fold(1.0, f64::min)
+}
+
+/// 解析窗口重置时间（Unix 秒或 RFC3339）
+fn parse_reset(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    match value.parse::<i64>() {
+        Ok(seconds) => DateTime::from_timestamp(seconds, 0),
+        Err(_) => DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|at| at.with_timezone(&Utc)),
+    }
+}
+
+fn window_mut<'a>(limits: &'a mut UnifiedRateLimit, name: &str) -> &'a mut Option<UsageWindow> {
+    match name {
+        "five_hour" => &mut limits.five_hour,
+        _ => &mut limits.seven_day,
+    }
+}
+
+/// 按窗口开始以来的平均消耗速度推算用尽时间，预计在重置前用尽时返回
+pub fn project_exhaustion(
+    utilization: f64,
+    reset_at: DateTime<Utc>,
+    window: Duration,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    if utilization >= 1.0 {
+        return Some(now);
+    }
+    let elapsed = (now - (reset_at - window)).num_seconds();
+    if utilization <= 0.0 || elapsed <= 0 {
+        return None;
+    }
+    let remaining = (elapsed as f64 * (1.0 - utilization) / utilization) as i64;
+    let exhausted_at = now + Duration::seconds(remaining);
+    (exhausted_at < reset_at).then_some(exhausted_at)
+}
+
+/// 根据 `anthropic-ratelimit-unified-*` 响应头更新订阅窗口，返回响应头中是否带有窗口信息
+pub fn record_unified_headers(
+    limits: &mut UnifiedRateLimit,
+    headers: &HashMap<String, String>,
+    now: DateTime<Utc>,
+) -> bool {
+    let header = |name: String| headers.get(&name).map(|v| v.trim().to_string());
+    let mut updated = false;
+    for (name, prefix, hours) in UNIFIED_WINDOWS {
+        let utilization =
+            header(format!("{}-utilization", prefix)).and_then(|v| v.parse::<f64>().ok());
+        let status = header(format!("{}-status", prefix));
+        let reset_at = header(format!("{}-reset", prefix)).and_then(|v| parse_reset(&v));
+        if utilization.is_none() && status.is_none() && reset_at.is_none() {
+            continue;
+        }
+        let projected_exhaustion_at = match (utilization, reset_at) {
+            (Some(utilization), Some(reset_at)) => {
+                project_exhaustion(utilization, reset_at, Duration::hours(*hours), now)
+            }
+            _ => None,
+        };
+        *window_mut(limits, name) = Some(UsageWindow {
+            utilization,
+            status,
+            reset_at,
+            projected_exhaustion_at,
+        });
+        updated = true;
+    }
+
+    if let Some(status) = header("anthropic-ratelimit-unified-status".to_string()) {
+        limits.status = Some(status);
+        limits.reset_at =
+            header("anthropic-ratelimit-unified-reset".to_string()).and_then(|v| parse_reset(&v));
+        limits.representative_claim =
+            header("anthropic-ratelimit-unified-representative-claim".to_string());
+        updated = true;
+    }
+    if updated {
+        limits.updated_at = Some(now);
+    }
+    updated
+}
+
+/// 尚未重置的订阅窗口
+fn active_windows(
+    limits: &UnifiedRateLimit,
+    now: DateTime<Utc>,
+) -> impl Iterator<Item = &UsageWindow> {
+    [&limits.five_hour, &limits.seven_day]
+        .into_iter()
+        .flatten()
+        .filter(move |w| w.reset_at.is_none_or(|at| at > now))
+}
+
+/// 订阅窗口已用尽时返回最晚的重置时间
+pub fn unified_blocked_until(
+    limits: &UnifiedRateLimit,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let overall = limits
+        .reset_at
+        .filter(|at| limits.status.as_deref() == Some("rejected") && *at > now);
+    active_windows(limits, now)
+        .filter(|w| {
+            w.status.as_deref() == Some("rejected") || w.utilization.is_some_and(|u| u >= 1.0)
+        })
+        .filter_map(|w| w.reset_at)
+        .chain(overall)
+        .max()
+}
+
+/// 订阅窗口是否接近上限（已用比例过高，或按当前速度会在重置前用尽）
+pub fn near_unified_limit(limits: &UnifiedRateLimit, now: DateTime<Utc>) -> bool {
+    active_windows(limits, now).any(|w| {
+        w.utilization.is_some_and(|u| u >= NEAR_LIMIT_UTILIZATION)
+            || w.status.as_deref() == Some("allowed_warning")
+            || w.projected_exhaustion_at.is_some()
+    })
+}
+
+/// 凭证的剩余额度比例：限流额度与订阅窗口剩余比例中的最小值
+pub fn credential_headroom(credential: &ClaudeCredentials, now: DateTime<Utc>) -> f64 {
+    active_windows(&credential.unified_limits, now)
+        .filter_map(|w| w.utilization)
+        .map(|u| (1.0 - u).clamp(0.0, 1.0))
+        .fold(headroom(&credential.rate_limits, now), f64::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers_and_headroom() {
+        let now = Utc::now();
+        let reset = (now + chrono::Duration::seconds(30)).to_rfc3339();
+        let headers: HashMap<String, String> = [
+            ("anthropic-ratelimit-requests-limit", "50"),
+            ("anthropic-ratelimit-requests-remaining", "40"),
+            ("anthropic-ratelimit-requests-reset", reset.as_str()),
+            ("anthropic-ratelimit-input-tokens-limit", "10000"),
+            ("anthropic-ratelimit-input-tokens-remaining", "2500"),
+            ("anthropic-ratelimit-input-tokens-reset", reset.as_str()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let mut state = RateLimitState::default();
+        assert!(record_rate_limit_headers(&mut state, &headers, now));
+        assert_eq!(state.requests.as_ref().unwrap().remaining, Some(40));
+        assert!(state.output_tokens.is_none());
+        assert_eq!(headroom(&state, now), 0.25);
+
+        // 重置时间过后额度视为恢复
+        assert_eq!(headroom(&state, now + chrono::Duration::seconds(60)), 1.0);
+        assert!(!record_rate_limit_headers(&mut state, &HashMap::new(), now));
+    }
+
+    #[test]
+    fn test_unified_windows() {
+        let now = Utc::now();
+        // 5 小时窗口已过去 4 小时，用掉 40%，重置前不会用尽；每周窗口过半已用 80%，会提前用尽
+        let five_hour_reset = now + Duration::hours(1);
+        let seven_day_reset = now + Duration::hours(84);
+        let headers: HashMap<String, String> = [
+            ("anthropic-ratelimit-unified-status", "allowed".to_string()),
+            (
+                "anthropic-ratelimit-unified-representative-claim",
+                "seven_day".to_string(),
+            ),
+            (
+                "anthropic-ratelimit-unified-5h-utilization",
+                "0.4".to_string(),
+            ),
+            (
+                "anthropic-ratelimit-unified-5h-reset",
+                five_hour_reset.timestamp().to_string(),
+            ),
+            (
+                "anthropic-ratelimit-unified-7d-utilization",
+                "0.8".to_string(),
+            ),
+            (
+                "anthropic-ratelimit-unified-7d-reset",
+                seven_day_reset.timestamp().to_string(),
+            ),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+
+        let mut limits = UnifiedRateLimit::default();
+        assert!(record_unified_headers(&mut limits, &headers, now));
+        let five_hour = limits.five_hour.as_ref().unwrap();
+        assert_eq!(five_hour.utilization, Some(0.4));
+        assert!(five_hour.projected_exhaustion_at.is_none());
+        let seven_day = limits.seven_day.as_ref().unwrap();
+        let exhausted_at = seven_day.projected_exhaustion_at.unwrap();
+        assert!(
+            (exhausted_at - (now + Duration::hours(21)))
+                .num_seconds()
+                .abs()
+                <= 1
+        );
+        assert!(near_unified_limit(&limits, now));
+        assert!(unified_blocked_until(&limits, now).is_none());
+
+        // 窗口用尽后在重置前不可用，重置后恢复
+        limits.five_hour.as_mut().unwrap().status = Some("rejected".to_string());
+        let reset = limits.five_hour.as_ref().unwrap().reset_at;
+        assert_eq!(unified_blocked_until(&limits, now), reset);
+        assert!(unified_blocked_until(&limits, now + Duration::hours(2)).is_none());
+    }
+
+    #[test]
+    fn test_usage_decrements_remaining() {
+        let mut state = RateLimitState {
+            requests: Some(RateLimitQuota {
+                limit: Some(10),
+                remaining: Some(1),
+                reset_at: None,
+            }),
+            input_tokens: Some(RateLimitQuota {
+                limit: Some(1000),
+                remaining: Some(800),
+                reset_at: None,
+            }),
+            ..Default::default()
+        };
+        let usage = UsageTokens::from_usage(&serde_json::json!({
+            "input_tokens": 100,
+            "cache_creation_input_tokens": 50,
+            "cache_read_input_tokens": 400,
+            "output_tokens": 20
+        }))
+        .unwrap();
+        assert_eq!(
+            usage,
+            UsageTokens {
+                input: 150,
+                output: 20
+            }
+        );
+
+        record_usage(&mut state, usage);
+        assert_eq!(state.requests.as_ref().unwrap().remaining, Some(0));
+        assert_eq!(state.input_tokens.as_ref().unwrap().remaining, Some(650));
+        assert_eq!(headroom(&state, Utc::now()), 0.0);
+    }
+}
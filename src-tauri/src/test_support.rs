@@ -0,0 +1,134 @@
+//! 测试辅助工具
+//!
+//! 提供本地 HTTP 桩服务，用于替代 STS、OAuth 等远程端点；以及全局凭证池的测试隔离。
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::{Mutex, MutexGuard};
+use tokio::task::JoinHandle;
+
+lazy_static::lazy_static! {
+    /// 串行化使用全局凭证池的测试
+    static ref POOL_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// 独占全局凭证池
+///
+/// 进入时清空凭证池，持有期间其他使用凭证池的测试等待，
+/// 避免 `acquire_credential` 或健康探测选中其他测试创建的凭证。
+pub async fn isolated_pool() -> MutexGuard<'static, ()> {
+    let guard = POOL_LOCK.lock().await;
+    crate::provider::CREDENTIALS.write().await.clear();
+    guard
+}
+
+/// 捕获到的 HTTP 请求
+#[derive(Debug, Clone)]
+pub struct CapturedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl CapturedRequest {
+    /// 按名称（不区分大小写）查找请求头
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// 启动一个只处理一次请求的本地 HTTP 桩服务
+///
+/// 返回服务地址（`http://127.0.0.1:port`）以及捕获请求的任务句柄。
+pub async fn spawn_stub(
+    status: u16,
+    content_type: &str,
+    body: &str,
+) -> (String, JoinHandle<CapturedRequest>) {
+    let (url, handle) = spawn_stub_sequence(vec![(status, content_type, body)]).await;
+    let handle = tokio::spawn(async move { handle.await.unwrap().remove(0) });
+    (url, handle)
+}
+
+/// 启动一个按顺序返回多个响应的本地 HTTP 桩服务
+pub async fn spawn_stub_sequence(
+    responses: Vec<(u16, &str, &str)>,
+) -> (String, JoinHandle<Vec<CapturedRequest>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let responses: Vec<(u16, String, String)> = responses
+        .into_iter()
+        .map(|(status, content_type, body)| (status, content_type.to_string(), body.to_string()))
+        .collect();
+
+    let handle = tokio::spawn(async move {
+        let mut captured = Vec::new();
+        for (status, content_type, body) in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            captured.push(read_request(&mut socket).await);
+            let response = format!(
+                "HTTP/1.1 {} OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                content_type,
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            socket.shutdown().await.ok();
+        }
+        captured
+    });
+
+    (url, handle)
+}
+
+async fn read_request(socket: &mut tokio::net::TcpStream) -> CapturedRequest {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = socket.read(&mut chunk).await.unwrap();
+        if n == 0 {
+            break buf.len();
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.lines();
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, v)| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    while buf.len() < header_end + content_length {
+        let n = socket.read(&mut chunk).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let body = String::from_utf8_lossy(&buf[header_end..]).to_string();
+
+    CapturedRequest {
+        method,
+        path,
+        headers,
+        body,
+    }
+}
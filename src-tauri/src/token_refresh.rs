@@ -1,11 +1,14 @@
 //! Token 刷新逻辑
 //!
-//! 支持 OAuth、Claude Code、Console 等认证方式的 Token 刷新
+//! 支持 OAuth、Claude Code、Console 等认证方式的 Token 刷新，以及 Vertex AI、Foundry Entra ID 访问令牌的重新换取
 
 #![allow(dead_code)]
 
+use crate::auth::foundry::fetch_entra_token;
 use crate::auth::oauth::refresh_oauth_token;
+use crate::auth::vertex::{fetch_access_token, ServiceAccountKey};
 use crate::credentials::{AuthType, ClaudeCredentials};
+use crate::error::PluginError;
 use anyhow::Result;
 use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
@@ -35,15 +38,35 @@ pub async fn refresh_token(credential: &mut ClaudeCredentials) -> Result<TokenRe
         }
         AuthType::SetupToken => {
             // Setup Token 没有 refresh_token，无法刷新
-            anyhow::bail!("Setup Token 不支持刷新，请重新授权")
+            Err(PluginError::refresh_not_supported(
+                credential.auth_type,
+                "Setup Token 不支持刷新，请重新授权",
+            )
+            .into())
         }
         AuthType::Bedrock => {
             // Bedrock 使用 AWS 凭证，不需要刷新
-            anyhow::bail!("Bedrock 凭证不需要刷新")
+            Err(
+                PluginError::refresh_not_supported(credential.auth_type, "Bedrock 凭证不需要刷新")
+                    .into(),
+            )
         }
+        AuthType::BedrockApiKey => {
+            // Bedrock API Key 由 AWS 控制台签发，无法刷新
+            Err(PluginError::refresh_not_supported(
+                credential.auth_type,
+                "Bedrock API Key 不需要刷新",
+            )
+            .into())
+        }
+        AuthType::Vertex => refresh_vertex_token(credential).await,
+        AuthType::Foundry => refresh_foundry_token(credential).await,
         AuthType::Ccr => {
             // CCR 使用 API Key，不需要刷新
-            anyhow::bail!("CCR 凭证不需要刷新")
+            Err(
+                PluginError::refresh_not_supported(credential.auth_type, "CCR 凭证不需要刷新")
+                    .into(),
+            )
         }
     }
 }
@@ -54,14 +77,15 @@ async fn refresh_oauth_based_token(credential: &mut ClaudeCredentials) -> Result
     let refresh_token = credential
         .refresh_token
         .as_ref()
-        .ok_or_else(|| anyhow::anyhow!("缺少 refresh_token"))?;
+        .ok_or_else(|| PluginError::invalid_config("缺少 refresh_token"))?;
 
     // 验证 refresh_token 完整性
     if refresh_token.len() < 50 {
-        anyhow::bail!(
+        return Err(PluginError::invalid_config(format!(
             "refresh_token 已被截断（长度: {} 字符）。正常的 refresh_token 长度应该更长",
             refresh_token.len()
-        );
+        ))
+        .into());
     }
 
     info!(
@@ -79,7 +103,7 @@ async fn refresh_oauth_based_token(credential: &mut ClaudeCredentials) -> Result
     }
     credential.expire = tokens.expires_at.map(|dt| dt.to_rfc3339());
     credential.last_refresh = Some(Utc::now().to_rfc3339());
-    credential.is_healthy = true;
+    crate::health::record_success(credential);
     credential.last_error = None;
 
     if let Some(ref email) = tokens.email {
@@ -96,6 +120,74 @@ async fn refresh_oauth_based_token(credential: &mut ClaudeCredentials) -> Result
     })
 }
 
+/// 使用服务账号重新换取 Vertex 访问令牌
+async fn refresh_vertex_token(credential: &mut ClaudeCredentials) -> Result<TokenRefreshResult> {
+    let key = credential
+        .service_account_json
+        .as_ref()
+        .ok_or_else(|| PluginError::invalid_config("缺少 service_account_json"))?;
+    let key = ServiceAccountKey::from_value(key)?;
+
+    let token = fetch_access_token(&key, credential.token_uri.as_deref()).await?;
+
+    credential.access_token = Some(token.access_token.clone());
+    credential.expire = Some(token.expires_at.to_rfc3339());
+    credential.last_refresh = Some(Utc::now().to_rfc3339());
+    if credential.email.is_none() {
+        credential.email = Some(key.client_email.clone());
+    }
+
+    Ok(TokenRefreshResult {
+        access_token: token.access_token,
+        refresh_token: None,
+        expires_at: Some(token.expires_at),
+        email: Some(key.client_email),
+    })
+}
+
+/// 使用 Entra ID 客户端凭证重新换取 Foundry 访问令牌
+async fn refresh_foundry_token(credential: &mut ClaudeCredentials) -> Result<TokenRefreshResult> {
+    if credential.api_key.is_some() {
+        return Err(PluginError::refresh_not_supported(
+            credential.auth_type,
+            "Foundry API Key 不需要刷新",
+        )
+        .into());
+    }
+    let (tenant_id, client_id, client_secret) = match (
+        credential.tenant_id.as_deref(),
+        credential.client_id.as_deref(),
+        credential.client_secret.as_deref(),
+    ) {
+        (Some(tenant), Some(client), Some(secret)) => (tenant, client, secret),
+        _ => {
+            return Err(PluginError::invalid_config(
+                "缺少 Entra ID 客户端凭证（tenant_id / client_id / client_secret）",
+            )
+            .into())
+        }
+    };
+
+    let token = fetch_entra_token(
+        credential.authority_host.as_deref(),
+        tenant_id,
+        client_id,
+        client_secret,
+    )
+    .await?;
+
+    credential.access_token = Some(token.access_token.clone());
+    credential.expire = Some(token.expires_at.to_rfc3339());
+    credential.last_refresh = Some(Utc::now().to_rfc3339());
+
+    Ok(TokenRefreshResult {
+        access_token: token.access_token,
+        refresh_token: None,
+        expires_at: Some(token.expires_at),
+        email: None,
+    })
+}
+
 /// 检查 Token 是否已过期
 pub fn is_token_expired(expire: Option<&str>) -> bool {
     if let Some(expire_str) = expire {
@@ -0,0 +1,509 @@
+//! Anthropic Messages 与 Gemini generateContent / streamGenerateContent 之间的转换
+//!
+//! Gemini 的模型名和是否流式由 URL 路径决定，调用方需在请求体中补充 `model` 与 `stream` 字段。
+
+use crate::transform::openai_chat::{
+    enable_thinking, function_to_anthropic_tool, image_block_from_url, json_format_instruction,
+    push_message, text_of, DEFAULT_MAX_TOKENS,
+};
+use crate::transform::sse::{format_event, SseParser};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, VecDeque};
+
+/// Gemini 请求转换为 Anthropic Messages 请求
+pub fn gemini_to_anthropic_request(request: &Value) -> Value {
+    let mut system = Vec::new();
+    let instruction = text_of(&request["systemInstruction"]["parts"]);
+    if !instruction.is_empty() {
+        system.push(instruction);
+    }
+
+    // Gemini 的 functionCall / functionResponse 可以不带 id，按名称顺序配对并生成 id
+    let mut pending: VecDeque<(String, String)> = VecDeque::new();
+    let mut call_count = 0;
+    let mut messages: Vec<Value> = Vec::new();
+    for content in request["contents"].as_array().into_iter().flatten() {
+        let role = if content["role"] == "model" {
+            "assistant"
+        } else {
+            "user"
+        };
+        let mut blocks = Vec::new();
+        for part in content["parts"].as_array().into_iter().flatten() {
+            if let Some(call) = part.get("functionCall") {
+                let name = call["name"].as_str().unwrap_or_default().to_string();
+                let id = call["id"].as_str().map(str::to_string).unwrap_or_else(|| {
+                    call_count += 1;
+                    format!("toolu_{}_{}", name, call_count)
+                });
+                pending.push_back((name, id.clone()));
+                blocks.push(json!({
+                    "type": "tool_use",
+                    "id": id,
+                    "name": call["name"],
+                    "input": if call["args"].is_object() { call["args"].clone() } else { json!({}) },
+                }));
+            } else if let Some(result) = part.get("functionResponse") {
+                let name = result["name"].as_str().unwrap_or_default();
+                let id = match result["id"].as_str() {
+                    Some(id) => {
+                        pending.retain(|(_, pending_id)| pending_id != id);
+                        id.to_string()
+                    }
+                    None => pending
+                        .iter()
+                        .position(|(pending_name, _)| pending_name == name)
+                        .and_then(|i| pending.remove(i))
+                        .map(|(_, id)| id)
+                        .unwrap_or_else(|| format!("toolu_{}", name)),
+                };
+                let output = &result["response"];
+                blocks.push(json!({
+                    "type": "tool_result",
+                    "tool_use_id": id,
+                    "content": output["output"]
+                        .as_str()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| output.to_string()),
+                }));
+            } else if part["thought"].as_bool() == Some(true) {
+                // 仅回传带签名的思考内容
+                if let Some(signature) = part["thoughtSignature"].as_str() {
+                    blocks.push(json!({
+                        "type": "thinking",
+                        "thinking": part["text"].as_str().unwrap_or_default(),
+                        "signature": signature,
+                    }));
+                }
+            } else if let Some(text) = part["text"].as_str() {
+                blocks.push(json!({ "type": "text", "text": text }));
+            } else if let Some(data) = part.get("inlineData") {
+                let media_type = data["mimeType"].as_str().unwrap_or_default();
+                let source =
+                    json!({ "type": "base64", "media_type": media_type, "data": data["data"] });
+                let kind = if media_type == "application/pdf" {
+                    "document"
+                } else {
+                    "image"
+                };
+                blocks.push(json!({ "type": kind, "source": source }));
+            } else if let Some(uri) = part["fileData"]["fileUri"].as_str() {
+                blocks.push(image_block_from_url(uri));
+            }
+        }
+        push_message(&mut messages, role, blocks);
+    }
+
+    let config = &request["generationConfig"];
+    if config["responseMimeType"] == "application/json" {
+        let schema = config
+            .get("responseJsonSchema")
+            .or_else(|| config.get("responseSchema"));
+        let kind = if schema.is_some() {
+            "json_schema"
+        } else {
+            "json_object"
+        };
+        if let Some(instruction) = json_format_instruction(
+            Some(kind),
+            None,
+            &normalize_schema(schema.unwrap_or(&Value::Null)),
+        ) {
+            system.push(instruction);
+        }
+    }
+
+    let mut max_tokens = config["maxOutputTokens"]
+        .as_u64()
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+
+    let mut body = Map::new();
+    body.insert("model".to_string(), request["model"].clone());
+    body.insert("messages".to_string(), Value::Array(messages));
+    if !system.is_empty() {
+        body.insert("system".to_string(), json!(system.join("\n\n")));
+    }
+    if let Some(temperature) = config["temperature"].as_f64() {
+        // Gemini 温度范围为 0-2，Anthropic 为 0-1
+        body.insert("temperature".to_string(), json!(temperature.min(1.0)));
+    }
+    if let Some(top_p) = config.get("topP") {
+        body.insert("top_p".to_string(), top_p.clone());
+    }
+    if let Some(top_k) = config.get("topK") {
+        body.insert("top_k".to_string(), top_k.clone());
+    }
+    if let Some(stop) = config.get("stopSequences") {
+        body.insert("stop_sequences".to_string(), stop.clone());
+    }
+    if request["stream"].as_bool() == Some(true) {
+        body.insert("stream".to_string(), json!(true));
+    }
+
+    let tools: Vec<Value> = request["tools"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|tool| {
+            tool["functionDeclarations"]
+                .as_array()
+                .into_iter()
+                .flatten()
+        })
+        .map(|declaration| {
+            let mut function = declaration.clone();
+            let parameters = declaration
+                .get("parametersJsonSchema")
+                .or_else(|| declaration.get("parameters"))
+                .map(normalize_schema);
+            function["parameters"] = parameters.unwrap_or(Value::Null);
+            function_to_anthropic_tool(&function)
+        })
+        .collect();
+    if !tools.is_empty() {
+        body.insert("tools".to_string(), Value::Array(tools));
+    }
+    let calling = &request["toolConfig"]["functionCallingConfig"];
+    let allowed = calling["allowedFunctionNames"].as_array();
+    let tool_choice = match calling["mode"].as_str() {
+        Some("NONE") => Some(json!({ "type": "none" })),
+        Some("ANY") => match allowed.map(Vec::as_slice) {
+            Some([name]) => Some(json!({ "type": "tool", "name": name })),
+            _ => Some(json!({ "type": "any" })),
+        },
+        Some("AUTO") => Some(json!({ "type": "auto" })),
+        _ => None,
+    };
+    if let Some(choice) = tool_choice {
+        body.insert("tool_choice".to_string(), choice);
+    }
+
+    // thinkingBudget 为 0 表示关闭思考，-1 表示动态预算
+    let thinking = &config["thinkingConfig"];
+    if let Some(budget) = thinking["thinkingBudget"].as_i64().filter(|b| *b != 0) {
+        let budget = if budget < 0 {
+            8192
+        } else {
+            (budget as u64).max(1024)
+        };
+        max_tokens = max_tokens.max(budget + 1024);
+        enable_thinking(&mut body, budget);
+    }
+    body.insert("max_tokens".to_string(), json!(max_tokens));
+
+    Value::Object(body)
+}
+
+/// Gemini OpenAPI 子集 schema 转换为 JSON Schema（类型名转为小写）
+fn normalize_schema(schema: &Value) -> Value {
+    match schema {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    let value = match (key.as_str(), value) {
+                        ("type", Value::String(kind)) => json!(kind.to_lowercase()),
+                        _ => normalize_schema(value),
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(normalize_schema).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Anthropic Messages 响应转换为 GenerateContentResponse
+pub fn anthropic_to_gemini_response(response: &Value) -> Value {
+    if response["type"] == "error" {
+        return gemini_error(&response["error"]);
+    }
+
+    let parts: Vec<Value> = response["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|block| match block["type"].as_str() {
+            Some("text") => Some(json!({ "text": block["text"] })),
+            Some("thinking") => Some(thought_part(
+                block["thinking"].as_str().unwrap_or_default(),
+                block["signature"].as_str(),
+            )),
+            Some("tool_use") => Some(function_call_part(block, &block["input"])),
+            _ => None,
+        })
+        .collect();
+
+    json!({
+        "candidates": [{
+            "content": { "role": "model", "parts": parts },
+            "finishReason": map_finish_reason(response["stop_reason"].as_str()),
+            "index": 0,
+        }],
+        "usageMetadata": anthropic_usage_to_gemini(&response["usage"]),
+        "modelVersion": response["model"],
+        "responseId": response["id"],
+    })
+}
+
+fn thought_part(thinking: &str, signature: Option<&str>) -> Value {
+    let mut part = json!({ "text": thinking, "thought": true });
+    if let Some(signature) = signature {
+        part["thoughtSignature"] = json!(signature);
+    }
+    part
+}
+
+fn function_call_part(block: &Value, args: &Value) -> Value {
+    json!({ "functionCall": { "id": block["id"], "name": block["name"], "args": args } })
+}
+
+/// stop_reason 映射为 finishReason
+pub fn map_finish_reason(reason: Option<&str>) -> &'static str {
+    match reason {
+        Some("max_tokens") | Some("model_context_window_exceeded") => "MAX_TOKENS",
+        Some("refusal") => "SAFETY",
+        _ => "STOP",
+    }
+}
+
+/// Anthropic usage 映射为 usageMetadata（缓存读写计入 promptTokenCount）
+pub fn anthropic_usage_to_gemini(usage: &Value) -> Value {
+    let cache_read = usage["cache_read_input_tokens"].as_u64().unwrap_or(0);
+    let prompt = usage["input_tokens"].as_u64().unwrap_or(0)
+        + cache_read
+        + usage["cache_creation_input_tokens"].as_u64().unwrap_or(0);
+    let candidates = usage["output_tokens"].as_u64().unwrap_or(0);
+    json!({
+        "promptTokenCount": prompt,
+        "candidatesTokenCount": candidates,
+        "cachedContentTokenCount": cache_read,
+        "totalTokenCount": prompt + candidates,
+    })
+}
+
+/// Anthropic 错误转换为 Google API 错误结构
+fn gemini_error(error: &Value) -> Value {
+    let (code, status) = match error["type"].as_str() {
+        Some("invalid_request_error") | Some("request_too_large") => (400, "INVALID_ARGUMENT"),
+        Some("authentication_error") => (401, "UNAUTHENTICATED"),
+        Some("permission_error") => (403, "PERMISSION_DENIED"),
+        Some("not_found_error") => (404, "NOT_FOUND"),
+        Some("rate_limit_error") => (429, "RESOURCE_EXHAUSTED"),
+        Some("overloaded_error") => (503, "UNAVAILABLE"),
+        _ => (500, "INTERNAL"),
+    };
+    json!({ "error": { "code": code, "message": error["message"], "status": status } })
+}
+
+/// Anthropic 流式事件转换为 streamGenerateContent 响应块
+///
+/// 文本与思考增量逐块输出；工具调用在参数 JSON 完整后整体输出；
+/// `message_delta` 时输出带 finishReason 与 usageMetadata 的最后一块。
+#[derive(Debug, Default)]
+pub struct AnthropicToGeminiStream {
+    id: Value,
+    model: Value,
+    usage: Value,
+    /// 内容块索引 → (工具调用块, 已累积的参数 JSON)
+    tools: HashMap<u64, (Value, String)>,
+    signatures: HashMap<u64, String>,
+}
+
+impl AnthropicToGeminiStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 输入一个 Anthropic 流式事件，返回对应的响应块
+    pub fn push_event(&mut self, event: &Value) -> Vec<Value> {
+        let index = event["index"].as_u64().unwrap_or(0);
+        match event["type"].as_str() {
+            Some("message_start") => {
+                let message = &event["message"];
+                self.id = message["id"].clone();
+                self.model = message["model"].clone();
+                self.usage = message["usage"].clone();
+                Vec::new()
+            }
+            Some("content_block_start") if event["content_block"]["type"] == "tool_use" => {
+                self.tools
+                    .insert(index, (event["content_block"].clone(), String::new()));
+                Vec::new()
+            }
+            Some("content_block_delta") => {
+                let delta = &event["delta"];
+                match delta["type"].as_str() {
+                    Some("text_delta") => vec![self.chunk(vec![json!({ "text": delta["text"] })])],
+                    Some("thinking_delta") => vec![self.chunk(vec![thought_part(
+                        delta["thinking"].as_str().unwrap_or_default(),
+                        None,
+                    )])],
+                    Some("signature_delta") => {
+                        if let Some(signature) = delta["signature"].as_str() {
+                            self.signatures.insert(index, signature.to_string());
+                        }
+                        Vec::new()
+                    }
+                    Some("input_json_delta") => {
+                        if let Some((_, buffer)) = self.tools.get_mut(&index) {
+                            buffer.push_str(delta["partial_json"].as_str().unwrap_or_default());
+                        }
+                        Vec::new()
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            Some("content_block_stop") => {
+                if let Some((block, buffer)) = self.tools.remove(&index) {
+                    let args = serde_json::from_str(&buffer).unwrap_or_else(|_| json!({}));
+                    vec![self.chunk(vec![function_call_part(&block, &args)])]
+                } else if let Some(signature) = self.signatures.remove(&index) {
+                    vec![self.chunk(vec![thought_part("", Some(&signature))])]
+                } else {
+                    Vec::new()
+                }
+            }
+            Some("message_delta") => {
+                if let (Some(usage), Some(target)) =
+                    (event["usage"].as_object(), self.usage.as_object_mut())
+                {
+                    target.extend(usage.clone());
+                } else if event["usage"].is_object() {
+                    self.usage = event["usage"].clone();
+                }
+                vec![json!({
+                    "candidates": [{
+                        "finishReason": map_finish_reason(event["delta"]["stop_reason"].as_str()),
+                        "index": 0,
+                    }],
+                    "usageMetadata": anthropic_usage_to_gemini(&self.usage),
+                    "modelVersion": self.model,
+                    "responseId": self.id,
+                })]
+            }
+            Some("error") => vec![gemini_error(&event["error"])],
+            _ => Vec::new(),
+        }
+    }
+
+    fn chunk(&self, parts: Vec<Value>) -> Value {
+        json!({
+            "candidates": [{ "content": { "role": "model", "parts": parts }, "index": 0 }],
+            "modelVersion": self.model,
+            "responseId": self.id,
+        })
+    }
+}
+
+/// 将完整的 Anthropic SSE 文本转换为 Gemini SSE 文本（`alt=sse`）
+pub fn anthropic_sse_to_gemini(text: &str) -> String {
+    let mut parser = SseParser::new();
+    let mut stream = AnthropicToGeminiStream::new();
+    let mut events = parser.feed(text);
+    events.extend(parser.finish());
+
+    let mut output = String::new();
+    for event in events {
+        if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
+            for chunk in stream.push_event(&data) {
+                output.push_str(&format_event(None, &chunk));
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gemini_to_anthropic_request() {
+        let request = json!({
+            "model": "claude-sonnet-4-5-20250929",
+            "systemInstruction": { "parts": [{ "text": "You are terse." }] },
+            "generationConfig": { "maxOutputTokens": 300, "temperature": 0.2, "topK": 40, "stopSequences": ["END"] },
+            "tools": [{ "functionDeclarations": [{
+                "name": "get_weather",
+                "description": "Weather",
+                "parameters": { "type": "OBJECT", "properties": { "city": { "type": "STRING" } } }
+            }] }],
+            "toolConfig": { "functionCallingConfig": { "mode": "ANY" } },
+            "contents": [
+                { "role": "user", "parts": [
+                    { "text": "Weather?" },
+                    { "inlineData": { "mimeType": "image/png", "data": "AAAA" } }
+                ]},
+                { "role": "model", "parts": [{ "functionCall": { "name": "get_weather", "args": { "city": "Paris" } } }] },
+                { "role": "user", "parts": [{ "functionResponse": { "name": "get_weather", "response": { "output": "Sunny" } } }] }
+            ]
+        });
+
+        let body = gemini_to_anthropic_request(&request);
+        assert_eq!(body["system"], "You are terse.");
+        assert_eq!(body["max_tokens"], 300);
+        assert_eq!(body["top_k"], 40);
+        assert_eq!(body["stop_sequences"], json!(["END"]));
+        assert_eq!(body["tool_choice"], json!({ "type": "any" }));
+        assert_eq!(
+            body["tools"][0]["input_schema"]["properties"]["city"]["type"],
+            "string"
+        );
+
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(
+            messages[0]["content"][1]["source"]["media_type"],
+            "image/png"
+        );
+        let call_id = &messages[1]["content"][0]["id"];
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], *call_id);
+        assert_eq!(messages[2]["content"][0]["content"], "Sunny");
+    }
+
+    #[test]
+    fn test_anthropic_to_gemini_response_and_stream() {
+        let response = json!({
+            "id": "msg_01",
+            "model": "claude-sonnet-4-5-20250929",
+            "content": [
+                { "type": "text", "text": "Checking." },
+                { "type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": { "city": "Paris" } }
+            ],
+            "stop_reason": "max_tokens",
+            "usage": { "input_tokens": 10, "output_tokens": 5, "cache_read_input_tokens": 20 }
+        });
+        let converted = anthropic_to_gemini_response(&response);
+        let candidate = &converted["candidates"][0];
+        assert_eq!(candidate["finishReason"], "MAX_TOKENS");
+        assert_eq!(candidate["content"]["parts"][0]["text"], "Checking.");
+        assert_eq!(
+            candidate["content"]["parts"][1]["functionCall"]["args"],
+            json!({ "city": "Paris" })
+        );
+        assert_eq!(converted["usageMetadata"]["promptTokenCount"], 30);
+        assert_eq!(converted["usageMetadata"]["totalTokenCount"], 35);
+
+        let sse = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_01\",\"model\":\"m\",\"usage\":{\"input_tokens\":4,\"output_tokens\":1}}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"f\",\"input\":{}}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"a\\\":\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"1}\"}}\n\n",
+            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+            "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"tool_use\"},\"usage\":{\"output_tokens\":9}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        );
+        let chunks: Vec<Value> = anthropic_sse_to_gemini(sse)
+            .split("\n\n")
+            .filter_map(|block| block.strip_prefix("data: "))
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(
+            chunks[0]["candidates"][0]["content"]["parts"][0]["functionCall"]["args"],
+            json!({ "a": 1 })
+        );
+        assert_eq!(chunks[1]["candidates"][0]["finishReason"], "STOP");
+        assert_eq!(chunks[1]["usageMetadata"]["candidatesTokenCount"], 9);
+    }
+}
@@ -0,0 +1,209 @@
+//! 请求 / 响应格式转换
+//!
+//! 支持 Anthropic Messages 与 OpenAI Chat Completions / Responses、Gemini 之间的互相转换：
+//! 既用于 OpenAI 协议的上游中转，也用于接收 OpenAI / Gemini 格式的入口请求
+
+pub mod gemini;
+pub mod openai_chat;
+pub mod openai_responses;
+pub mod sse;
+pub mod stream;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 入口请求格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceFormat {
+    /// Anthropic Messages API
+    #[default]
+    Anthropic,
+    /// OpenAI Chat Completions API
+    OpenaiChat,
+    /// OpenAI Responses API
+    OpenaiResponses,
+    /// Gemini generateContent / streamGenerateContent
+    Gemini,
+}
+
+impl std::fmt::Display for SourceFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceFormat::Anthropic => write!(f, "anthropic"),
+            SourceFormat::OpenaiChat => write!(f, "openai_chat"),
+            SourceFormat::OpenaiResponses => write!(f, "openai_responses"),
+            SourceFormat::Gemini => write!(f, "gemini"),
+        }
+    }
+}
+
+impl std::str::FromStr for SourceFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "anthropic" | "messages" => Ok(SourceFormat::Anthropic),
+            "openai" | "openai_chat" | "chat_completions" => Ok(SourceFormat::OpenaiChat),
+            "openai_responses" | "responses" => Ok(SourceFormat::OpenaiResponses),
+            "gemini" | "generate_content" => Ok(SourceFormat::Gemini),
+            _ => anyhow::bail!("未知的请求格式: {}", s),
+        }
+    }
+}
+
+/// 仅 OpenAI Chat Completions 使用的顶层字段
+const OPENAI_CHAT_FIELDS: &[&str] = &[
+    "max_completion_tokens",
+    "response_format",
+    "stream_options",
+    "parallel_tool_calls",
+    "reasoning_effort",
+    "frequency_penalty",
+    "presence_penalty",
+    "logprobs",
+    "seed",
+    "stop",
+    "n",
+];
+
+/// 根据请求体特征识别入口格式
+///
+/// 带 `contents` 时视为 Gemini；带 `input` 而没有 `messages` 时视为 Responses；出现 OpenAI 独有的字段、system / developer / tool 角色、tool_calls、
+/// image_url 内容或 function 类型的工具时视为 Chat Completions，否则视为 Anthropic。
+pub fn detect_source_format(request: &Value) -> SourceFormat {
+    if request.get("contents").is_some() {
+        return SourceFormat::Gemini;
+    }
+    if request.get("input").is_some() && request.get("messages").is_none() {
+        return SourceFormat::OpenaiResponses;
+    }
+    if OPENAI_CHAT_FIELDS.iter().any(|f| request.get(f).is_some())
+        || request["tool_choice"].is_string()
+        || request["tools"]
+            .as_array()
+            .is_some_and(|tools| tools.iter().any(|t| t["type"] == "function"))
+    {
+        return SourceFormat::OpenaiChat;
+    }
+
+    let openai_message = |message: &Value| {
+        matches!(
+            message["role"].as_str(),
+            Some("system") | Some("developer") | Some("tool")
+        ) || message.get("tool_calls").is_some()
+            || message["content"]
+                .as_array()
+                .is_some_and(|parts| parts.iter().any(|p| p["type"] == "image_url"))
+    };
+    if request["messages"]
+        .as_array()
+        .is_some_and(|messages| messages.iter().any(openai_message))
+    {
+        return SourceFormat::OpenaiChat;
+    }
+
+    SourceFormat::Anthropic
+}
+
+/// 将入口请求转换为 Anthropic Messages 请求
+pub fn to_anthropic_request(format: SourceFormat, request: Value) -> Value {
+    match format {
+        SourceFormat::Anthropic => request,
+        SourceFormat::OpenaiChat => openai_chat::openai_to_anthropic_request(&request),
+        SourceFormat::OpenaiResponses => openai_responses::responses_to_anthropic_request(&request),
+        SourceFormat::Gemini => gemini::gemini_to_anthropic_request(&request),
+    }
+}
+
+/// 将 Anthropic Messages 响应转换回入口格式
+///
+/// 同时支持完整的流式响应：SSE 文本转换为 SSE 文本，事件数组转换为事件（响应块）数组。
+/// 增量转换见 [`stream::StreamTransform`]。`include_usage` 对应 Chat Completions 请求的
+/// `stream_options.include_usage`，只影响 Chat Completions 流式响应。
+pub fn from_anthropic_response(
+    format: SourceFormat,
+    response: Value,
+    include_usage: bool,
+) -> Value {
+    match (format, response) {
+        (SourceFormat::Anthropic, response) => response,
+        (SourceFormat::OpenaiChat, Value::String(text)) => {
+            Value::String(openai_chat::anthropic_sse_to_openai(&text, include_usage))
+        }
+        (SourceFormat::OpenaiChat, Value::Array(events)) => {
+            let mut stream = openai_chat::AnthropicToOpenAiStream::new(include_usage);
+            Value::Array(events.iter().flat_map(|e| stream.push_event(e)).collect())
+        }
+        (SourceFormat::OpenaiChat, response) if response.is_object() => {
+            openai_chat::anthropic_to_openai_response(&response)
+        }
+        (SourceFormat::OpenaiResponses, Value::String(text)) => {
+            Value::String(openai_responses::anthropic_sse_to_responses(&text))
+        }
+        (SourceFormat::OpenaiResponses, Value::Array(events)) => {
+            let mut stream = openai_responses::AnthropicToResponsesStream::new();
+            let mut converted: Vec<_> = events.iter().flat_map(|e| stream.push_event(e)).collect();
+            converted.extend(stream.finish());
+            Value::Array(converted)
+        }
+        (SourceFormat::OpenaiResponses, response) if response.is_object() => {
+            openai_responses::anthropic_to_responses_response(&response)
+        }
+        (SourceFormat::Gemini, Value::String(text)) => {
+            Value::String(gemini::anthropic_sse_to_gemini(&text))
+        }
+        (SourceFormat::Gemini, Value::Array(events)) => {
+            let mut stream = gemini::AnthropicToGeminiStream::new();
+            Value::Array(events.iter().flat_map(|e| stream.push_event(e)).collect())
+        }
+        (SourceFormat::Gemini, response) if response.is_object() => {
+            gemini::anthropic_to_gemini_response(&response)
+        }
+        // 其他流式响应原样返回
+        (_, response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detect_source_format() {
+        let anthropic = json!({
+            "model": "claude-sonnet-4-5-20250929",
+            "max_tokens": 16,
+            "system": "Be brief.",
+            "messages": [{ "role": "user", "content": [{ "type": "text", "text": "Hi" }] }]
+        });
+        assert_eq!(detect_source_format(&anthropic), SourceFormat::Anthropic);
+
+        let with_system_role = json!({
+            "model": "claude-sonnet-4-5-20250929",
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": "Hi" }
+            ]
+        });
+        assert_eq!(
+            detect_source_format(&with_system_role),
+            SourceFormat::OpenaiChat
+        );
+
+        let with_field = json!({ "model": "gpt-4o", "max_completion_tokens": 8, "messages": [] });
+        assert_eq!(detect_source_format(&with_field), SourceFormat::OpenaiChat);
+        let responses = json!({ "model": "gpt-5", "input": "Hi", "instructions": "Be brief." });
+        assert_eq!(
+            detect_source_format(&responses),
+            SourceFormat::OpenaiResponses
+        );
+        let gemini = json!({ "contents": [{ "role": "user", "parts": [{ "text": "Hi" }] }] });
+        assert_eq!(detect_source_format(&gemini), SourceFormat::Gemini);
+        assert_eq!(
+            "openai".parse::<SourceFormat>().unwrap(),
+            SourceFormat::OpenaiChat
+        );
+    }
+}
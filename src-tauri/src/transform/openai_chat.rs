@@ -0,0 +1,1161 @@
+//! Anthropic Messages 与 OpenAI Chat Completions 之间的转换
+//!
+//! 同时用于 OpenAI 协议的中转服务（Anthropic → OpenAI → Anthropic）
+//! 以及 OpenAI 格式的入口请求（OpenAI → Anthropic → OpenAI）
+
+use crate::transform::sse::{format_event, format_typed_event, SseParser};
+use serde_json::{json, Map, Value};
+
+/// Anthropic 请求转换为 Chat Completions 请求（用于 OpenAI 兼容的中转服务）
+pub fn anthropic_to_openai_request(request: &Value) -> Value {
+    let mut messages = Vec::new();
+
+    let system = text_of(&request["system"]);
+    if !system.is_empty() {
+        messages.push(json!({ "role": "system", "content": system }));
+    }
+
+    for message in request["messages"].as_array().into_iter().flatten() {
+        match message["role"].as_str() {
+            Some("assistant") => messages.push(assistant_to_openai(&message["content"])),
+            _ => messages.extend(user_to_openai(&message["content"])),
+        }
+    }
+
+    let mut body = Map::new();
+    body.insert("model".to_string(), request["model"].clone());
+    body.insert("messages".to_string(), Value::Array(messages));
+    if let Some(max_tokens) = request.get("max_tokens") {
+        body.insert("max_tokens".to_string(), max_tokens.clone());
+    }
+    for key in ["temperature", "top_p"] {
+        if let Some(value) = request.get(key) {
+            body.insert(key.to_string(), value.clone());
+        }
+    }
+    if let Some(stop) = request.get("stop_sequences") {
+        body.insert("stop".to_string(), stop.clone());
+    }
+    if request["stream"].as_bool() == Some(true) {
+        body.insert("stream".to_string(), json!(true));
+        body.insert(
+            "stream_options".to_string(),
+            json!({ "include_usage": true }),
+        );
+    }
+    if let Some(user_id) = request["metadata"]["user_id"].as_str() {
+        body.insert("user".to_string(), json!(user_id));
+    }
+
+    if let Some(tools) = request["tools"].as_array() {
+        let tools: Vec<Value> = tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool["name"],
+                        "description": tool["description"].as_str().unwrap_or_default(),
+                        "parameters": tool["input_schema"],
+                    }
+                })
+            })
+            .collect();
+        body.insert("tools".to_string(), Value::Array(tools));
+    }
+    if let Some(choice) = request.get("tool_choice") {
+        let mapped = match choice["type"].as_str() {
+            Some("any") => json!("required"),
+            Some("none") => json!("none"),
+            Some("tool") => json!({ "type": "function", "function": { "name": choice["name"] } }),
+            _ => json!("auto"),
+        };
+        body.insert("tool_choice".to_string(), mapped);
+        if choice["disable_parallel_tool_use"].as_bool() == Some(true) {
+            body.insert("parallel_tool_calls".to_string(), json!(false));
+        }
+    }
+
+    // 扩展思考按预算映射为 reasoning_effort
+    if request["thinking"]["type"] == "enabled" {
+        let budget = request["thinking"]["budget_tokens"].as_u64().unwrap_or(0);
+        let effort = match budget {
+            0..=4095 => "low",
+            4096..=16383 => "medium",
+            _ => "high",
+        };
+        body.insert("reasoning_effort".to_string(), json!(effort));
+    }
+
+    Value::Object(body)
+}
+
+/// 用户消息：tool_result 拆分为 tool 消息，其余内容合并为一条 user 消息
+fn user_to_openai(content: &Value) -> Vec<Value> {
+    let Some(blocks) = content.as_array() else {
+        return vec![json!({ "role": "user", "content": content })];
+    };
+
+    let mut messages = Vec::new();
+    let mut parts = Vec::new();
+    for block in blocks {
+        match block["type"].as_str() {
+            Some("tool_result") => {
+                let mut text = text_of(&block["content"]);
+                if block["is_error"].as_bool() == Some(true) {
+                    text = format!("Error: {}", text);
+                }
+                messages.push(json!({
+                    "role": "tool",
+                    "tool_call_id": block["tool_use_id"],
+                    "content": text,
+                }));
+            }
+            Some("text") => parts.push(json!({ "type": "text", "text": block["text"] })),
+            Some("image") => {
+                let source = &block["source"];
+                let url = match source["type"].as_str() {
+                    Some("base64") => format!(
+                        "data:{};base64,{}",
+                        source["media_type"].as_str().unwrap_or("image/png"),
+                        source["data"].as_str().unwrap_or_default()
+                    ),
+                    _ => source["url"].as_str().unwrap_or_default().to_string(),
+                };
+                parts.push(json!({ "type": "image_url", "image_url": { "url": url } }));
+            }
+            _ => {}
+        }
+    }
+
+    if !parts.is_empty() {
+        messages.push(json!({ "role": "user", "content": parts }));
+    }
+    messages
+}
+
+/// 助手消息：文本合并为 content，tool_use 转为 tool_calls，思考内容放入 reasoning_content
+fn assistant_to_openai(content: &Value) -> Value {
+    let Some(blocks) = content.as_array() else {
+        return json!({ "role": "assistant", "content": content });
+    };
+
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+    for block in blocks {
+        match block["type"].as_str() {
+            Some("text") => text.push_str(block["text"].as_str().unwrap_or_default()),
+            Some("thinking") => reasoning.push_str(block["thinking"].as_str().unwrap_or_default()),
+            Some("tool_use") => tool_calls.push(json!({
+                "id": block["id"],
+                "type": "function",
+                "function": {
+                    "name": block["name"],
+                    "arguments": block["input"].to_string(),
+                }
+            })),
+            _ => {}
+        }
+    }
+
+    let mut message = json!({
+        "role": "assistant",
+        "content": if text.is_empty() && !tool_calls.is_empty() { Value::Null } else { json!(text) },
+    });
+    if !reasoning.is_empty() {
+        message["reasoning_content"] = json!(reasoning);
+    }
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+    message
+}
+
+/// Chat Completions 响应转换为 Anthropic Messages 响应
+pub fn openai_to_anthropic_response(response: &Value) -> Value {
+    let choice = &response["choices"][0];
+    let message = &choice["message"];
+    let mut content = Vec::new();
+
+    if let Some(reasoning) = message["reasoning_content"]
+        .as_str()
+        .filter(|s| !s.is_empty())
+    {
+        content.push(json!({ "type": "thinking", "thinking": reasoning, "signature": "" }));
+    }
+    if let Some(text) = message["content"].as_str().filter(|s| !s.is_empty()) {
+        content.push(json!({ "type": "text", "text": text }));
+    }
+    for call in message["tool_calls"].as_array().into_iter().flatten() {
+        content.push(json!({
+            "type": "tool_use",
+            "id": call["id"],
+            "name": call["function"]["name"],
+            "input": parse_arguments(call["function"]["arguments"].as_str().unwrap_or_default()),
+        }));
+    }
+
+    json!({
+        "id": response["id"],
+        "type": "message",
+        "role": "assistant",
+        "model": response["model"],
+        "content": content,
+        "stop_reason": map_finish_reason(choice["finish_reason"].as_str()),
+        "stop_sequence": Value::Null,
+        "usage": openai_usage_to_anthropic(&response["usage"]),
+    })
+}
+
+/// finish_reason 映射为 stop_reason
+pub fn map_finish_reason(reason: Option<&str>) -> Value {
+    match reason {
+        Some("stop") => json!("end_turn"),
+        Some("length") => json!("max_tokens"),
+        Some("tool_calls") | Some("function_call") => json!("tool_use"),
+        Some("content_filter") => json!("refusal"),
+        Some(_) => json!("end_turn"),
+        None => Value::Null,
+    }
+}
+
+/// OpenAI usage 映射为 Anthropic usage（缓存命中部分单独计入 cache_read_input_tokens）
+pub fn openai_usage_to_anthropic(usage: &Value) -> Value {
+    let prompt = usage["prompt_tokens"].as_u64().unwrap_or(0);
+    let cached = usage["prompt_tokens_details"]["cached_tokens"]
+        .as_u64()
+        .unwrap_or(0);
+    json!({
+        "input_tokens": prompt.saturating_sub(cached),
+        "output_tokens": usage["completion_tokens"].as_u64().unwrap_or(0),
+        "cache_read_input_tokens": cached,
+    })
+}
+
+/// 默认 max_tokens（OpenAI 请求可省略，Anthropic 必填）
+pub const DEFAULT_MAX_TOKENS: u64 = 4096;
+
+/// Chat Completions 请求转换为 Anthropic Messages 请求（OpenAI 格式入口）
+pub fn openai_to_anthropic_request(request: &Value) -> Value {
+    let mut system = Vec::new();
+    let mut messages: Vec<Value> = Vec::new();
+
+    for message in request["messages"].as_array().into_iter().flatten() {
+        match message["role"].as_str() {
+            Some("system") | Some("developer") => {
+                let text = text_of(&message["content"]);
+                if !text.is_empty() {
+                    system.push(text);
+                }
+            }
+            Some("assistant") => {
+                push_message(&mut messages, "assistant", openai_assistant_blocks(message))
+            }
+            Some("tool") => push_message(
+                &mut messages,
+                "user",
+                vec![json!({
+                    "type": "tool_result",
+                    "tool_use_id": message["tool_call_id"],
+                    "content": text_of(&message["content"]),
+                })],
+            ),
+            _ => push_message(
+                &mut messages,
+                "user",
+                openai_user_blocks(&message["content"]),
+            ),
+        }
+    }
+
+    // response_format 通过系统提示约束输出
+    let schema = &request["response_format"]["json_schema"];
+    if let Some(instruction) = json_format_instruction(
+        request["response_format"]["type"].as_str(),
+        schema["name"].as_str(),
+        &schema["schema"],
+    ) {
+        system.push(instruction);
+    }
+
+    let mut max_tokens = request["max_completion_tokens"]
+        .as_u64()
+        .or_else(|| request["max_tokens"].as_u64())
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+
+    let mut body = Map::new();
+    body.insert("model".to_string(), request["model"].clone());
+    body.insert("messages".to_string(), Value::Array(messages));
+    if !system.is_empty() {
+        body.insert("system".to_string(), json!(system.join("\n\n")));
+    }
+    if let Some(temperature) = request["temperature"].as_f64() {
+        // OpenAI 温度范围为 0-2，Anthropic 为 0-1
+        body.insert("temperature".to_string(), json!(temperature.min(1.0)));
+    }
+    if let Some(top_p) = request.get("top_p") {
+        body.insert("top_p".to_string(), top_p.clone());
+    }
+    match &request["stop"] {
+        Value::String(stop) => {
+            body.insert("stop_sequences".to_string(), json!([stop]));
+        }
+        Value::Array(stops) => {
+            body.insert("stop_sequences".to_string(), json!(stops));
+        }
+        _ => {}
+    }
+    if request["stream"].as_bool() == Some(true) {
+        body.insert("stream".to_string(), json!(true));
+    }
+    if let Some(user) = request["user"].as_str() {
+        body.insert("metadata".to_string(), json!({ "user_id": user }));
+    }
+
+    if let Some(tools) = request["tools"].as_array() {
+        let tools: Vec<Value> = tools
+            .iter()
+            .filter(|tool| tool["type"] == "function")
+            .map(|tool| function_to_anthropic_tool(&tool["function"]))
+            .collect();
+        body.insert("tools".to_string(), Value::Array(tools));
+    }
+    let mut tool_choice = match &request["tool_choice"] {
+        Value::String(choice) => match choice.as_str() {
+            "none" => Some(json!({ "type": "none" })),
+            "required" => Some(json!({ "type": "any" })),
+            _ => Some(json!({ "type": "auto" })),
+        },
+        Value::Object(choice) => Some(
+            json!({ "type": "tool", "name": choice.get("function").map(|f| f["name"].clone()) }),
+        ),
+        _ => None,
+    };
+    if request["parallel_tool_calls"].as_bool() == Some(false) {
+        tool_choice.get_or_insert_with(|| json!({ "type": "auto" }))["disable_parallel_tool_use"] =
+            json!(true);
+    }
+    if let Some(choice) = tool_choice {
+        body.insert("tool_choice".to_string(), choice);
+    }
+
+    // reasoning_effort 映射为扩展思考预算
+    if let Some(effort) = request["reasoning_effort"].as_str() {
+        let budget = reasoning_budget(effort);
+        max_tokens = max_tokens.max(budget + 1024);
+        enable_thinking(&mut body, budget);
+    }
+    body.insert("max_tokens".to_string(), json!(max_tokens));
+
+    Value::Object(body)
+}
+
+/// 结构化输出要求转换为系统提示（json_object / json_schema）
+pub fn json_format_instruction(
+    kind: Option<&str>,
+    name: Option<&str>,
+    schema: &Value,
+) -> Option<String> {
+    match kind {
+        Some("json_object") => Some("Respond only with a valid JSON object.".to_string()),
+        Some("json_schema") => Some(format!(
+            "Respond only with a valid JSON object that conforms to this JSON schema ({}):\n{}",
+            name.unwrap_or("response"),
+            schema
+        )),
+        _ => None,
+    }
+}
+
+/// 推理强度映射为扩展思考预算
+pub fn reasoning_budget(effort: &str) -> u64 {
+    match effort {
+        "minimal" | "low" => 1024,
+        "medium" => 8192,
+        _ => 24576,
+    }
+}
+
+/// 开启扩展思考
+///
+/// Anthropic 要求扩展思考时 temperature 为 1、top_p 不低于 0.95 且不设置 top_k，
+/// 因此移除不兼容的采样参数（由上游使用默认值）。
+pub fn enable_thinking(body: &mut Map<String, Value>, budget: u64) {
+    body.insert(
+        "thinking".to_string(),
+        json!({ "type": "enabled", "budget_tokens": budget }),
+    );
+    body.remove("temperature");
+    body.remove("top_k");
+    if body
+        .get("top_p")
+        .and_then(Value::as_f64)
+        .is_some_and(|top_p| top_p < 0.95)
+    {
+        body.remove("top_p");
+    }
+}
+
+/// OpenAI function 定义（name / description / parameters）转换为 Anthropic 工具
+pub fn function_to_anthropic_tool(function: &Value) -> Value {
+    let mut tool = json!({
+        "name": function["name"],
+        "input_schema": if function["parameters"].is_object() {
+            function["parameters"].clone()
+        } else {
+            json!({ "type": "object", "properties": {} })
+        },
+    });
+    if let Some(description) = function["description"].as_str() {
+        tool["description"] = json!(description);
+    }
+    tool
+}
+
+/// 追加消息，同角色的相邻消息合并（Anthropic 要求 user / assistant 交替）
+pub fn push_message(messages: &mut Vec<Value>, role: &str, blocks: Vec<Value>) {
+    if blocks.is_empty() {
+        return;
+    }
+    if let Some(last) = messages.last_mut().filter(|m| m["role"] == role) {
+        if let Some(content) = last["content"].as_array_mut() {
+            content.extend(blocks);
+            return;
+        }
+    }
+    messages.push(json!({ "role": role, "content": blocks }));
+}
+
+/// OpenAI 用户消息内容转换为 Anthropic 内容块
+fn openai_user_blocks(content: &Value) -> Vec<Value> {
+    match content {
+        Value::String(text) => vec![json!({ "type": "text", "text": text })],
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| match part["type"].as_str() {
+                Some("text") => Some(json!({ "type": "text", "text": part["text"] })),
+                Some("image_url") => {
+                    let url = part["image_url"]["url"]
+                        .as_str()
+                        .or_else(|| part["image_url"].as_str())?;
+                    Some(image_block_from_url(url))
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// 图片 URL（data URL 或远程地址）转换为 Anthropic 图片块
+pub fn image_block_from_url(url: &str) -> Value {
+    if let Some((media_type, data)) = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+    {
+        return json!({
+            "type": "image",
+            "source": { "type": "base64", "media_type": media_type, "data": data },
+        });
+    }
+    json!({ "type": "image", "source": { "type": "url", "url": url } })
+}
+
+/// OpenAI 助手消息转换为 Anthropic 内容块
+fn openai_assistant_blocks(message: &Value) -> Vec<Value> {
+    let mut blocks = Vec::new();
+    let text = text_of(&message["content"]);
+    if !text.is_empty() {
+        blocks.push(json!({ "type": "text", "text": text }));
+    }
+    for call in message["tool_calls"].as_array().into_iter().flatten() {
+        blocks.push(json!({
+            "type": "tool_use",
+            "id": call["id"],
+            "name": call["function"]["name"],
+            "input": parse_arguments(call["function"]["arguments"].as_str().unwrap_or_default()),
+        }));
+    }
+    blocks
+}
+
+/// Anthropic Messages 响应转换为 chat.completion 对象
+pub fn anthropic_to_openai_response(response: &Value) -> Value {
+    if response["type"] == "error" {
+        return json!({
+            "error": {
+                "message": response["error"]["message"],
+                "type": response["error"]["type"],
+                "code": Value::Null,
+            }
+        });
+    }
+
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+    for block in response["content"].as_array().into_iter().flatten() {
+        match block["type"].as_str() {
+            Some("text") => text.push_str(block["text"].as_str().unwrap_or_default()),
+            Some("thinking") => reasoning.push_str(block["thinking"].as_str().unwrap_or_default()),
+            Some("tool_use") => tool_calls.push(json!({
+                "id": block["id"],
+                "type": "function",
+                "function": { "name": block["name"], "arguments": block["input"].to_string() },
+            })),
+            _ => {}
+        }
+    }
+
+    let mut message = json!({
+        "role": "assistant",
+        "content": if text.is_empty() && !tool_calls.is_empty() { Value::Null } else { json!(text) },
+    });
+    if !reasoning.is_empty() {
+        message["reasoning_content"] = json!(reasoning);
+    }
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+
+    json!({
+        "id": response["id"],
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": response["model"],
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": map_stop_reason(response["stop_reason"].as_str()),
+        }],
+        "usage": anthropic_usage_to_openai(&response["usage"]),
+    })
+}
+
+/// stop_reason 映射为 finish_reason
+pub fn map_stop_reason(reason: Option<&str>) -> Value {
+    match reason {
+        Some("max_tokens") | Some("model_context_window_exceeded") => json!("length"),
+        Some("tool_use") => json!("tool_calls"),
+        Some("refusal") => json!("content_filter"),
+        Some(_) => json!("stop"),
+        None => Value::Null,
+    }
+}
+
+/// Anthropic usage 映射为 OpenAI usage（缓存读写计入 prompt_tokens）
+pub fn anthropic_usage_to_openai(usage: &Value) -> Value {
+    let cache_read = usage["cache_read_input_tokens"].as_u64().unwrap_or(0);
+    let prompt = usage["input_tokens"].as_u64().unwrap_or(0)
+        + cache_read
+        + usage["cache_creation_input_tokens"].as_u64().unwrap_or(0);
+    let completion = usage["output_tokens"].as_u64().unwrap_or(0);
+    json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": prompt + completion,
+        "prompt_tokens_details": { "cached_tokens": cache_read },
+    })
+}
+
+/// 解析工具参数 JSON，失败时保留原始字符串
+pub fn parse_arguments(arguments: &str) -> Value {
+    if arguments.trim().is_empty() {
+        return json!({});
+    }
+    serde_json::from_str(arguments).unwrap_or_else(|_| json!({ "raw_arguments": arguments }))
+}
+
+/// 提取文本（字符串或内容块数组中的 text）
+pub fn text_of(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter_map(|b| b["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// 当前正在输出的内容块
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpenBlock {
+    Thinking,
+    Text,
+    Tool(u64),
+}
+
+/// Chat Completions 流式 chunk 转换为 Anthropic 流式事件
+#[derive(Debug, Default)]
+pub struct OpenAiToAnthropicStream {
+    started: bool,
+    finished: bool,
+    open: Option<OpenBlock>,
+    next_index: u64,
+    stop_reason: Option<Value>,
+    usage: Option<Value>,
+}
+
+impl OpenAiToAnthropicStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 输入一个 chunk，返回转换后的事件
+    pub fn push_chunk(&mut self, chunk: &Value) -> Vec<Value> {
+        let mut events = Vec::new();
+
+        if !self.started {
+            self.started = true;
+            events.push(json!({
+                "type": "message_start",
+                "message": {
+                    "id": chunk["id"],
+                    "type": "message",
+                    "role": "assistant",
+                    "model": chunk["model"],
+                    "content": [],
+                    "stop_reason": Value::Null,
+                    "stop_sequence": Value::Null,
+                    "usage": { "input_tokens": 0, "output_tokens": 0 },
+                }
+            }));
+        }
+
+        if chunk["usage"].is_object() {
+            self.usage = Some(openai_usage_to_anthropic(&chunk["usage"]));
+        }
+
+        for choice in chunk["choices"].as_array().into_iter().flatten() {
+            let delta = &choice["delta"];
+
+            if let Some(reasoning) = delta["reasoning_content"]
+                .as_str()
+                .filter(|s| !s.is_empty())
+            {
+                self.ensure_block(
+                    OpenBlock::Thinking,
+                    json!({ "type": "thinking", "thinking": "" }),
+                    &mut events,
+                );
+                events.push(self.delta(json!({ "type": "thinking_delta", "thinking": reasoning })));
+            }
+            if let Some(text) = delta["content"].as_str().filter(|s| !s.is_empty()) {
+                self.ensure_block(
+                    OpenBlock::Text,
+                    json!({ "type": "text", "text": "" }),
+                    &mut events,
+                );
+                events.push(self.delta(json!({ "type": "text_delta", "text": text })));
+            }
+            for call in delta["tool_calls"].as_array().into_iter().flatten() {
+                let tool_index = call["index"].as_u64().unwrap_or(0);
+                if self.open != Some(OpenBlock::Tool(tool_index)) {
+                    self.ensure_block(
+                        OpenBlock::Tool(tool_index),
+                        json!({
+                            "type": "tool_use",
+                            "id": call["id"],
+                            "name": call["function"]["name"],
+                            "input": {},
+                        }),
+                        &mut events,
+                    );
+                }
+                if let Some(arguments) = call["function"]["arguments"]
+                    .as_str()
+                    .filter(|s| !s.is_empty())
+                {
+                    events.push(
+                        self.delta(
+                            json!({ "type": "input_json_delta", "partial_json": arguments }),
+                        ),
+                    );
+                }
+            }
+
+            if let Some(reason) = choice["finish_reason"].as_str() {
+                self.stop_reason = Some(map_finish_reason(Some(reason)));
+            }
+        }
+
+        events
+    }
+
+    /// 结束流，输出收尾事件（重复调用不会重复输出）
+    pub fn finish(&mut self) -> Vec<Value> {
+        if !self.started || self.finished {
+            return Vec::new();
+        }
+        self.finished = true;
+
+        let mut events = Vec::new();
+        self.close_block(&mut events);
+
+        let usage = self
+            .usage
+            .clone()
+            .unwrap_or_else(|| json!({ "output_tokens": 0 }));
+        events.push(json!({
+            "type": "message_delta",
+            "delta": {
+                "stop_reason": self.stop_reason.clone().unwrap_or(json!("end_turn")),
+                "stop_sequence": Value::Null,
+            },
+            "usage": usage,
+        }));
+        events.push(json!({ "type": "message_stop" }));
+        events
+    }
+
+    fn ensure_block(&mut self, block: OpenBlock, content_block: Value, events: &mut Vec<Value>) {
+        if self.open == Some(block) {
+            return;
+        }
+        self.close_block(events);
+        events.push(json!({
+            "type": "content_block_start",
+            "index": self.next_index,
+            "content_block": content_block,
+        }));
+        self.open = Some(block);
+    }
+
+    fn close_block(&mut self, events: &mut Vec<Value>) {
+        if self.open.take().is_some() {
+            events.push(json!({ "type": "content_block_stop", "index": self.next_index }));
+            self.next_index += 1;
+        }
+    }
+
+    fn delta(&self, delta: Value) -> Value {
+        json!({ "type": "content_block_delta", "index": self.next_index, "delta": delta })
+    }
+}
+
+/// Anthropic 流式事件转换为 Chat Completions 流式 chunk（OpenAI 格式入口）
+///
+/// 工具调用按出现顺序分配 `tool_calls[].index`；`message_delta` 时输出 finish_reason，
+/// 请求设置了 `stream_options.include_usage` 时随后输出一个 `choices` 为空、带 usage 的 chunk。
+#[derive(Debug, Default)]
+pub struct AnthropicToOpenAiStream {
+    id: Value,
+    model: Value,
+    created: i64,
+    usage: Value,
+    include_usage: bool,
+    /// 内容块索引 → tool_calls 索引
+    tool_indices: std::collections::HashMap<u64, usize>,
+    finished: bool,
+}
+
+impl AnthropicToOpenAiStream {
+    pub fn new(include_usage: bool) -> Self {
+        Self {
+            include_usage,
+            ..Self::default()
+        }
+    }
+
+    /// 输入一个 Anthropic 流式事件，返回对应的 chunk
+    pub fn push_event(&mut self, event: &Value) -> Vec<Value> {
+        let index = event["index"].as_u64().unwrap_or(0);
+        match event["type"].as_str() {
+            Some("message_start") => {
+                let message = &event["message"];
+                self.id = message["id"].clone();
+                self.model = message["model"].clone();
+                self.created = chrono::Utc::now().timestamp();
+                self.usage = message["usage"].clone();
+                vec![self.chunk(json!({ "role": "assistant", "content": "" }), Value::Null)]
+            }
+            Some("content_block_start") if event["content_block"]["type"] == "tool_use" => {
+                let block = &event["content_block"];
+                let tool_index = self.tool_indices.len();
+                self.tool_indices.insert(index, tool_index);
+                vec![self.chunk(
+                    json!({ "tool_calls": [{
+                        "index": tool_index,
+                        "id": block["id"],
+                        "type": "function",
+                        "function": { "name": block["name"], "arguments": "" },
+                    }] }),
+                    Value::Null,
+                )]
+            }
+            Some("content_block_delta") => {
+                let delta = &event["delta"];
+                let converted = match delta["type"].as_str() {
+                    Some("text_delta") => json!({ "content": delta["text"] }),
+                    Some("thinking_delta") => json!({ "reasoning_content": delta["thinking"] }),
+                    Some("input_json_delta") => match self.tool_indices.get(&index) {
+                        Some(tool_index) => json!({ "tool_calls": [{
+                            "index": tool_index,
+                            "function": { "arguments": delta["partial_json"] },
+                        }] }),
+                        None => return Vec::new(),
+                    },
+                    _ => return Vec::new(),
+                };
+                vec![self.chunk(converted, Value::Null)]
+            }
+            Some("message_delta") => {
+                if let (Some(usage), Some(target)) =
+                    (event["usage"].as_object(), self.usage.as_object_mut())
+                {
+                    target.extend(usage.clone());
+                } else if event["usage"].is_object() {
+                    self.usage = event["usage"].clone();
+                }
+                let reason = map_stop_reason(event["delta"]["stop_reason"].as_str());
+                let mut chunks = vec![self.chunk(json!({}), reason)];
+                if self.include_usage {
+                    let mut usage_chunk = self.chunk(json!({}), Value::Null);
+                    usage_chunk["choices"] = json!([]);
+                    usage_chunk["usage"] = anthropic_usage_to_openai(&self.usage);
+                    chunks.push(usage_chunk);
+                }
+                chunks
+            }
+            Some("message_stop") => {
+                self.finished = true;
+                Vec::new()
+            }
+            Some("error") => vec![anthropic_to_openai_response(event)],
+            _ => Vec::new(),
+        }
+    }
+
+    /// 是否已收到 `message_stop`
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn chunk(&self, delta: Value, finish_reason: Value) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        })
+    }
+}
+
+/// 将完整的 Anthropic SSE 文本转换为 Chat Completions SSE 文本（以 `[DONE]` 结尾）
+pub fn anthropic_sse_to_openai(text: &str, include_usage: bool) -> String {
+    let mut parser = SseParser::new();
+    let mut stream = AnthropicToOpenAiStream::new(include_usage);
+    let mut events = parser.feed(text);
+    events.extend(parser.finish());
+
+    let mut output = String::new();
+    for event in events {
+        if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
+            for chunk in stream.push_event(&data) {
+                output.push_str(&format_event(None, &chunk));
+            }
+        }
+    }
+    output.push_str("data: [DONE]\n\n");
+    output
+}
+
+/// 将完整的 Chat Completions SSE 文本转换为 Anthropic SSE 文本
+pub fn openai_sse_to_anthropic(text: &str) -> String {
+    let mut parser = SseParser::new();
+    let mut stream = OpenAiToAnthropicStream::new();
+    let mut events = parser.feed(text);
+    events.extend(parser.finish());
+
+    let mut output = String::new();
+    for event in events {
+        if event.data.trim() == "[DONE]" {
+            break;
+        }
+        if let Ok(chunk) = serde_json::from_str::<Value>(&event.data) {
+            for converted in stream.push_chunk(&chunk) {
+                output.push_str(&format_typed_event(&converted));
+            }
+        }
+    }
+    for converted in stream.finish() {
+        output.push_str(&format_typed_event(&converted));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anthropic_to_openai_request() {
+        let request = json!({
+            "model": "claude-sonnet-4-5-20250929",
+            "max_tokens": 1024,
+            "system": [{ "type": "text", "text": "You are terse." }],
+            "stop_sequences": ["END"],
+            "thinking": { "type": "enabled", "budget_tokens": 8000 },
+            "tools": [{ "name": "get_weather", "description": "Weather", "input_schema": { "type": "object" } }],
+            "tool_choice": { "type": "any" },
+            "messages": [
+                { "role": "user", "content": [
+                    { "type": "text", "text": "Weather?" },
+                    { "type": "image", "source": { "type": "base64", "media_type": "image/jpeg", "data": "AAAA" } }
+                ]},
+                { "role": "assistant", "content": [
+                    { "type": "thinking", "thinking": "Need a tool", "signature": "sig" },
+                    { "type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": { "city": "Paris" } }
+                ]},
+                { "role": "user", "content": [
+                    { "type": "tool_result", "tool_use_id": "toolu_1", "content": "Sunny" }
+                ]}
+            ]
+        });
+
+        let body = anthropic_to_openai_request(&request);
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(
+            messages[0],
+            json!({ "role": "system", "content": "You are terse." })
+        );
+        assert_eq!(
+            messages[1]["content"][1]["image_url"]["url"],
+            "data:image/jpeg;base64,AAAA"
+        );
+        assert_eq!(messages[2]["reasoning_content"], "Need a tool");
+        assert_eq!(messages[2]["content"], Value::Null);
+        assert_eq!(
+            messages[2]["tool_calls"][0]["function"]["arguments"],
+            "{\"city\":\"Paris\"}"
+        );
+        assert_eq!(
+            messages[3],
+            json!({ "role": "tool", "tool_call_id": "toolu_1", "content": "Sunny" })
+        );
+        assert_eq!(body["tool_choice"], "required");
+        assert_eq!(
+            body["tools"][0]["function"]["parameters"],
+            json!({ "type": "object" })
+        );
+        assert_eq!(body["stop"], json!(["END"]));
+        assert_eq!(body["reasoning_effort"], "medium");
+    }
+
+    #[test]
+    fn test_openai_to_anthropic_response() {
+        let response = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "model": "sonnet-4.5",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "Checking.",
+                    "tool_calls": [{ "id": "call_1", "type": "function", "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" } }]
+                },
+                "finish_reason": "tool_calls"
+            }],
+            "usage": { "prompt_tokens": 120, "completion_tokens": 15, "prompt_tokens_details": { "cached_tokens": 100 } }
+        });
+
+        let message = openai_to_anthropic_response(&response);
+        assert_eq!(
+            message["content"][0],
+            json!({ "type": "text", "text": "Checking." })
+        );
+        assert_eq!(message["content"][1]["input"], json!({ "city": "Paris" }));
+        assert_eq!(message["stop_reason"], "tool_use");
+        assert_eq!(message["usage"]["input_tokens"], 20);
+        assert_eq!(message["usage"]["cache_read_input_tokens"], 100);
+    }
+
+    #[test]
+    fn test_openai_stream_to_anthropic_events() {
+        let mut stream = OpenAiToAnthropicStream::new();
+        let mut events = Vec::new();
+        for chunk in [
+            json!({ "id": "c1", "model": "m", "choices": [{ "index": 0, "delta": { "role": "assistant", "content": "Hi" } }] }),
+            json!({ "id": "c1", "model": "m", "choices": [{ "index": 0, "delta": { "tool_calls": [{ "index": 0, "id": "call_1", "function": { "name": "f", "arguments": "{\"a\"" } }] } }] }),
+            json!({ "id": "c1", "model": "m", "choices": [{ "index": 0, "delta": { "tool_calls": [{ "index": 0, "function": { "arguments": ":1}" } }] }, "finish_reason": "tool_calls" }] }),
+            json!({ "id": "c1", "model": "m", "choices": [], "usage": { "prompt_tokens": 10, "completion_tokens": 5 } }),
+        ] {
+            events.extend(stream.push_chunk(&chunk));
+        }
+        events.extend(stream.finish());
+        assert!(stream.finish().is_empty());
+
+        let types: Vec<_> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(
+            types,
+            vec![
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert_eq!(events[4]["index"], 1);
+        assert_eq!(events[4]["content_block"]["name"], "f");
+        assert_eq!(events[6]["delta"]["partial_json"], ":1}");
+        assert_eq!(events[8]["delta"]["stop_reason"], "tool_use");
+        assert_eq!(events[8]["usage"]["output_tokens"], 5);
+    }
+
+    #[test]
+    fn test_anthropic_stream_to_openai_chunks() {
+        let mut stream = AnthropicToOpenAiStream::new(true);
+        let mut chunks = Vec::new();
+        let events = [
+            json!({ "type": "message_start", "message": { "id": "msg_1", "model": "m", "usage": { "input_tokens": 9, "output_tokens": 1 } } }),
+            json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "text", "text": "" } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "Hi" } }),
+            json!({ "type": "content_block_stop", "index": 0 }),
+            json!({ "type": "content_block_start", "index": 1, "content_block": { "type": "tool_use", "id": "toolu_1", "name": "f", "input": {} } }),
+            json!({ "type": "content_block_delta", "index": 1, "delta": { "type": "input_json_delta", "partial_json": "{}" } }),
+            json!({ "type": "content_block_stop", "index": 1 }),
+            json!({ "type": "message_delta", "delta": { "stop_reason": "tool_use" }, "usage": { "output_tokens": 4 } }),
+            json!({ "type": "message_stop" }),
+        ];
+        for event in &events {
+            chunks.extend(stream.push_event(event));
+        }
+
+        assert!(stream.is_finished());
+        assert_eq!(chunks.len(), 6);
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "Hi");
+        assert_eq!(
+            chunks[2]["choices"][0]["delta"]["tool_calls"][0]["id"],
+            "toolu_1"
+        );
+        assert_eq!(
+            chunks[3]["choices"][0]["delta"]["tool_calls"][0]["function"]["arguments"],
+            "{}"
+        );
+        assert_eq!(chunks[4]["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(chunks[5]["usage"]["total_tokens"], 13);
+
+        // 未设置 stream_options.include_usage 时不输出 usage chunk
+        let mut without_usage = AnthropicToOpenAiStream::new(false);
+        let chunks: Vec<Value> = events
+            .iter()
+            .flat_map(|e| without_usage.push_event(e))
+            .collect();
+        assert_eq!(chunks.len(), 5);
+        assert!(chunks.iter().all(|c| c.get("usage").is_none()));
+    }
+
+    #[test]
+    fn test_openai_to_anthropic_request() {
+        let request = json!({
+            "model": "claude-sonnet-4-5-20250929",
+            "max_completion_tokens": 512,
+            "temperature": 1.5,
+            "stop": "END",
+            "response_format": { "type": "json_object" },
+            "parallel_tool_calls": false,
+            "tools": [{ "type": "function", "function": { "name": "get_weather", "parameters": { "type": "object" } } }],
+            "tool_choice": { "type": "function", "function": { "name": "get_weather" } },
+            "messages": [
+                { "role": "system", "content": "You are terse." },
+                { "role": "user", "content": [
+                    { "type": "text", "text": "Weather?" },
+                    { "type": "image_url", "image_url": { "url": "data:image/png;base64,AAAA" } }
+                ]},
+                { "role": "assistant", "content": null, "tool_calls": [
+                    { "id": "call_1", "type": "function", "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" } },
+                    { "id": "call_2", "type": "function", "function": { "name": "get_weather", "arguments": "{\"city\":\"Rome\"}" } }
+                ]},
+                { "role": "tool", "tool_call_id": "call_1", "content": "Sunny" },
+                { "role": "tool", "tool_call_id": "call_2", "content": "Rainy" }
+            ]
+        });
+
+        let body = openai_to_anthropic_request(&request);
+        assert_eq!(body["max_tokens"], 512);
+        assert_eq!(body["temperature"], 1.0);
+        assert_eq!(body["stop_sequences"], json!(["END"]));
+        assert!(body["system"]
+            .as_str()
+            .unwrap()
+            .starts_with("You are terse.\n\n"));
+        assert_eq!(
+            body["tool_choice"],
+            json!({ "type": "tool", "name": "get_weather", "disable_parallel_tool_use": true })
+        );
+        assert_eq!(
+            body["tools"][0]["input_schema"],
+            json!({ "type": "object" })
+        );
+
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0]["content"][1]["source"]["media_type"],
+            "image/png"
+        );
+        assert_eq!(
+            messages[1]["content"][1]["input"],
+            json!({ "city": "Rome" })
+        );
+        // 相邻的 tool 消息合并为一条 user 消息
+        assert_eq!(messages[2]["content"].as_array().unwrap().len(), 2);
+        assert_eq!(messages[2]["content"][1]["tool_use_id"], "call_2");
+
+        // 开启扩展思考时移除不兼容的采样参数
+        let body = openai_to_anthropic_request(&json!({
+            "model": "claude-sonnet-4-5-20250929",
+            "temperature": 0.3,
+            "top_p": 0.5,
+            "reasoning_effort": "low",
+            "messages": [{ "role": "user", "content": "Hi" }]
+        }));
+        assert_eq!(body["thinking"]["budget_tokens"], 1024);
+        assert!(body.get("temperature").is_none());
+        assert!(body.get("top_p").is_none());
+    }
+
+    #[test]
+    fn test_anthropic_to_openai_response() {
+        let response = json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-sonnet-4-5-20250929",
+            "content": [
+                { "type": "text", "text": "Let me check." },
+                { "type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": { "city": "Paris" } }
+            ],
+            "stop_reason": "tool_use",
+            "usage": { "input_tokens": 20, "output_tokens": 10, "cache_read_input_tokens": 100 }
+        });
+
+        let completion = anthropic_to_openai_response(&response);
+        assert_eq!(completion["object"], "chat.completion");
+        let choice = &completion["choices"][0];
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert_eq!(choice["message"]["content"], "Let me check.");
+        assert_eq!(
+            choice["message"]["tool_calls"][0]["function"]["arguments"],
+            "{\"city\":\"Paris\"}"
+        );
+        assert_eq!(completion["usage"]["prompt_tokens"], 120);
+        assert_eq!(completion["usage"]["total_tokens"], 130);
+        assert_eq!(
+            completion["usage"]["prompt_tokens_details"]["cached_tokens"],
+            100
+        );
+    }
+}
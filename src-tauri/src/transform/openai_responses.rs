@@ -0,0 +1,774 @@
+//! Anthropic Messages 与 OpenAI Responses API 之间的转换
+//!
+//! 仅支持无状态转换：`previous_response_id` 不会被解析，客户端需在 `input` 中携带完整上下文。
+//! 推理项通过 `encrypted_content` 携带 Anthropic thinking 签名，以便下一轮原样回传。
+
+use crate::transform::openai_chat::{
+    enable_thinking, function_to_anthropic_tool, image_block_from_url, json_format_instruction,
+    parse_arguments, push_message, reasoning_budget, text_of, DEFAULT_MAX_TOKENS,
+};
+use crate::transform::sse::{format_typed_event, SseParser};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Responses 请求转换为 Anthropic Messages 请求
+pub fn responses_to_anthropic_request(request: &Value) -> Value {
+    let mut system = Vec::new();
+    if let Some(instructions) = request["instructions"].as_str() {
+        system.push(instructions.to_string());
+    }
+
+    let mut messages: Vec<Value> = Vec::new();
+    match &request["input"] {
+        Value::String(text) => push_message(
+            &mut messages,
+            "user",
+            vec![json!({ "type": "text", "text": text })],
+        ),
+        Value::Array(items) => {
+            for item in items {
+                push_input_item(&mut messages, &mut system, item);
+            }
+        }
+        _ => {}
+    }
+
+    let text_format = &request["text"]["format"];
+    if let Some(instruction) = json_format_instruction(
+        text_format["type"].as_str(),
+        text_format["name"].as_str(),
+        &text_format["schema"],
+    ) {
+        system.push(instruction);
+    }
+
+    let mut max_tokens = request["max_output_tokens"]
+        .as_u64()
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+
+    let mut body = Map::new();
+    body.insert("model".to_string(), request["model"].clone());
+    body.insert("messages".to_string(), Value::Array(messages));
+    if !system.is_empty() {
+        body.insert("system".to_string(), json!(system.join("\n\n")));
+    }
+    if let Some(temperature) = request["temperature"].as_f64() {
+        body.insert("temperature".to_string(), json!(temperature.min(1.0)));
+    }
+    if let Some(top_p) = request.get("top_p") {
+        body.insert("top_p".to_string(), top_p.clone());
+    }
+    if request["stream"].as_bool() == Some(true) {
+        body.insert("stream".to_string(), json!(true));
+    }
+    if let Some(user) = request["user"].as_str() {
+        body.insert("metadata".to_string(), json!({ "user_id": user }));
+    }
+
+    // Responses 的 function 工具为扁平结构（name / parameters 位于顶层），内置工具忽略
+    if let Some(tools) = request["tools"].as_array() {
+        let tools: Vec<Value> = tools
+            .iter()
+            .filter(|tool| tool["type"] == "function")
+            .map(function_to_anthropic_tool)
+            .collect();
+        body.insert("tools".to_string(), Value::Array(tools));
+    }
+    let mut tool_choice = match &request["tool_choice"] {
+        Value::String(choice) => match choice.as_str() {
+            "none" => Some(json!({ "type": "none" })),
+            "required" => Some(json!({ "type": "any" })),
+            _ => Some(json!({ "type": "auto" })),
+        },
+        Value::Object(choice) => Some(json!({ "type": "tool", "name": choice.get("name") })),
+        _ => None,
+    };
+    if request["parallel_tool_calls"].as_bool() == Some(false) {
+        tool_choice.get_or_insert_with(|| json!({ "type": "auto" }))["disable_parallel_tool_use"] =
+            json!(true);
+    }
+    if let Some(choice) = tool_choice {
+        body.insert("tool_choice".to_string(), choice);
+    }
+
+    if let Some(effort) = request["reasoning"]["effort"].as_str() {
+        let budget = reasoning_budget(effort);
+        max_tokens = max_tokens.max(budget + 1024);
+        enable_thinking(&mut body, budget);
+    }
+    body.insert("max_tokens".to_string(), json!(max_tokens));
+
+    Value::Object(body)
+}
+
+/// 转换单个 input 项
+fn push_input_item(messages: &mut Vec<Value>, system: &mut Vec<String>, item: &Value) {
+    match item["type"].as_str().unwrap_or("message") {
+        "message" => match item["role"].as_str() {
+            Some("system") | Some("developer") => {
+                let text = text_of(&item["content"]);
+                if !text.is_empty() {
+                    system.push(text);
+                }
+            }
+            Some("assistant") => {
+                let text = text_of(&item["content"]);
+                if !text.is_empty() {
+                    push_message(
+                        messages,
+                        "assistant",
+                        vec![json!({ "type": "text", "text": text })],
+                    );
+                }
+            }
+            _ => push_message(messages, "user", input_content_blocks(&item["content"])),
+        },
+        "function_call" => push_message(
+            messages,
+            "assistant",
+            vec![json!({
+                "type": "tool_use",
+                "id": item["call_id"],
+                "name": item["name"],
+                "input": parse_arguments(item["arguments"].as_str().unwrap_or_default()),
+            })],
+        ),
+        "function_call_output" => push_message(
+            messages,
+            "user",
+            vec![json!({
+                "type": "tool_result",
+                "tool_use_id": item["call_id"],
+                "content": text_of(&item["output"]),
+            })],
+        ),
+        // 仅回传带签名的推理项；无签名的 thinking 块会被 Anthropic 拒绝
+        "reasoning" => {
+            if let Some(signature) = item["encrypted_content"].as_str() {
+                let thinking = item["summary"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|s| s["text"].as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                push_message(
+                    messages,
+                    "assistant",
+                    vec![
+                        json!({ "type": "thinking", "thinking": thinking, "signature": signature }),
+                    ],
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+/// input 消息内容转换为 Anthropic 内容块
+fn input_content_blocks(content: &Value) -> Vec<Value> {
+    match content {
+        Value::String(text) => vec![json!({ "type": "text", "text": text })],
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| match part["type"].as_str() {
+                Some("input_text") | Some("output_text") | Some("text") => {
+                    Some(json!({ "type": "text", "text": part["text"] }))
+                }
+                Some("input_image") => part["image_url"].as_str().map(image_block_from_url),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Anthropic Messages 响应转换为 Responses 响应对象
+pub fn anthropic_to_responses_response(response: &Value) -> Value {
+    if response["type"] == "error" {
+        return json!({
+            "error": {
+                "message": response["error"]["message"],
+                "type": response["error"]["type"],
+                "code": Value::Null,
+            }
+        });
+    }
+
+    let base = item_id_base(response["id"].as_str().unwrap_or_default());
+    let mut output = Vec::new();
+    // 所有文本块合并为一个 message 项，位置取第一个文本块
+    let mut message_index: Option<usize> = None;
+    for (index, block) in response["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        match block["type"].as_str() {
+            Some("thinking") => output.push(reasoning_item(
+                &format!("rs_{}_{}", base, index),
+                block["thinking"].as_str().unwrap_or_default(),
+                block["signature"].as_str(),
+            )),
+            Some("text") => {
+                let part = output_text(block["text"].as_str().unwrap_or_default());
+                match message_index {
+                    Some(i) => output[i]["content"].as_array_mut().unwrap().push(part),
+                    None => {
+                        message_index = Some(output.len());
+                        output.push(message_item(
+                            &format!("msg_{}", base),
+                            vec![part],
+                            "completed",
+                        ));
+                    }
+                }
+            }
+            Some("tool_use") => output.push(function_call_item(
+                block["id"].as_str().unwrap_or_default(),
+                &block["name"],
+                &block["input"].to_string(),
+                "completed",
+            )),
+            _ => {}
+        }
+    }
+
+    response_object(
+        &format!("resp_{}", base),
+        &response["model"],
+        response_status(response["stop_reason"].as_str()),
+        output,
+        Some(&response["usage"]),
+    )
+}
+
+/// 响应 ID 去掉 `msg_` 前缀，用于派生各输出项 ID
+fn item_id_base(id: &str) -> String {
+    id.strip_prefix("msg_").unwrap_or(id).to_string()
+}
+
+/// stop_reason 映射为响应状态（max_tokens 视为未完成）
+fn response_status(stop_reason: Option<&str>) -> &'static str {
+    match stop_reason {
+        Some("max_tokens") | Some("model_context_window_exceeded") => "incomplete",
+        _ => "completed",
+    }
+}
+
+fn output_text(text: &str) -> Value {
+    json!({ "type": "output_text", "text": text, "annotations": [] })
+}
+
+fn message_item(id: &str, content: Vec<Value>, status: &str) -> Value {
+    json!({
+        "type": "message",
+        "id": id,
+        "status": status,
+        "role": "assistant",
+        "content": content,
+    })
+}
+
+fn function_call_item(call_id: &str, name: &Value, arguments: &str, status: &str) -> Value {
+    json!({
+        "type": "function_call",
+        "id": format!("fc_{}", call_id),
+        "call_id": call_id,
+        "name": name,
+        "arguments": arguments,
+        "status": status,
+    })
+}
+
+fn reasoning_item(id: &str, thinking: &str, signature: Option<&str>) -> Value {
+    let mut item = json!({
+        "type": "reasoning",
+        "id": id,
+        "summary": if thinking.is_empty() {
+            json!([])
+        } else {
+            json!([{ "type": "summary_text", "text": thinking }])
+        },
+    });
+    if let Some(signature) = signature {
+        item["encrypted_content"] = json!(signature);
+    }
+    item
+}
+
+fn response_object(
+    id: &str,
+    model: &Value,
+    status: &str,
+    output: Vec<Value>,
+    usage: Option<&Value>,
+) -> Value {
+    let mut response = json!({
+        "id": id,
+        "object": "response",
+        "created_at": chrono::Utc::now().timestamp(),
+        "status": status,
+        "model": model,
+        "output": output,
+    });
+    if status == "incomplete" {
+        response["incomplete_details"] = json!({ "reason": "max_output_tokens" });
+    }
+    if let Some(usage) = usage {
+        response["usage"] = anthropic_usage_to_responses(usage);
+    }
+    response
+}
+
+/// Anthropic usage 映射为 Responses usage（缓存读写计入 input_tokens）
+pub fn anthropic_usage_to_responses(usage: &Value) -> Value {
+    let cache_read = usage["cache_read_input_tokens"].as_u64().unwrap_or(0);
+    let input = usage["input_tokens"].as_u64().unwrap_or(0)
+        + cache_read
+        + usage["cache_creation_input_tokens"].as_u64().unwrap_or(0);
+    let output = usage["output_tokens"].as_u64().unwrap_or(0);
+    json!({
+        "input_tokens": input,
+        "input_tokens_details": { "cached_tokens": cache_read },
+        "output_tokens": output,
+        "output_tokens_details": { "reasoning_tokens": 0 },
+        "total_tokens": input + output,
+    })
+}
+
+/// 流中正在输出的内容块
+#[derive(Debug)]
+struct StreamItem {
+    output_index: usize,
+    item: Value,
+    /// 已累积的文本 / 思考内容 / 工具参数 JSON
+    buffer: String,
+}
+
+/// Anthropic 流式事件转换为 Responses 流式事件
+///
+/// 每个 Anthropic 内容块对应一个输出项；`message_stop` 时输出 `response.completed`。
+#[derive(Debug, Default)]
+pub struct AnthropicToResponsesStream {
+    id: String,
+    model: Value,
+    sequence: u64,
+    items: HashMap<u64, StreamItem>,
+    output: Vec<Value>,
+    usage: Value,
+    stop_reason: Option<String>,
+    finished: bool,
+}
+
+impl AnthropicToResponsesStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 输入一个 Anthropic 流式事件，返回对应的 Responses 事件
+    pub fn push_event(&mut self, event: &Value) -> Vec<Value> {
+        let mut events = Vec::new();
+        match event["type"].as_str() {
+            Some("message_start") => {
+                let message = &event["message"];
+                self.id = item_id_base(message["id"].as_str().unwrap_or_default());
+                self.model = message["model"].clone();
+                self.usage = message["usage"].clone();
+                let response = self.snapshot("in_progress");
+                events
+                    .push(self.event(json!({ "type": "response.created", "response": response })));
+                events.push(
+                    self.event(json!({ "type": "response.in_progress", "response": response })),
+                );
+            }
+            Some("content_block_start") => self.start_item(event, &mut events),
+            Some("content_block_delta") => self.delta_item(event, &mut events),
+            Some("content_block_stop") => self.stop_item(event, &mut events),
+            Some("message_delta") => {
+                if let Some(reason) = event["delta"]["stop_reason"].as_str() {
+                    self.stop_reason = Some(reason.to_string());
+                }
+                if let (Some(usage), Some(target)) =
+                    (event["usage"].as_object(), self.usage.as_object_mut())
+                {
+                    target.extend(usage.clone());
+                } else if event["usage"].is_object() {
+                    self.usage = event["usage"].clone();
+                }
+            }
+            Some("message_stop") => events.extend(self.finish()),
+            Some("error") => {
+                let error = &event["error"];
+                events.push(self.event(json!({
+                    "type": "error",
+                    "code": error["type"],
+                    "message": error["message"],
+                    "param": Value::Null,
+                })));
+            }
+            _ => {}
+        }
+        events
+    }
+
+    /// 结束流，输出 `response.completed`（重复调用不会重复输出）
+    pub fn finish(&mut self) -> Vec<Value> {
+        if self.finished || self.id.is_empty() {
+            return Vec::new();
+        }
+        self.finished = true;
+
+        let status = response_status(self.stop_reason.as_deref());
+        let mut response = self.snapshot(status);
+        response["usage"] = anthropic_usage_to_responses(&self.usage);
+        let kind = if status == "incomplete" {
+            "response.incomplete"
+        } else {
+            "response.completed"
+        };
+        vec![self.event(json!({ "type": kind, "response": response }))]
+    }
+
+    fn start_item(&mut self, event: &Value, events: &mut Vec<Value>) {
+        let index = event["index"].as_u64().unwrap_or(0);
+        let block = &event["content_block"];
+        let output_index = self.output.len() + self.items.len();
+        let item = match block["type"].as_str() {
+            Some("text") => {
+                message_item(&format!("msg_{}_{}", self.id, index), vec![], "in_progress")
+            }
+            Some("thinking") => reasoning_item(&format!("rs_{}_{}", self.id, index), "", None),
+            Some("tool_use") => function_call_item(
+                block["id"].as_str().unwrap_or_default(),
+                &block["name"],
+                "",
+                "in_progress",
+            ),
+            _ => return,
+        };
+
+        events.push(self.event(json!({
+            "type": "response.output_item.added",
+            "output_index": output_index,
+            "item": item,
+        })));
+        match block["type"].as_str() {
+            Some("text") => events.push(self.event(json!({
+                "type": "response.content_part.added",
+                "item_id": item["id"],
+                "output_index": output_index,
+                "content_index": 0,
+                "part": output_text(""),
+            }))),
+            Some("thinking") => events.push(self.event(json!({
+                "type": "response.reasoning_summary_part.added",
+                "item_id": item["id"],
+                "output_index": output_index,
+                "summary_index": 0,
+                "part": { "type": "summary_text", "text": "" },
+            }))),
+            _ => {}
+        }
+
+        self.items.insert(
+            index,
+            StreamItem {
+                output_index,
+                item,
+                buffer: String::new(),
+            },
+        );
+    }
+
+    fn delta_item(&mut self, event: &Value, events: &mut Vec<Value>) {
+        let index = event["index"].as_u64().unwrap_or(0);
+        let delta = &event["delta"];
+        let Some(state) = self.items.get_mut(&index) else {
+            return;
+        };
+        let item_id = state.item["id"].clone();
+        let output_index = state.output_index;
+
+        let converted = match delta["type"].as_str() {
+            Some("text_delta") => {
+                let text = delta["text"].as_str().unwrap_or_default();
+                state.buffer.push_str(text);
+                json!({
+                    "type": "response.output_text.delta",
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "content_index": 0,
+                    "delta": text,
+                })
+            }
+            Some("thinking_delta") => {
+                let thinking = delta["thinking"].as_str().unwrap_or_default();
+                state.buffer.push_str(thinking);
+                json!({
+                    "type": "response.reasoning_summary_text.delta",
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "summary_index": 0,
+                    "delta": thinking,
+                })
+            }
+            Some("signature_delta") => {
+                state.item["encrypted_content"] = delta["signature"].clone();
+                return;
+            }
+            Some("input_json_delta") => {
+                let partial = delta["partial_json"].as_str().unwrap_or_default();
+                state.buffer.push_str(partial);
+                json!({
+                    "type": "response.function_call_arguments.delta",
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "delta": partial,
+                })
+            }
+            _ => return,
+        };
+        events.push(self.event(converted));
+    }
+
+    fn stop_item(&mut self, event: &Value, events: &mut Vec<Value>) {
+        let index = event["index"].as_u64().unwrap_or(0);
+        let Some(StreamItem {
+            output_index,
+            mut item,
+            buffer,
+        }) = self.items.remove(&index)
+        else {
+            return;
+        };
+        let item_id = item["id"].clone();
+
+        match item["type"].as_str() {
+            Some("message") => {
+                events.push(self.event(json!({
+                    "type": "response.output_text.done",
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "content_index": 0,
+                    "text": buffer,
+                })));
+                events.push(self.event(json!({
+                    "type": "response.content_part.done",
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "content_index": 0,
+                    "part": output_text(&buffer),
+                })));
+                item["content"] = json!([output_text(&buffer)]);
+            }
+            Some("reasoning") => {
+                events.push(self.event(json!({
+                    "type": "response.reasoning_summary_text.done",
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "summary_index": 0,
+                    "text": buffer,
+                })));
+                let part = json!({ "type": "summary_text", "text": buffer });
+                events.push(self.event(json!({
+                    "type": "response.reasoning_summary_part.done",
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "summary_index": 0,
+                    "part": part,
+                })));
+                item["summary"] = json!([part]);
+            }
+            Some("function_call") => {
+                let arguments = if buffer.is_empty() {
+                    "{}".to_string()
+                } else {
+                    buffer
+                };
+                events.push(self.event(json!({
+                    "type": "response.function_call_arguments.done",
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "arguments": arguments,
+                })));
+                item["arguments"] = json!(arguments);
+            }
+            _ => {}
+        }
+
+        if item.get("status").is_some() {
+            item["status"] = json!("completed");
+        }
+        events.push(self.event(json!({
+            "type": "response.output_item.done",
+            "output_index": output_index,
+            "item": item,
+        })));
+        self.output.push(item);
+    }
+
+    fn snapshot(&self, status: &str) -> Value {
+        response_object(
+            &format!("resp_{}", self.id),
+            &self.model,
+            status,
+            self.output.clone(),
+            None,
+        )
+    }
+
+    /// 附加递增的 sequence_number
+    fn event(&mut self, mut event: Value) -> Value {
+        event["sequence_number"] = json!(self.sequence);
+        self.sequence += 1;
+        event
+    }
+}
+
+/// 将完整的 Anthropic SSE 文本转换为 Responses SSE 文本
+pub fn anthropic_sse_to_responses(text: &str) -> String {
+    let mut parser = SseParser::new();
+    let mut stream = AnthropicToResponsesStream::new();
+    let mut events = parser.feed(text);
+    events.extend(parser.finish());
+
+    let mut output = String::new();
+    for event in events {
+        if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
+            for converted in stream.push_event(&data) {
+                output.push_str(&format_typed_event(&converted));
+            }
+        }
+    }
+    for converted in stream.finish() {
+        output.push_str(&format_typed_event(&converted));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_responses_to_anthropic_request() {
+        let request = json!({
+            "model": "claude-sonnet-4-5-20250929",
+            "instructions": "You are terse.",
+            "max_output_tokens": 256,
+            "reasoning": { "effort": "low" },
+            "tools": [{ "type": "function", "name": "get_weather", "parameters": { "type": "object" } }, { "type": "web_search" }],
+            "tool_choice": { "type": "function", "name": "get_weather" },
+            "input": [
+                { "role": "user", "content": [
+                    { "type": "input_text", "text": "Weather?" },
+                    { "type": "input_image", "image_url": "https://example.com/a.png" }
+                ]},
+                { "type": "reasoning", "id": "rs_1", "summary": [{ "type": "summary_text", "text": "Need a tool" }], "encrypted_content": "sig" },
+                { "type": "function_call", "call_id": "toolu_1", "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" },
+                { "type": "function_call_output", "call_id": "toolu_1", "output": "Sunny" }
+            ]
+        });
+
+        let body = responses_to_anthropic_request(&request);
+        assert_eq!(body["system"], "You are terse.");
+        assert_eq!(body["max_tokens"], 2048);
+        assert_eq!(body["thinking"]["budget_tokens"], 1024);
+        assert_eq!(body["tools"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            body["tool_choice"],
+            json!({ "type": "tool", "name": "get_weather" })
+        );
+
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["content"][1]["source"]["type"], "url");
+        assert_eq!(
+            messages[1]["content"][0],
+            json!({ "type": "thinking", "thinking": "Need a tool", "signature": "sig" })
+        );
+        assert_eq!(
+            messages[1]["content"][1]["input"],
+            json!({ "city": "Paris" })
+        );
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "toolu_1");
+    }
+
+    #[test]
+    fn test_anthropic_to_responses_response() {
+        let response = json!({
+            "id": "msg_01",
+            "type": "message",
+            "model": "claude-sonnet-4-5-20250929",
+            "content": [
+                { "type": "thinking", "thinking": "Need a tool", "signature": "sig" },
+                { "type": "text", "text": "Checking." },
+                { "type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": { "city": "Paris" } }
+            ],
+            "stop_reason": "tool_use",
+            "usage": { "input_tokens": 10, "output_tokens": 5, "cache_read_input_tokens": 90 }
+        });
+
+        let converted = anthropic_to_responses_response(&response);
+        assert_eq!(converted["id"], "resp_01");
+        assert_eq!(converted["status"], "completed");
+        let output = converted["output"].as_array().unwrap();
+        assert_eq!(output[0]["type"], "reasoning");
+        assert_eq!(output[0]["encrypted_content"], "sig");
+        assert_eq!(output[1]["content"][0]["text"], "Checking.");
+        assert_eq!(output[2]["call_id"], "toolu_1");
+        assert_eq!(output[2]["arguments"], "{\"city\":\"Paris\"}");
+        assert_eq!(converted["usage"]["input_tokens"], 100);
+        assert_eq!(
+            converted["usage"]["input_tokens_details"]["cached_tokens"],
+            90
+        );
+    }
+
+    #[test]
+    fn test_anthropic_stream_to_responses_events() {
+        let mut stream = AnthropicToResponsesStream::new();
+        let mut events = Vec::new();
+        for event in [
+            json!({ "type": "message_start", "message": { "id": "msg_01", "model": "m", "usage": { "input_tokens": 7, "output_tokens": 1 } } }),
+            json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "text", "text": "" } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "Hi" } }),
+            json!({ "type": "content_block_stop", "index": 0 }),
+            json!({ "type": "content_block_start", "index": 1, "content_block": { "type": "tool_use", "id": "toolu_1", "name": "f", "input": {} } }),
+            json!({ "type": "content_block_delta", "index": 1, "delta": { "type": "input_json_delta", "partial_json": "{\"a\":1}" } }),
+            json!({ "type": "content_block_stop", "index": 1 }),
+            json!({ "type": "message_delta", "delta": { "stop_reason": "tool_use" }, "usage": { "output_tokens": 12 } }),
+            json!({ "type": "message_stop" }),
+        ] {
+            events.extend(stream.push_event(&event));
+        }
+        assert!(stream.finish().is_empty());
+
+        let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(
+            types,
+            vec![
+                "response.created",
+                "response.in_progress",
+                "response.output_item.added",
+                "response.content_part.added",
+                "response.output_text.delta",
+                "response.output_text.done",
+                "response.content_part.done",
+                "response.output_item.done",
+                "response.output_item.added",
+                "response.function_call_arguments.delta",
+                "response.function_call_arguments.done",
+                "response.output_item.done",
+                "response.completed",
+            ]
+        );
+        assert_eq!(events[12]["sequence_number"], 12);
+        let completed = &events[12]["response"];
+        assert_eq!(completed["output"][1]["arguments"], "{\"a\":1}");
+        assert_eq!(completed["usage"]["output_tokens"], 12);
+        assert_eq!(completed["usage"]["input_tokens"], 7);
+    }
+}
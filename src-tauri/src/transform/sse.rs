@@ -0,0 +1,152 @@
+//! SSE 解析与序列化
+
+/// 一个 SSE 事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// `event:` 字段
+    pub event: Option<String>,
+    /// `data:` 字段（多行以换行拼接）
+    pub data: String,
+}
+
+/// 增量 SSE 解析器
+///
+/// 数据块可以在任意位置截断，未完成的事件会保留到下一次输入。
+/// 按字节输入时，被截断在多字节 UTF-8 字符中间的尾部字节同样保留到下一次输入。
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: String,
+    /// 尚未组成完整 UTF-8 字符的字节
+    pending: Vec<u8>,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 输入一段数据，返回其中已完整的事件
+    pub fn feed(&mut self, chunk: &str) -> Vec<SseEvent> {
+        // 在缓冲区上规范化换行：CRLF 可能被切分在两个数据块之间，
+        // 末尾单独的 `\r` 会留在缓冲区，与下一块开头的 `\n` 拼接后再替换
+        self.buffer.push_str(chunk);
+        if self.buffer.contains("\r\n") {
+            self.buffer = self.buffer.replace("\r\n", "\n");
+        }
+
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.find("\n\n") {
+            let block: String = self.buffer.drain(..pos + 2).collect();
+            if let Some(event) = parse_block(&block) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// 输入一段原始字节，返回其中已完整的事件
+    pub fn feed_bytes(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.pending.extend_from_slice(chunk);
+        let complete = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            // 末尾是不完整的字符：等待后续字节
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            // 无效字节无法恢复，按替换字符处理
+            Err(_) => self.pending.len(),
+        };
+        let bytes: Vec<u8> = self.pending.drain(..complete).collect();
+        self.feed(&String::from_utf8_lossy(&bytes))
+    }
+
+    /// 结束输入，返回缓冲区中剩余的事件
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.buffer.push_str(&String::from_utf8_lossy(&pending));
+        }
+        let block = std::mem::take(&mut self.buffer);
+        parse_block(&block)
+    }
+}
+
+fn parse_block(block: &str) -> Option<SseEvent> {
+    let mut event = None;
+    let mut data: Vec<&str> = Vec::new();
+
+    for line in block.lines().map(|line| line.trim_end_matches('\r')) {
+        if let Some(value) = line.strip_prefix("event:") {
+            event = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+
+    if event.is_none() && data.is_empty() {
+        return None;
+    }
+    Some(SseEvent {
+        event,
+        data: data.join("\n"),
+    })
+}
+
+/// 序列化为 SSE 文本
+pub fn format_event(event: Option<&str>, data: &serde_json::Value) -> String {
+    match event {
+        Some(event) => format!("event: {}\ndata: {}\n\n", event, data),
+        None => format!("data: {}\n\n", data),
+    }
+}
+
+/// 以 `type` 字段作为事件名序列化（Anthropic / Responses 流格式）
+pub fn format_typed_event(data: &serde_json::Value) -> String {
+    format_event(data["type"].as_str(), data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parser_handles_split_chunks() {
+        let mut parser = SseParser::new();
+        assert!(parser
+            .feed("event: message_start\ndata: {\"a\":")
+            .is_empty());
+
+        let events = parser.feed("1}\r\n\r\ndata: [DONE]\n\ndata: tail");
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: Some("message_start".to_string()),
+                    data: "{\"a\":1}".to_string(),
+                },
+                SseEvent {
+                    event: None,
+                    data: "[DONE]".to_string(),
+                },
+            ]
+        );
+        assert_eq!(parser.finish().unwrap().data, "tail");
+
+        // CRLF 在 `\r` 之后被切分
+        let mut parser = SseParser::new();
+        assert!(parser.feed("data: {\"b\":2}\r").is_empty());
+        assert!(parser.feed("\n\r").is_empty());
+        let events = parser.feed("\ndata: next\r");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "{\"b\":2}");
+        assert_eq!(parser.finish().unwrap().data, "next");
+
+        // 多字节字符在字节中间被切分
+        let mut parser = SseParser::new();
+        let bytes = "data: 你好\n\n".as_bytes();
+        assert!(parser.feed_bytes(&bytes[..8]).is_empty());
+        let events = parser.feed_bytes(&bytes[8..]);
+        assert_eq!(events[0].data, "你好");
+
+        let text = format_typed_event(&serde_json::json!({ "type": "ping" }));
+        assert_eq!(text, "event: ping\ndata: {\"type\":\"ping\"}\n\n");
+    }
+}
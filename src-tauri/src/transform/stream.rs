@@ -0,0 +1,200 @@
+//! 流式转换会话
+//!
+//! 一个会话对应一次流式响应：先将上游 SSE（Anthropic 或 OpenAI 协议中转）解码为 Anthropic 事件，
+//! 再编码为入口格式的 SSE。数据块可以在任意位置截断，内容块索引、工具参数 JSON 与 usage
+//! 等状态由各转换器在会话内保存。
+
+use crate::transform::gemini::AnthropicToGeminiStream;
+use crate::transform::openai_chat::{AnthropicToOpenAiStream, OpenAiToAnthropicStream};
+use crate::transform::openai_responses::AnthropicToResponsesStream;
+use crate::transform::sse::{format_event, format_typed_event, SseEvent, SseParser};
+use crate::transform::SourceFormat;
+use serde_json::Value;
+
+/// Anthropic 事件 → 入口格式的编码器
+#[derive(Debug)]
+enum Encoder {
+    Anthropic,
+    OpenaiChat(AnthropicToOpenAiStream),
+    OpenaiResponses(AnthropicToResponsesStream),
+    Gemini(AnthropicToGeminiStream),
+}
+
+/// 流式转换状态
+#[derive(Debug)]
+pub struct StreamTransform {
+    parser: SseParser,
+    /// 上游为 OpenAI 协议时的解码器
+    decoder: Option<OpenAiToAnthropicStream>,
+    encoder: Encoder,
+    /// 已输出 Chat Completions 的 `[DONE]`
+    done_sent: bool,
+}
+
+impl StreamTransform {
+    pub fn new(openai_upstream: bool, format: SourceFormat, include_usage: bool) -> Self {
+        let encoder = match format {
+            SourceFormat::Anthropic => Encoder::Anthropic,
+            SourceFormat::OpenaiChat => {
+                Encoder::OpenaiChat(AnthropicToOpenAiStream::new(include_usage))
+            }
+            SourceFormat::OpenaiResponses => {
+                Encoder::OpenaiResponses(AnthropicToResponsesStream::new())
+            }
+            SourceFormat::Gemini => Encoder::Gemini(AnthropicToGeminiStream::new()),
+        };
+        Self {
+            parser: SseParser::new(),
+            decoder: openai_upstream.then(OpenAiToAnthropicStream::new),
+            encoder,
+            done_sent: false,
+        }
+    }
+
+    /// 输入一段上游 SSE 原始字节（可截断在多字节字符中间），返回已可输出的转换结果
+    pub fn feed(&mut self, chunk: &[u8]) -> String {
+        let events = self.parser.feed_bytes(chunk);
+        self.convert(events)
+    }
+
+    /// 结束流，输出缓冲区剩余内容与收尾事件
+    pub fn close(&mut self) -> String {
+        let events: Vec<SseEvent> = self.parser.finish().into_iter().collect();
+        let mut output = self.convert(events);
+        output.push_str(&self.flush_decoder());
+
+        match &mut self.encoder {
+            Encoder::OpenaiResponses(stream) => {
+                for event in stream.finish() {
+                    output.push_str(&format_typed_event(&event));
+                }
+            }
+            Encoder::OpenaiChat(_) if !self.done_sent => {
+                self.done_sent = true;
+                output.push_str("data: [DONE]\n\n");
+            }
+            _ => {}
+        }
+        output
+    }
+
+    fn convert(&mut self, events: Vec<SseEvent>) -> String {
+        let mut output = String::new();
+        for event in events {
+            if event.data.trim() == "[DONE]" {
+                output.push_str(&self.flush_decoder());
+                continue;
+            }
+            let Ok(data) = serde_json::from_str::<Value>(&event.data) else {
+                continue;
+            };
+            let decoded = match &mut self.decoder {
+                Some(decoder) => decoder.push_chunk(&data),
+                None => vec![data],
+            };
+            for event in decoded {
+                output.push_str(&self.encode(&event));
+            }
+        }
+        output
+    }
+
+    fn flush_decoder(&mut self) -> String {
+        let decoded = match &mut self.decoder {
+            Some(decoder) => decoder.finish(),
+            None => return String::new(),
+        };
+        decoded.iter().map(|event| self.encode(event)).collect()
+    }
+
+    fn encode(&mut self, event: &Value) -> String {
+        match &mut self.encoder {
+            Encoder::Anthropic => format_typed_event(event),
+            Encoder::OpenaiChat(stream) => {
+                let mut output: String = stream
+                    .push_event(event)
+                    .iter()
+                    .map(|chunk| format_event(None, chunk))
+                    .collect();
+                if stream.is_finished() && !self.done_sent {
+                    self.done_sent = true;
+                    output.push_str("data: [DONE]\n\n");
+                }
+                output
+            }
+            Encoder::OpenaiResponses(stream) => stream
+                .push_event(event)
+                .iter()
+                .map(format_typed_event)
+                .collect(),
+            Encoder::Gemini(stream) => stream
+                .push_event(event)
+                .iter()
+                .map(|chunk| format_event(None, chunk))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_lines(text: &str) -> Vec<Value> {
+        text.split("\n\n")
+            .filter_map(|block| block.lines().find_map(|l| l.strip_prefix("data: ")))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_anthropic_stream_to_openai_chat_in_split_chunks() {
+        let mut stream = StreamTransform::new(false, SourceFormat::OpenaiChat, true);
+        let sse = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"model\":\"m\",\"usage\":{\"input_tokens\":3,\"output_tokens\":1}}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n",
+            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+            "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":2}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        );
+
+        // 按任意位置切分输入
+        let mut output = String::new();
+        for piece in sse.as_bytes().chunks(37) {
+            output.push_str(&stream.feed(piece));
+        }
+        output.push_str(&stream.close());
+
+        assert_eq!(output.matches("data: [DONE]").count(), 1);
+        let chunks = data_lines(&output);
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "Hello");
+        assert_eq!(chunks[2]["choices"][0]["finish_reason"], "stop");
+        assert_eq!(chunks[3]["usage"]["total_tokens"], 5);
+    }
+
+    #[test]
+    fn test_openai_upstream_to_responses_stream() {
+        let mut stream = StreamTransform::new(true, SourceFormat::OpenaiResponses, false);
+        let mut output = stream.feed(concat!(
+            "data: {\"id\":\"c1\",\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"function\":{\"name\":\"f\",\"arguments\":\"{\\\"a\\\"\"}}]}}]}\n\n",
+            "data: {\"id\":\"c1\",\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\":1}\"}}]},\"finish_reason\":\"tool_calls\"}]}\n\n",
+        ).as_bytes());
+        output.push_str(&stream.feed(b"data: [DONE]\n\n"));
+        output.push_str(&stream.close());
+
+        let events = data_lines(&output);
+        let completed = events.last().unwrap();
+        assert_eq!(completed["type"], "response.completed");
+        assert_eq!(completed["response"]["output"][0]["type"], "function_call");
+        assert_eq!(completed["response"]["output"][0]["arguments"], "{\"a\":1}");
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| e["type"] == "response.completed")
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! 用量与费用统计
+//!
+//! `release_credential` 传入的 usage 按凭证与模型累计 Token 数，并根据价格表估算费用。
+//! 内置价格为各模型的公开标价（Bedrock、Vertex 全球端点与 Anthropic 一致，Foundry 与中转服务按
+//! Anthropic 计价），可在配置 `pricing` 中按模型或按后端覆盖。
+
+use crate::config::{ModelPrice, PricingSettings};
+use crate::credentials::{AuthType, UsageTotals};
+use serde_json::Value;
+
+/// 未提供模型时的统计键
+pub const UNKNOWN_MODEL: &str = "unknown";
+
+/// 内置价格表（模型 ID 前缀 → 美元 / 百万 Token）
+const BUILTIN_PRICES: &[(&str, ModelPrice)] = &[
+    ("claude-opus-4-5", ModelPrice::new(5.0, 25.0)),
+    ("claude-opus-4", ModelPrice::new(15.0, 75.0)),
+    ("claude-sonnet-4", ModelPrice::new(3.0, 15.0)),
+    ("claude-haiku-4-5", ModelPrice::new(1.0, 5.0)),
+    ("claude-3-7-sonnet", ModelPrice::new(3.0, 15.0)),
+    ("claude-3-5-sonnet", ModelPrice::new(3.0, 15.0)),
+    ("claude-3-5-haiku", ModelPrice::new(0.8, 4.0)),
+    ("claude-3-opus", ModelPrice::new(15.0, 75.0)),
+    ("claude-3-haiku", ModelPrice::new(0.25, 1.25)),
+];
+
+/// 计价后端
+pub fn pricing_backend(auth_type: AuthType) -> &'static str {
+    match auth_type {
+        AuthType::Bedrock | AuthType::BedrockApiKey => "bedrock",
+        AuthType::Vertex => "vertex",
+        _ => "anthropic",
+    }
+}
+
+/// 规范化模型 ID：去掉 Bedrock 的区域 / 厂商前缀与版本后缀、Vertex 的 `@` 版本
+pub fn normalize_model(model: &str) -> String {
+    let model = model.trim().to_lowercase();
+    let model = match model.find("claude") {
+        Some(start) => &model[start..],
+        None => model.as_str(),
+    };
+    let model = model.split(['@', ':']).next().unwrap_or(model);
+    model.strip_suffix("-v1").unwrap_or(model).to_string()
+}
+
+/// 按最长前缀匹配价格
+fn match_price<'a>(
+    prices: impl Iterator<Item = (&'a str, &'a ModelPrice)>,
+    model: &str,
+) -> Option<ModelPrice> {
+    prices
+        .filter(|(prefix, _)| model.starts_with(&normalize_model(prefix)))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, price)| *price)
+}
+
+/// 查找模型价格：后端覆盖 > 模型覆盖 > 内置价格
+pub fn resolve_price(backend: &str, model: &str, settings: &PricingSettings) -> Option<ModelPrice> {
+    let model = normalize_model(model);
+    let by_backend = settings
+        .backends
+        .get(backend)
+        .and_then(|prices| match_price(prices.iter().map(|(k, v)| (k.as_str(), v)), &model));
+    by_backend
+        .or_else(|| match_price(settings.models.iter().map(|(k, v)| (k.as_str(), v)), &model))
+        .or_else(|| match_price(BUILTIN_PRICES.iter().map(|(k, v)| (*k, v)), &model))
+}
+
+/// 解析 Anthropic `usage` 对象为一次请求的统计，按价格估算费用
+///
+/// 缓存写入按 `cache_creation.ephemeral_1h_input_tokens` 区分 1 小时缓存，其余按 5 分钟缓存计价。
+pub fn usage_totals(usage: &Value, price: Option<&ModelPrice>) -> UsageTotals {
+    let field = |name: &str| usage[name].as_u64().unwrap_or(0);
+    let cache_creation = field("cache_creation_input_tokens");
+    let cache_creation_1h = usage["cache_creation"]["ephemeral_1h_input_tokens"]
+        .as_u64()
+        .unwrap_or(0)
+        .min(cache_creation);
+
+    let mut totals = UsageTotals {
+        requests: 1,
+        input_tokens: field("input_tokens"),
+        output_tokens: field("output_tokens"),
+        cache_creation_input_tokens: cache_creation,
+        cache_read_input_tokens: field("cache_read_input_tokens"),
+        ..Default::default()
+    };
+    match price {
+        Some(price) => {
+            let cost = totals.input_tokens as f64 * price.input
+                + totals.output_tokens as f64 * price.output
+                + (cache_creation - cache_creation_1h) as f64 * price.cache_write()
+                + cache_creation_1h as f64 * price.cache_write_1h()
+                + totals.cache_read_input_tokens as f64 * price.cache_read();
+            totals.cost_usd = cost / 1_000_000.0;
+        }
+        None => totals.unpriced_requests = 1,
+    }
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_resolve_price_with_overrides() {
+        let settings = PricingSettings::default();
+        assert_eq!(
+            normalize_model("us.anthropic.claude-sonnet-4-5-20250929-v1:0"),
+            "claude-sonnet-4-5-20250929"
+        );
+        assert_eq!(
+            normalize_model("claude-opus-4-5@20251101"),
+            "claude-opus-4-5"
+        );
+        let opus_45 = resolve_price("vertex", "claude-opus-4-5@20251101", &settings).unwrap();
+        assert_eq!(opus_45.input, 5.0);
+        let opus_41 = resolve_price("anthropic", "claude-opus-4-1-20250805", &settings).unwrap();
+        assert_eq!(opus_41.input, 15.0);
+        assert!(resolve_price("anthropic", "gpt-4o", &settings).is_none());
+
+        // 后端覆盖优先于模型覆盖
+        let settings = PricingSettings {
+            models: HashMap::from([("claude-sonnet-4".to_string(), ModelPrice::new(2.0, 10.0))]),
+            backends: HashMap::from([(
+                "bedrock".to_string(),
+                HashMap::from([("claude-sonnet-4-5".to_string(), ModelPrice::new(3.3, 16.5))]),
+            )]),
+        };
+        let model = "global.anthropic.claude-sonnet-4-5-20250929-v1:0";
+        assert_eq!(
+            resolve_price("bedrock", model, &settings).unwrap().input,
+            3.3
+        );
+        assert_eq!(
+            resolve_price("anthropic", model, &settings).unwrap().input,
+            2.0
+        );
+    }
+
+    #[test]
+    fn test_usage_cost() {
+        let price = ModelPrice::new(3.0, 15.0);
+        let totals = usage_totals(
+            &serde_json::json!({
+                "input_tokens": 1000,
+                "output_tokens": 2000,
+                "cache_creation_input_tokens": 3000,
+                "cache_read_input_tokens": 10000,
+                "cache_creation": { "ephemeral_5m_input_tokens": 2000, "ephemeral_1h_input_tokens": 1000 }
+            }),
+            Some(&price),
+        );
+        assert_eq!(totals.cache_creation_input_tokens, 3000);
+        // 0.003 + 0.03 + 2000 * 3.75 + 1000 * 6 + 10000 * 0.3（每百万）
+        let expected = 0.003 + 0.03 + 0.0075 + 0.006 + 0.003;
+        assert!((totals.cost_usd - expected).abs() < 1e-9);
+
+        let unpriced = usage_totals(&serde_json::json!({ "input_tokens": 5 }), None);
+        assert_eq!(unpriced.unpriced_requests, 1);
+        assert_eq!(unpriced.cost_usd, 0.0);
+    }
+}